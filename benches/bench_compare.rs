@@ -0,0 +1,185 @@
+//! Compares the aligners on the same random cases, for several lengths and error rates.
+//! rust-bio's pairwise aligner is included with the compare-bio feature:
+//! cargo bench --bench bench_compare --features compare-bio
+
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lib::{
+    alignment_lib::{kmer_distance, AlignmentFunction, Penalties},
+    reference::affine_gap_align,
+    wavefront_alignment::wavefront_align,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// An aligner of the comparison: score returns the score of the optimal global alignment of a
+/// query and a text, with our penalties. Adding an aligner only needs a new entry in
+/// contenders.
+struct Contender {
+    name: &'static str,
+    score: fn(&str, &str, &Penalties) -> u32,
+}
+
+fn contenders() -> Vec<Contender> {
+    #[allow(unused_mut)]
+    let mut contenders = vec![
+        Contender {
+            name: "wfa",
+            score: |query, text, pens| wavefront_align(query, text, pens).unwrap().score,
+        },
+        Contender {
+            name: "swg",
+            score: |query, text, pens| affine_gap_align(query, text, pens).unwrap().score,
+        },
+    ];
+    #[cfg(feature = "compare-bio")]
+    contenders.push(Contender {
+        name: "rust-bio",
+        score: bio_score,
+    });
+    contenders
+}
+
+/// Aligns using rust-bio, whose scores are maximized: the penalties are negated. In both
+/// libraries, a gap of length l costs open + l * extension, and matches are free.
+#[cfg(feature = "compare-bio")]
+fn bio_score(query: &str, text: &str, pens: &Penalties) -> u32 {
+    let mismatch_score = -(pens.mismatch_pen as i32);
+    let mut aligner = bio::alignment::pairwise::Aligner::with_capacity(
+        query.len(),
+        text.len(),
+        -(pens.open_pen as i32),
+        -(pens.extd_pen as i32),
+        move |a: u8, b: u8| if a == b { 0 } else { mismatch_score },
+    );
+    -aligner.global(query.as_bytes(), text.as_bytes()).score as u32
+}
+
+/// Returns a random text of the given length, and a query made from it by editing
+/// error_rate * length of its bases: substitutions, insertions and deletions in equal
+/// proportions. The query is never longer than the text, as WFA requires.
+fn random_case(rng: &mut StdRng, length: usize, error_rate: f64) -> (String, String) {
+    const BASES: [char; 4] = ['A', 'C', 'G', 'T'];
+    let mut text: Vec<char> = (0..length).map(|_| BASES[rng.gen_range(0..4)]).collect();
+    let mut query = text.clone();
+    for _ in 0..(length as f64 * error_rate) as usize {
+        let pos = rng.gen_range(0..query.len());
+        let base = BASES[rng.gen_range(0..4)];
+        match rng.gen_range(0..3) {
+            0 => query[pos] = base,
+            1 => query.insert(pos, base),
+            _ => {
+                query.remove(pos);
+            }
+        }
+    }
+    if query.len() > text.len() {
+        std::mem::swap(&mut query, &mut text);
+    }
+    (query.into_iter().collect(), text.into_iter().collect())
+}
+
+fn compare(c: &mut Criterion, length: usize, error_rate: f64) {
+    let pens = Penalties {
+        mismatch_pen: 4,
+        open_pen: 6,
+        extd_pen: 2,
+    };
+    let mut rng = StdRng::seed_from_u64(648);
+    let (query, text) = random_case(&mut rng, length, error_rate);
+    let contenders = contenders();
+    // The timings are only comparable if every aligner finds the same optimum.
+    let scores: Vec<u32> = contenders
+        .iter()
+        .map(|contender| (contender.score)(&query, &text, &pens))
+        .collect();
+    assert!(
+        scores.iter().all(|score| *score == scores[0]),
+        "The aligners disagree on the score: {:?}",
+        scores
+    );
+
+    let mut group = c.benchmark_group(format!(
+        "compare length {} {}% error",
+        length,
+        error_rate * 100.0
+    ));
+    for contender in &contenders {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(contender.name),
+            &(&query, &text),
+            |b, (query, text)| {
+                b.iter(|| (contender.score)(black_box(query), black_box(text), black_box(&pens)))
+            },
+        );
+    }
+    group.finish();
+}
+
+fn compare_l100(c: &mut Criterion) {
+    for error_rate in [0.01, 0.1, 0.3] {
+        compare(c, 100, error_rate);
+    }
+}
+
+fn compare_l1000(c: &mut Criterion) {
+    for error_rate in [0.01, 0.1, 0.3] {
+        compare(c, 1000, error_rate);
+    }
+}
+
+/// The cases behind the thresholds of auto_align: WFA and SWG on random DNA of several
+/// lengths, around the error rates where WFA gets slower than SWG. The 6-mer distance that
+/// auto_align compares to its threshold is part of the name of each group.
+fn crossover(c: &mut Criterion) {
+    let pens = Penalties {
+        mismatch_pen: 4,
+        open_pen: 6,
+        extd_pen: 2,
+    };
+    for length in [20, 50, 100, 300, 1000] {
+        for error_rate in [0.2, 0.3, 0.4, 0.5] {
+            let mut rng = StdRng::seed_from_u64(675);
+            let (query, text) = random_case(&mut rng, length, error_rate);
+            let mut group = c.benchmark_group(format!(
+                "crossover length {} {}% error, 6-mer distance {:.2}",
+                length,
+                error_rate * 100.0,
+                kmer_distance(&query, &text, 6)
+            ));
+            for (name, align) in [
+                ("wfa", wavefront_align as AlignmentFunction),
+                ("swg", affine_gap_align),
+            ] {
+                group.bench_with_input(
+                    BenchmarkId::from_parameter(name),
+                    &(&query, &text),
+                    |b, (query, text)| {
+                        b.iter(|| align(black_box(query), black_box(text), black_box(&pens)))
+                    },
+                );
+            }
+            group.finish();
+        }
+    }
+}
+
+criterion_group! {
+    name = compare_100;
+    config = Criterion::default().significance_level(0.05).sample_size(10).measurement_time(Duration::from_secs(1));
+    targets = compare_l100
+}
+
+criterion_group! {
+    name = compare_1000;
+    config = Criterion::default().significance_level(0.05).sample_size(10).measurement_time(Duration::from_secs(10));
+    targets = compare_l1000
+}
+
+criterion_group! {
+    name = auto_align_crossover;
+    config = Criterion::default().significance_level(0.05).sample_size(10).measurement_time(Duration::from_secs(1));
+    targets = crossover
+}
+
+criterion_main!(compare_100, compare_1000, auto_align_crossover);
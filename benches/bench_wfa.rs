@@ -1,7 +1,11 @@
 use std::time::Duration;
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use lib::{alignment_lib::Penalties, wavefront_alignment::wavefront_align};
+use lib::{
+    alignment_lib::{Packed2Bit, Penalties},
+    reference::{affine_gap_align, affine_gap_align_linear_space},
+    wavefront_alignment::{wavefront_align, wavefront_align_astar, wavefront_align_packed},
+};
 
 fn wavefront_bench_l100_e1(c: &mut Criterion) {
     let query = "ACTCTATTTTACTCAGTGCAGGGTGAGCCGCCTATGCGGAGTGCAGTTACATAGGGAAAGCGGGGCTCAATTGCTACTCGTATGGGGTGTCACAGACGC";
@@ -71,6 +75,17 @@ fn wavefront_bench_l1000_e10(c: &mut Criterion) {
     c.bench_function("wfa length 1000 10% error", |b| {
         b.iter(|| wavefront_align(black_box(query), black_box(text), black_box(&pens)))
     });
+
+    // The reference SWG implementations, using quadratic and linear space.
+    c.bench_function("swg length 1000 10% error", |b| {
+        b.iter(|| affine_gap_align(black_box(query), black_box(text), black_box(&pens)))
+    });
+
+    c.bench_function("swg linear space length 1000 10% error", |b| {
+        b.iter(|| {
+            affine_gap_align_linear_space(black_box(query), black_box(text), black_box(&pens))
+        })
+    });
 }
 
 fn wavefront_bench_l1000_e30(c: &mut Criterion) {
@@ -99,6 +114,22 @@ fn wavefront_bench_l10000_e1(c: &mut Criterion) {
     c.bench_function("wfa length 10000 1% error", |b| {
         b.iter(|| wavefront_align(black_box(query), black_box(text), black_box(&pens)))
     });
+
+    c.bench_function("wfa a* length 10000 1% error", |b| {
+        b.iter(|| wavefront_align_astar(black_box(query), black_box(text), black_box(&pens)))
+    });
+
+    let packed_query = Packed2Bit::new(query).unwrap();
+    let packed_text = Packed2Bit::new(text).unwrap();
+    c.bench_function("wfa packed length 10000 1% error", |b| {
+        b.iter(|| {
+            wavefront_align_packed(
+                black_box(&packed_query),
+                black_box(&packed_text),
+                black_box(&pens),
+            )
+        })
+    });
 }
 
 fn wavefront_bench_l10000_e10(c: &mut Criterion) {
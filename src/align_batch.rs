@@ -0,0 +1,273 @@
+use clap::Parser;
+use lib::alignment_lib::AlignmentFunction;
+use lib::formats::{read_fasta, to_paf, FastaRecord};
+use lib::prelude::*;
+
+use std::collections::BTreeMap; // Reordering the results of the workers.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() {
+    let args = BatchArgs::parse();
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Type used for CLI args parsing using clap.
+#[derive(Parser, Debug)]
+#[clap(
+    author,
+    version,
+    about = "Aligns every record of a FASTA file to a reference."
+)]
+struct BatchArgs {
+    /// FASTA file of the queries. They are read and aligned one at a time.
+    queries: PathBuf,
+
+    /// FASTA file holding the reference, as its only record.
+    reference: PathBuf,
+
+    #[clap(short, long, default_value_t = AlignmentAlgorithm::Wavefront)]
+    /// Alignment algorithm that will be used. Possible values: Wavefront, SWG.
+    algorithm: AlignmentAlgorithm,
+
+    #[clap(short, long)]
+    /// Penalty for mismatching 2 chars.
+    mismatch_pen: u32,
+
+    #[clap(short, long)]
+    /// Penalty for opening a gap.
+    open_pen: u32,
+
+    #[clap(short, long)]
+    /// Penalty for extending a gap by 1. Is also applied once when the gap is opened.
+    extd_pen: u32,
+
+    #[clap(short, long)]
+    /// Aligns the queries on every core. The output stays in the order of the queries.
+    parallel: bool,
+
+    #[clap(long, arg_enum, default_value = "paf")]
+    /// Output format: one PAF line per query, or a TSV table with a header line.
+    format: OutputFormat,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Paf,
+    Tsv,
+}
+
+/// Header line of the TSV output.
+const TSV_HEADER: &str = "query\tscore\tidentity\tcigar";
+
+fn run(args: &BatchArgs) -> io::Result<()> {
+    let reference = read_reference(BufReader::new(File::open(&args.reference)?))?;
+    let queries = BufReader::new(File::open(&args.queries)?);
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    align_batch(queries, &reference, args, &mut out)?;
+    out.flush()
+}
+
+/// Reads the only record of the reference FASTA file.
+fn read_reference<R: BufRead>(reader: R) -> io::Result<FastaRecord> {
+    let mut records = read_fasta(reader);
+    match (records.next(), records.next()) {
+        (Some(record), None) => record.map_err(invalid_data),
+        (None, _) => Err(invalid_data("The reference FASTA file has no record.")),
+        (Some(_), Some(_)) => Err(invalid_data(
+            "The reference FASTA file has more than one record.",
+        )),
+    }
+}
+
+fn invalid_data<E: std::fmt::Debug>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+/// Aligns every query read from queries to the reference, and writes the output lines to out
+/// in the order of the queries. The queries that can't be aligned are reported on stderr.
+/// Returns the number of queries read.
+fn align_batch<R: BufRead + Send, W: Write>(
+    queries: R,
+    reference: &FastaRecord,
+    args: &BatchArgs,
+    out: &mut W,
+) -> io::Result<usize> {
+    let pens = Penalties {
+        mismatch_pen: args.mismatch_pen,
+        open_pen: args.open_pen,
+        extd_pen: args.extd_pen,
+    };
+    let align: AlignmentFunction = match args.algorithm.function() {
+        Some(align) => align,
+        None => panic!("{} not yet implemented.", args.algorithm),
+    };
+    let align_record = |query: &FastaRecord| {
+        align(&query.seq, &reference.seq, &pens)
+            .map(|alignment| format_alignment(&alignment, query, reference, args.format))
+            .map_err(|e| format!("Couldn't align {}: {:?}", query.name, e))
+    };
+    let mut write_result = |result: Result<String, String>| match result {
+        Ok(line) => writeln!(out, "{}", line),
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(())
+        }
+    };
+    if args.format == OutputFormat::Tsv {
+        write_result(Ok(TSV_HEADER.to_string()))?;
+    }
+
+    if !args.parallel {
+        let mut count = 0;
+        for query in read_fasta(queries) {
+            write_result(align_record(&query.map_err(invalid_data)?))?;
+            count += 1;
+        }
+        return Ok(count);
+    }
+
+    let num_threads = num_cpus::get();
+    thread::scope(|scope| {
+        // The queue is bounded, so that the reader doesn't get ahead of the workers by more
+        // than a few queries. Once the workers are gone, the reader's sends fail.
+        let (query_tx, query_rx): (SyncSender<(usize, FastaRecord)>, _) =
+            mpsc::sync_channel(2 * num_threads);
+        let query_rx = Arc::new(Mutex::new(query_rx));
+        let (result_tx, result_rx): (Sender<(usize, _)>, Receiver<(usize, _)>) = mpsc::channel();
+        let reader = scope.spawn(move || {
+            let mut count = 0;
+            for query in read_fasta(queries) {
+                if query_tx
+                    .send((count, query.map_err(invalid_data)?))
+                    .is_err()
+                {
+                    break;
+                }
+                count += 1;
+            }
+            Ok(count)
+        });
+        for _ in 0..num_threads {
+            let (query_rx, result_tx) = (Arc::clone(&query_rx), result_tx.clone());
+            scope.spawn(move || loop {
+                // The lock is released once the query is received, before aligning it.
+                let next = query_rx.lock().unwrap().recv();
+                match next {
+                    Ok((index, query)) => {
+                        if result_tx.send((index, align_record(&query))).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+        // Only the workers hold the channels: once they are done, the loop below ends.
+        drop(query_rx);
+        drop(result_tx);
+
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        for (index, result) in result_rx {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_index) {
+                write_result(result)?;
+                next_index += 1;
+            }
+        }
+        reader.join().unwrap()
+    })
+}
+
+/// Formats the alignment of query to reference as an output line, without its newline.
+fn format_alignment(
+    alignment: &Alignment,
+    query: &FastaRecord,
+    reference: &FastaRecord,
+    format: OutputFormat,
+) -> String {
+    match format {
+        OutputFormat::Paf => to_paf(alignment, &query.name, &reference.name),
+        OutputFormat::Tsv => format!(
+            "{}\t{}\t{:.4}\t{}",
+            query.name,
+            alignment.score,
+            alignment.identity(),
+            alignment.to_cigar()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const QUERIES: &str = ">read1 first read\nGATTACA\nGATTACA\n>read2\nGATTCAGATTACA\n\
+        >read3\nCATTACAGATTAGA\n>read4\nGATTACAGA\nTTACA\n>read5\nTTACAGAT\n";
+
+    fn parse(args: &[&str]) -> BatchArgs {
+        let base = [
+            "align_batch",
+            "queries.fa",
+            "reference.fa",
+            "-m",
+            "4",
+            "-o",
+            "6",
+            "-e",
+            "2",
+        ];
+        BatchArgs::try_parse_from(base.iter().chain(args)).unwrap()
+    }
+
+    fn run_batch(args: &BatchArgs) -> (usize, Vec<String>) {
+        let reference = read_reference(">ref\nGATTACAGATTACA\n".as_bytes()).unwrap();
+        let mut out = Vec::new();
+        let count = align_batch(QUERIES.as_bytes(), &reference, args, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap();
+        (count, lines.lines().map(str::to_string).collect())
+    }
+
+    #[test]
+    fn paf_output() {
+        let (count, lines) = run_batch(&parse(&[]));
+        assert_eq!(count, 5);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "read1\t14\t0\t14\t+\tref\t14\t0\t14\t14\t14\t255\tcg:Z:14M\tsc:i:0"
+        );
+        let names: Vec<&str> = lines
+            .iter()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(names, ["read1", "read2", "read3", "read4", "read5"]);
+
+        // The parallel output is the same, in the same order.
+        assert_eq!(run_batch(&parse(&["--parallel"])), (count, lines));
+    }
+
+    #[test]
+    fn tsv_output() {
+        let (count, lines) = run_batch(&parse(&["--format", "tsv", "--parallel"]));
+        assert_eq!(count, 5);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], TSV_HEADER);
+        assert_eq!(lines[1], "read1\t0\t1.0000\t14M");
+    }
+
+    #[test]
+    fn reference_must_have_one_record() {
+        assert!(read_reference("".as_bytes()).is_err());
+        assert!(read_reference(">a\nGAT\n>b\nCAT\n".as_bytes()).is_err());
+    }
+}
@@ -5,10 +5,21 @@
 /// The penalty for any gap is length * extd_pen + open_pen. The extension pen is also applied
 /// when a gap is opened.
 /// Penalties should be a positive int.
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::iter;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::io::{BufRead, Lines};
 use strum_macros::{Display, EnumString};
 
 /// The different alignment algorithms implemented in this crate.
-#[derive(Clone, Copy, Debug, EnumString, Display)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
 pub enum AlignmentAlgorithm {
     /// Basic WFA.
     Wavefront,
@@ -20,7 +31,8 @@ pub enum AlignmentAlgorithm {
 }
 
 /// Penalties used for WFA.
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Penalties {
     /// There is a single mismatch penalty for every char combination.
     /// WFA requires that the match penalty is set to 0.
@@ -33,50 +45,1952 @@ pub struct Penalties {
     pub extd_pen: u32,
 }
 
+impl Penalties {
+    /// Penalty of a gap of length 1: open_pen + extd_pen, saturating at u32::MAX.
+    pub(crate) fn open_extd_pen(&self) -> u32 {
+        self.open_pen.saturating_add(self.extd_pen)
+    }
+
+    /// Returns an upper bound of the optimal alignment score of query and text: the
+    /// minimum of the scores of aligning them on the main diagonal, and of aligning
+    /// them with 2 gaps. Computed using u64s, so that it doesn't overflow.
+    pub(crate) fn score_upper_bound(&self, query: &[char], text: &[char]) -> u64 {
+        let mismatches = query.iter().zip(text.iter()).filter(|(q, t)| q != t).count();
+        self.score_bound(mismatches, query.len(), text.len())
+    }
+
+    /// Same as score_upper_bound, for strings of query_len and text_len chars with mismatches
+    /// different chars on the main diagonal.
+    fn score_bound(&self, mismatches: usize, query_len: usize, text_len: usize) -> u64 {
+        let gap_pen = |len: usize| match len {
+            0 => 0,
+            len => self.open_pen as u64 + self.extd_pen as u64 * len as u64,
+        };
+        let diagonal =
+            mismatches as u64 * self.mismatch_pen as u64 + gap_pen(query_len.abs_diff(text_len));
+        diagonal.min(gap_pen(query_len) + gap_pen(text_len))
+    }
+
+    /// Checks that the penalties can be used to align a query of query_len chars to a text of
+    /// text_len chars, whatever their chars:
+    /// - they must not be degenerate (see check_degenerate): extd_pen must be > 0
+    ///   (AlignmentError::DegeneratePenalties).
+    /// - the worst-case score of the alignment, reached when every char mismatches, must fit
+    ///   in a u32 (AlignmentError::ScoreOverflow).
+    pub fn is_valid_for(&self, query_len: usize, text_len: usize) -> Result<(), AlignmentError> {
+        self.check_degenerate()?;
+        let bound = self.score_bound(query_len.min(text_len), query_len, text_len);
+        if bound > u32::MAX as u64 {
+            Err(AlignmentError::ScoreOverflow(format!(
+                "Aligning {} and {} chars could score up to {}, which doesn't fit in a u32.",
+                query_len, text_len, bound
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the penalties before aligning query and text with is_valid_for. Its worst-case
+    /// score is refined with the chars of the strings when it doesn't fit in a u32: strings
+    /// whose score is known to fit, e.g. equal strings, are still aligned.
+    pub(crate) fn check_valid_for(
+        &self,
+        query: &[char],
+        text: &[char],
+    ) -> Result<(), AlignmentError> {
+        match self.is_valid_for(query.len(), text.len()) {
+            Err(AlignmentError::ScoreOverflow(_)) => self.check_score_overflow(query, text),
+            result => result,
+        }
+    }
+
+    /// Checks that the scores computed when aligning query and text fit in a u32.
+    /// The check uses an upper bound of the score, so it can reject some alignments whose
+    /// optimal score would fit.
+    pub(crate) fn check_score_overflow(
+        &self,
+        query: &[char],
+        text: &[char],
+    ) -> Result<(), AlignmentError> {
+        let bound = self.score_upper_bound(query, text);
+        if bound > u32::MAX as u64 {
+            Err(AlignmentError::ScoreOverflow(format!(
+                "The alignment score could be up to {}, which doesn't fit in a u32.",
+                bound
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns AlignmentError::DegeneratePenalties if every penalty is 0: every alignment then
+    /// has a score of 0, so the alignment returned would be arbitrary. The same goes for an
+    /// extd_pen of 0, with which gaps of any length have the same cost: the gaps of the
+    /// alignment returned would be arbitrarily long.
+    pub(crate) fn check_degenerate(&self) -> Result<(), AlignmentError> {
+        if self.mismatch_pen == 0 && self.open_pen == 0 && self.extd_pen == 0 {
+            Err(AlignmentError::DegeneratePenalties(
+                "Every penalty is 0: all the alignments have a score of 0.".to_string(),
+            ))
+        } else if self.extd_pen == 0 {
+            Err(AlignmentError::DegeneratePenalties(
+                "extd_pen is 0: gaps of any length have the same cost.".to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Derives penalties from an error model: each aligned char is substituted with the
+    /// probability sub_rate, an indel starts after each char with the probability indel_rate,
+    /// and indel lengths follow a geometric distribution of mean mean_indel_len, i.e. an indel
+    /// is extended with the probability e = 1 - 1 / mean_indel_len.
+    /// The penalties are the negative log-odds of the events against an alignment without
+    /// them, in Phred units (-10 * log10(p)), like quality scores:
+    /// - mismatch_pen = 10 * log10((1 - sub_rate) / sub_rate),
+    /// - open_pen = 10 * log10((1 - indel_rate) / indel_rate * (mean_indel_len - 1)),
+    /// - extd_pen = -10 * log10(e),
+    ///
+    /// so that a gap of length l costs -10 * log10(indel_rate / (1 - indel_rate) * (1 - e) *
+    /// e^(l - 1)). Each penalty is rounded to the nearest integer, and is at least 1.
+    /// Panics if a rate isn't strictly between 0 and 1, or if mean_indel_len <= 1.
+    #[cfg(feature = "std")]
+    pub fn from_error_model(sub_rate: f64, indel_rate: f64, mean_indel_len: f64) -> Penalties {
+        assert!(
+            sub_rate > 0.0 && sub_rate < 1.0 && indel_rate > 0.0 && indel_rate < 1.0,
+            "The rates must be between 0 and 1, got {} and {}.",
+            sub_rate,
+            indel_rate
+        );
+        assert!(
+            mean_indel_len > 1.0,
+            "The mean indel length must be > 1, got {}.",
+            mean_indel_len
+        );
+        let phred = |odds: f64| (10.0 * odds.log10()).round().max(1.0) as u32;
+        let extension = 1.0 - 1.0 / mean_indel_len;
+        Penalties {
+            mismatch_pen: phred((1.0 - sub_rate) / sub_rate),
+            open_pen: phred((1.0 - indel_rate) / indel_rate * (mean_indel_len - 1.0)),
+            extd_pen: phred(1.0 / extension),
+        }
+    }
+}
+
+/// The cost of a gap, as a function of its length.
+/// The default gap_cost is the affine cost used everywhere else: open_cost + run_length *
+/// extend_cost. Other models (e.g. convex) override it.
+pub trait GapModel {
+    /// Cost paid once per gap, when it is opened.
+    fn open_cost(&self) -> u32;
+
+    /// Cost of each char of a gap, including the first one.
+    fn extend_cost(&self) -> u32;
+
+    /// Cost of a gap of run_length chars, saturating at u32::MAX.
+    fn gap_cost(&self, run_length: u32) -> u32 {
+        match run_length {
+            0 => 0,
+            len => self
+                .open_cost()
+                .saturating_add(self.extend_cost().saturating_mul(len)),
+        }
+    }
+}
+
+/// The affine gap model of Penalties: a gap of length l costs open_pen + l * extd_pen.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct AffineGap {
+    pub open_pen: u32,
+    pub extd_pen: u32,
+}
+
+impl GapModel for AffineGap {
+    fn open_cost(&self) -> u32 {
+        self.open_pen
+    }
+
+    fn extend_cost(&self) -> u32 {
+        self.extd_pen
+    }
+}
+
+/// Penalties price gaps like the AffineGap of their open_pen and extd_pen.
+impl GapModel for Penalties {
+    fn open_cost(&self) -> u32 {
+        self.open_pen
+    }
+
+    fn extend_cost(&self) -> u32 {
+        self.extd_pen
+    }
+}
+
+impl From<&Penalties> for AffineGap {
+    fn from(pens: &Penalties) -> AffineGap {
+        AffineGap {
+            open_pen: pens.open_pen,
+            extd_pen: pens.extd_pen,
+        }
+    }
+}
+
+/// Selects which end gaps are free, for alignments that don't need to span both strings.
+/// A free end gap doesn't change the score.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EndConfig {
+    /// The alignment can skip the start of the query.
+    pub query_start_free: bool,
+
+    /// The alignment can skip the end of the query.
+    pub query_end_free: bool,
+
+    /// The alignment can skip the start of the text.
+    pub text_start_free: bool,
+
+    /// The alignment can skip the end of the text.
+    pub text_end_free: bool,
+}
+
+impl EndConfig {
+    /// Global alignment: no end gap is free.
+    pub fn global() -> EndConfig {
+        EndConfig::default()
+    }
+
+    /// Semi-global alignment: the whole query is aligned to a part of the text.
+    pub fn semi_global() -> EndConfig {
+        EndConfig {
+            text_start_free: true,
+            text_end_free: true,
+            ..EndConfig::default()
+        }
+    }
+
+    /// Overlap alignment: the end of the query is aligned to the start of the text.
+    pub fn overlap() -> EndConfig {
+        EndConfig {
+            query_start_free: true,
+            text_end_free: true,
+            ..EndConfig::default()
+        }
+    }
+}
+
+/// Selects which of the 2 aligned strings is the reference, i.e. the text of the alignment:
+/// insertions and deletions are relative to it, whatever the lengths of the strings are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    /// The first string is the text, an insertion is a char of the second string only.
+    First,
+
+    /// The second string is the text, an insertion is a char of the first string only.
+    #[default]
+    Second,
+}
+
+/// The char inserted at gaps in the aligned strings.
+pub const GAP_CHAR: char = '-';
+
 /// This is the value returned by every alignment function after successfully aligning 2 strings.
-/// The aligned strings have '-' at gaps.
+/// The aligned strings have GAP_CHAR at gaps.
+/// The coordinates are char positions in the ungapped strings, as start..end, of the aligned
+/// region: for a global alignment, they span both strings. With free end gaps, the skipped
+/// chars are still in the aligned strings (against gaps), but outside of the coordinates.
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedAlignment"))]
 pub struct Alignment {
     pub score: u32,
     pub query_aligned: String,
     pub text_aligned: String,
+    pub query_start: usize,
+    pub query_end: usize,
+    pub text_start: usize,
+    pub text_end: usize,
+}
+
+/// The deserialized form of an Alignment. The alignments serialized before the coordinates
+/// were added are global: their missing coordinates span both strings.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SerializedAlignment {
+    score: u32,
+    query_aligned: String,
+    text_aligned: String,
+    #[serde(default)]
+    query_start: usize,
+    #[serde(default)]
+    query_end: Option<usize>,
+    #[serde(default)]
+    text_start: usize,
+    #[serde(default)]
+    text_end: Option<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedAlignment> for Alignment {
+    fn from(serialized: SerializedAlignment) -> Alignment {
+        let mut alignment = Alignment::new(
+            serialized.score,
+            serialized.query_aligned,
+            serialized.text_aligned,
+        );
+        alignment.query_start = serialized.query_start;
+        alignment.text_start = serialized.text_start;
+        if let Some(query_end) = serialized.query_end {
+            alignment.query_end = query_end;
+        }
+        if let Some(text_end) = serialized.text_end {
+            alignment.text_end = text_end;
+        }
+        alignment
+    }
+}
+
+/// A contiguous region of an alignment, returned by Alignment::divergent_blocks.
+/// The coordinates are char positions in the ungapped strings, as start..end.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct AlignmentBlock {
+    pub query_start: usize,
+    pub query_end: usize,
+    pub text_start: usize,
+    pub text_end: usize,
+    pub query_aligned: String,
+    pub text_aligned: String,
+}
+
+/// A run of consecutive matches of an alignment, returned by match_blocks.
+/// The coordinates are char positions in the ungapped strings.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct MatchBlock {
+    pub query_start: usize,
+    pub text_start: usize,
+    pub length: usize,
+}
+
+/// A difference between the query and the text of an alignment, returned by
+/// Alignment::to_variants. The positions are char positions in the text, the reference.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Variant {
+    /// The text char at pos is replaced by alt_base.
+    Snp {
+        pos: usize,
+        ref_base: char,
+        alt_base: char,
+    },
+
+    /// seq is inserted before the text char at pos.
+    Insertion { pos: usize, seq: String },
+
+    /// The len text chars starting at pos are deleted.
+    Deletion { pos: usize, len: usize },
+}
+
+/// An edit of a string, returned by Alignment::edit_script. The positions are char positions in
+/// the string the previous edits of the script have been applied to.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Edit {
+    /// c is inserted before the char at pos, or at the end if pos is the length.
+    Insert { pos: usize, c: char },
+
+    /// The char at pos is removed.
+    Delete { pos: usize },
+
+    /// The char at pos, from, is replaced by to.
+    Substitute { pos: usize, from: char, to: char },
+}
+
+/// The result of an adaptive WFA alignment, with statistics used to tune its cutoff.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct AdaptiveReport {
+    /// The alignment, whose score is the score reached by the wavefronts.
+    pub alignment: Alignment,
+
+    /// Cost of the alignment (see Alignment::recompute_score). It can be below
+    /// alignment.score: a pruned cell can leave a gap extension unavailable, the path then
+    /// opens a second gap right after the first one, and the strings merge both gaps.
+    pub path_score: u32,
+
+    /// Number of diagonals of the widest wavefront computed.
+    pub final_band_width: usize,
+
+    /// Number of diagonals removed from the wavefronts, over every score.
+    pub diagonals_pruned: usize,
+
+    /// Number of cells computed in each layer type (matches, inserts and deletes), including
+    /// the cells that were then pruned.
+    pub cells_computed: usize,
+}
+
+/// An exact alignment, with the band that would have sufficed to find it.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct BandReport {
+    pub alignment: Alignment,
+
+    /// Max distance between a diagonal of the alignment's path and the final diagonal.
+    pub min_sufficient_band: usize,
+}
+
+/// The result of wavefront_align_timeout_partial: the alignment of the strings, or of
+/// prefixes of them if the deadline was reached first.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PartialAlignment {
+    pub alignment: Alignment,
+
+    /// Whether the deadline was reached before the end of the strings. The alignment is then
+    /// an optimal alignment of their prefixes, and its score a lower bound of the full score.
+    pub partial: bool,
+
+    /// Number of query chars aligned.
+    pub query_end: usize,
+
+    /// Number of text chars aligned.
+    pub text_end: usize,
+}
+
+/// The operations of the columns of an alignment, as returned by Alignment::to_bitvecs.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct AlignmentBits {
+    /// Columns whose chars are equal.
+    pub matches: BitVec,
+
+    /// Columns with a gap in the query, i.e. deletions.
+    pub query_gaps: BitVec,
+
+    /// Columns with a gap in the text, i.e. insertions.
+    pub text_gaps: BitVec,
+}
+
+/// The alignment of 2 sequences of tokens, returned by wavefront_align_tokens. The aligned
+/// sequences have the same length: None is a gap.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct TokenAlignment<'a> {
+    pub score: u32,
+    pub query_aligned: Vec<Option<&'a str>>,
+    pub text_aligned: Vec<Option<&'a str>>,
+}
+
+/// The operation of a column of an alignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentOp {
+    /// Both chars are equal.
+    Match,
+
+    /// Both chars are different.
+    Mismatch,
+
+    /// A query char is aligned with a gap in the text.
+    Insertion,
+
+    /// A text char is aligned with a gap in the query.
+    Deletion,
+}
+
+impl AlignmentOp {
+    /// Returns the operation of a column, given its chars. None stands for a gap.
+    /// A column of 2 gaps has no operation: it is None.
+    pub(crate) fn from_column(query: Option<char>, text: Option<char>) -> Option<AlignmentOp> {
+        match (query, text) {
+            (Some(q), Some(t)) if q == t => Some(AlignmentOp::Match),
+            (Some(_), Some(_)) => Some(AlignmentOp::Mismatch),
+            (Some(_), None) => Some(AlignmentOp::Insertion),
+            (None, Some(_)) => Some(AlignmentOp::Deletion),
+            (None, None) => None,
+        }
+    }
+}
+
+impl Alignment {
+    /// Builds a global alignment: its coordinates span both ungapped strings.
+    pub fn new(score: u32, query_aligned: String, text_aligned: String) -> Alignment {
+        let mut alignment = Alignment {
+            score,
+            query_aligned,
+            text_aligned,
+            query_start: 0,
+            query_end: 0,
+            text_start: 0,
+            text_end: 0,
+        };
+        alignment.query_end = alignment.query_len();
+        alignment.text_end = alignment.text_len();
+        alignment
+    }
+
+    /// Restricts the coordinates of a global alignment to its aligned region: the end gaps
+    /// that are free in ends skip their chars. An end can have a run of insertions next to a
+    /// run of deletions: each of them is skipped if it is free.
+    pub(crate) fn with_free_ends(mut self, ends: &EndConfig) -> Alignment {
+        let ops: Vec<AlignmentOp> = self.ops().collect();
+        // Returns the number of (insertions, deletions) that are skipped at the end of ops.
+        let skipped =
+            |ops: &mut dyn Iterator<Item = &AlignmentOp>, query_free: bool, text_free: bool| {
+                let (mut insertions, mut deletions) = (0, 0);
+                for op in ops {
+                    match op {
+                        AlignmentOp::Insertion if query_free => insertions += 1,
+                        AlignmentOp::Deletion if text_free => deletions += 1,
+                        _ => break,
+                    }
+                }
+                (insertions, deletions)
+            };
+        let (insertions, deletions) =
+            skipped(&mut ops.iter(), ends.query_start_free, ends.text_start_free);
+        self.query_start = insertions;
+        self.text_start = deletions;
+        let (insertions, deletions) = skipped(
+            &mut ops.iter().rev(),
+            ends.query_end_free,
+            ends.text_end_free,
+        );
+        self.query_end -= insertions;
+        self.text_end -= deletions;
+        self
+    }
+
+    /// Returns the columns of the alignment as (query char, text char), with None for a gap.
+    pub fn columns(&self) -> impl Iterator<Item = (Option<char>, Option<char>)> + '_ {
+        let ungapped = |c: char| if c == GAP_CHAR { None } else { Some(c) };
+        self.query_aligned
+            .chars()
+            .zip(self.text_aligned.chars())
+            .map(move |(q, t)| (ungapped(q), ungapped(t)))
+    }
+
+    /// Returns the operations of the columns of the alignment. The columns of 2 gaps, which
+    /// align nothing, are skipped.
+    fn ops(&self) -> impl Iterator<Item = AlignmentOp> + '_ {
+        self.columns()
+            .filter_map(|(q, t)| AlignmentOp::from_column(q, t))
+    }
+
+    /// Returns the number of columns of the aligned region, leaving out the columns of the
+    /// chars outside of the coordinates.
+    pub fn aligned_len(&self) -> usize {
+        let skipped = self.query_start
+            + self.text_start
+            + self.query_len().saturating_sub(self.query_end)
+            + self.text_len().saturating_sub(self.text_end);
+        self.query_aligned.chars().count().saturating_sub(skipped)
+    }
+
+    /// Returns the length of the ungapped query.
+    pub(crate) fn query_len(&self) -> usize {
+        self.query_aligned
+            .chars()
+            .filter(|c| *c != GAP_CHAR)
+            .count()
+    }
+
+    /// Returns the length of the ungapped text.
+    pub(crate) fn text_len(&self) -> usize {
+        self.text_aligned.chars().filter(|c| *c != GAP_CHAR).count()
+    }
+
+    /// Computes the score of the alignment from its mismatches and gaps. The score saturates at
+    /// u32::MAX, which it can exceed with huge penalties.
+    pub fn recompute_score(&self, pens: &Penalties) -> u32 {
+        self.recompute_score_u64(pens).min(u32::MAX as u64) as u32
+    }
+
+    /// Computes the score of the alignment as recompute_score does, without saturating it.
+    fn recompute_score_u64(&self, pens: &Penalties) -> u64 {
+        let mut computed_score: u64 = 0;
+        let mut current_layer = AlignmentLayer::Matches;
+        for column in self.columns() {
+            match column {
+                // A column of 2 gaps aligns nothing, and doesn't close the current gap.
+                (None, None) => (),
+                (None, _) => {
+                    computed_score += pens.extd_pen as u64
+                        + match current_layer {
+                            AlignmentLayer::Deletes => 0,
+                            _ => pens.open_pen as u64,
+                        };
+                    current_layer = AlignmentLayer::Deletes;
+                }
+                (_, None) => {
+                    computed_score += pens.extd_pen as u64
+                        + match current_layer {
+                            AlignmentLayer::Inserts => 0,
+                            _ => pens.open_pen as u64,
+                        };
+                    current_layer = AlignmentLayer::Inserts;
+                }
+                (Some(q), Some(t)) => {
+                    current_layer = AlignmentLayer::Matches;
+                    if q != t {
+                        computed_score += pens.mismatch_pen as u64;
+                    }
+                }
+            }
+        }
+        computed_score
+    }
+
+    /// Returns the score divided by the length of the alignment, i.e. its number of columns, so
+    /// that the scores of alignments of different lengths can be compared.
+    /// Returns 0 for an empty alignment.
+    pub fn normalized_score(&self) -> f64 {
+        match self.query_aligned.chars().count() {
+            0 => 0.0,
+            columns => self.score as f64 / columns as f64,
+        }
+    }
+
+    /// Returns the fraction of the columns of the aligned region that are matches, gap columns
+    /// included. Returns 0 for an empty alignment.
+    pub fn identity(&self) -> f64 {
+        match self.aligned_len() {
+            0 => 0.0,
+            columns => {
+                let matches = self.ops().filter(|op| *op == AlignmentOp::Match).count();
+                matches as f64 / columns as f64
+            }
+        }
+    }
+
+    /// Checks that the score of the alignment matches its mismatches and gaps.
+    pub fn is_score_consistent(&self, pens: &Penalties) -> bool {
+        self.score as u64 == self.recompute_score_u64(pens)
+    }
+
+    /// Returns the alignment of text to query: insertions become deletions and vice versa.
+    /// The score is unchanged, since insertions and deletions have the same penalties.
+    pub fn swap(&self) -> Alignment {
+        Alignment {
+            score: self.score,
+            query_aligned: self.text_aligned.clone(),
+            text_aligned: self.query_aligned.clone(),
+            query_start: self.text_start,
+            query_end: self.text_end,
+            text_start: self.query_start,
+            text_end: self.query_end,
+        }
+    }
+
+    /// Returns the CIGAR string of the alignment, with the text as the reference: matches and
+    /// mismatches are both M, I is an insertion in the query and D a deletion.
+    pub fn to_cigar(&self) -> String {
+        self.cigar_with(|op| match op {
+            AlignmentOp::Match | AlignmentOp::Mismatch => 'M',
+            AlignmentOp::Insertion => 'I',
+            AlignmentOp::Deletion => 'D',
+        })
+    }
+
+    /// Same as to_cigar, with matches written as = and mismatches as X.
+    pub fn to_cigar_extended(&self) -> String {
+        self.cigar_with(|op| match op {
+            AlignmentOp::Match => '=',
+            AlignmentOp::Mismatch => 'X',
+            AlignmentOp::Insertion => 'I',
+            AlignmentOp::Deletion => 'D',
+        })
+    }
+
+    /// Run-length encodes the columns of the alignment, using op_char to name each operation.
+    fn cigar_with(&self, op_char: fn(AlignmentOp) -> char) -> String {
+        let mut cigar = String::new();
+        let mut run: Option<(char, usize)> = None;
+        for op in self.ops() {
+            let c = op_char(op);
+            run = match run {
+                Some((current, len)) if current == c => Some((c, len + 1)),
+                Some((current, len)) => {
+                    cigar.push_str(&format!("{}{}", len, current));
+                    Some((c, 1))
+                }
+                None => Some((c, 1)),
+            };
+        }
+        if let Some((current, len)) = run {
+            cigar.push_str(&format!("{}{}", len, current));
+        }
+        cigar
+    }
+
+    /// Returns the operations of the columns of the alignment as bit vectors: bit i of
+    /// matches, query_gaps and text_gaps is set if column i is a match, a deletion or an
+    /// insertion respectively. No bit is set for the mismatches.
+    pub fn to_bitvecs(&self) -> AlignmentBits {
+        let mut bits = AlignmentBits::default();
+        for op in self.ops() {
+            bits.matches.push(op == AlignmentOp::Match);
+            bits.query_gaps.push(op == AlignmentOp::Deletion);
+            bits.text_gaps.push(op == AlignmentOp::Insertion);
+        }
+        bits
+    }
+
+    /// Returns the aligned strings, for visualization. If mask_gaps_lowercase is set, the
+    /// chars of the columns right before and after a gap are lowercased, so that gap
+    /// boundaries stand out. Otherwise, the chars are returned as they were aligned.
+    pub fn rendered(&self, mask_gaps_lowercase: bool) -> (String, String) {
+        if !mask_gaps_lowercase {
+            return (self.query_aligned.clone(), self.text_aligned.clone());
+        }
+        let columns: Vec<(char, char)> = self
+            .query_aligned
+            .chars()
+            .zip(self.text_aligned.chars())
+            .collect();
+        let is_gap = |idx: usize| {
+            columns
+                .get(idx)
+                .is_some_and(|(q, t)| *q == GAP_CHAR || *t == GAP_CHAR)
+        };
+        let mut query = String::with_capacity(self.query_aligned.len());
+        let mut text = String::with_capacity(self.text_aligned.len());
+        for (idx, (q, t)) in columns.iter().enumerate() {
+            let next_to_gap = !is_gap(idx) && ((idx > 0 && is_gap(idx - 1)) || is_gap(idx + 1));
+            if next_to_gap {
+                query.push(q.to_ascii_lowercase());
+                text.push(t.to_ascii_lowercase());
+            } else {
+                query.push(*q);
+                text.push(*t);
+            }
+        }
+        (query, text)
+    }
+
+    /// Returns a self-contained HTML rendering of the alignment: the query is above the text,
+    /// in blocks of width columns (a single block if width is 0). The runs of matches,
+    /// mismatches and gaps are spans of class "match", "mismatch" and "gap", colored green, red
+    /// and gray by the style element of the output. The chars are HTML-escaped.
+    pub fn to_html(&self, width: usize) -> String {
+        let columns: Vec<(char, char)> = self
+            .query_aligned
+            .chars()
+            .zip(self.text_aligned.chars())
+            .collect();
+        let class = |(q, t): (char, char)| {
+            if q == GAP_CHAR || t == GAP_CHAR {
+                "gap"
+            } else if q == t {
+                "match"
+            } else {
+                "mismatch"
+            }
+        };
+        let mut html = String::from(
+            "<div class=\"alignment\"><style>\
+             .alignment .match{color:green}\
+             .alignment .mismatch{color:red}\
+             .alignment .gap{color:gray}\
+             </style><pre>",
+        );
+        let width = if width == 0 {
+            columns.len().max(1)
+        } else {
+            width
+        };
+        for (idx, block) in columns.chunks(width).enumerate() {
+            if idx > 0 {
+                html.push('\n');
+            }
+            for row in [0, 1] {
+                let mut run_class = None;
+                for column in block {
+                    if run_class != Some(class(*column)) {
+                        if run_class.is_some() {
+                            html.push_str("</span>");
+                        }
+                        run_class = Some(class(*column));
+                        html.push_str("<span class=\"");
+                        html.push_str(class(*column));
+                        html.push_str("\">");
+                    }
+                    push_html_escaped(&mut html, if row == 0 { column.0 } else { column.1 });
+                }
+                if run_class.is_some() {
+                    html.push_str("</span>");
+                }
+                html.push('\n');
+            }
+        }
+        html.push_str("</pre></div>");
+        html
+    }
+
+    /// Renders the alignment in blocks of 3 lines, as in EMBOSS needle: the query, a line
+    /// marking the matches with '|' and the mismatches with '.', and the text. The query and
+    /// text lines have the 1-based positions of their first and last chars in the margins, or
+    /// the position of the previous char for a line of gaps. Lines are at most line_width chars
+    /// long, margins included, with at least 1 column per block. If ruler is set, each block
+    /// starts with a line numbering the columns every 10, with a '.' every 5.
+    pub fn to_blocks(&self, line_width: usize, ruler: bool) -> String {
+        let columns: Vec<(Option<char>, Option<char>)> = self.columns().collect();
+        let digits = self
+            .query_len()
+            .max(self.text_len())
+            .max(1)
+            .to_string()
+            .len();
+        // "Query" and the start position on the left, the end position on the right.
+        let left_margin = 5 + 1 + digits + 1;
+        let width = line_width.saturating_sub(left_margin + 1 + digits).max(1);
+        let mut blocks = String::new();
+        let (mut query_pos, mut text_pos) = (0, 0);
+        let push_row =
+            |blocks: &mut String, label: &str, chars: &[Option<char>], pos: &mut usize| {
+                let start = *pos + chars.iter().any(Option::is_some) as usize;
+                *pos += chars.iter().flatten().count();
+                let row: String = chars.iter().map(|c| c.unwrap_or(GAP_CHAR)).collect();
+                blocks.push_str(&format!(
+                    "{:<5} {:>digits$} {} {}\n",
+                    label,
+                    start.min(*pos),
+                    row,
+                    pos
+                ));
+            };
+        for (idx, block) in columns.chunks(width).enumerate() {
+            if idx > 0 {
+                blocks.push('\n');
+            }
+            if ruler {
+                let mut marks = vec![' '; block.len()];
+                for c in 0..block.len() {
+                    let column = idx * width + c + 1;
+                    let number = column.to_string();
+                    if column % 10 == 0 && number.len() <= c + 1 {
+                        for (mark, digit) in marks[c + 1 - number.len()..=c]
+                            .iter_mut()
+                            .zip(number.chars())
+                        {
+                            *mark = digit;
+                        }
+                    } else if column % 5 == 0 {
+                        marks[c] = '.';
+                    }
+                }
+                let marks: String = marks.into_iter().collect();
+                blocks.push_str(format!("{:left_margin$}{}", "", marks).trim_end());
+                blocks.push('\n');
+            }
+            let query_chars: Vec<Option<char>> = block.iter().map(|(q, _)| *q).collect();
+            let text_chars: Vec<Option<char>> = block.iter().map(|(_, t)| *t).collect();
+            push_row(&mut blocks, "Query", &query_chars, &mut query_pos);
+            let markers: String = block
+                .iter()
+                .map(|column| match column {
+                    (Some(q), Some(t)) if q == t => '|',
+                    (Some(_), Some(_)) => '.',
+                    _ => ' ',
+                })
+                .collect();
+            blocks.push_str(format!("{:left_margin$}{}", "", markers).trim_end());
+            blocks.push('\n');
+            push_row(&mut blocks, "Text", &text_chars, &mut text_pos);
+        }
+        blocks
+    }
+
+    /// Computes the score of the alignment under each of the penalty sets, in a single pass.
+    /// The score is linear in the number of mismatches, gap openings and gap chars, so they
+    /// are counted once and then weighted by each penalty set. Like recompute_score, the
+    /// scores saturate at u32::MAX.
+    pub fn rescore_many(&self, pen_sets: &[Penalties]) -> Vec<u32> {
+        let mut mismatches: u64 = 0;
+        let mut gap_opens: u64 = 0;
+        let mut gap_chars: u64 = 0;
+        let mut current_layer = AlignmentLayer::Matches;
+        for (c1, c2) in self.query_aligned.chars().zip(self.text_aligned.chars()) {
+            let layer = if c1 == GAP_CHAR {
+                AlignmentLayer::Deletes
+            } else if c2 == GAP_CHAR {
+                AlignmentLayer::Inserts
+            } else {
+                AlignmentLayer::Matches
+            };
+            match layer {
+                AlignmentLayer::Matches => mismatches += (c1 != c2) as u64,
+                _ => {
+                    gap_chars += 1;
+                    gap_opens += (current_layer != layer) as u64;
+                }
+            }
+            current_layer = layer;
+        }
+        pen_sets
+            .iter()
+            .map(|pens| {
+                let score = mismatches * pens.mismatch_pen as u64
+                    + gap_opens * pens.open_pen as u64
+                    + gap_chars * pens.extd_pen as u64;
+                score.min(u32::MAX as u64) as u32
+            })
+            .collect()
+    }
+
+    /// Returns the part of the alignment that covers the query chars in start..end.
+    /// Gaps in the query are kept only if they are between 2 chars of that range.
+    /// The score of the sub-alignment depends on the penalties, which the alignment doesn't
+    /// know: it is left to 0, and recompute_score gives it, opening again a gap that starts
+    /// before the range at its start.
+    /// Returns AlignmentError::InvalidRange if start > end, or if end is greater than the
+    /// length of the query.
+    pub fn slice_query(&self, start: usize, end: usize) -> Result<Alignment, AlignmentError> {
+        let query_length = self.query_len();
+        if start > end || end > query_length {
+            return Err(AlignmentError::InvalidRange(format!(
+                "Invalid query range {}..{} for a query of length {}.",
+                start, end, query_length
+            )));
+        }
+        let mut query_pos = 0;
+        let mut text_pos = 0;
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        // The coordinates of the slice, in its own chars: the chars outside of the aligned
+        // region of the alignment stay outside of it.
+        let mut sliced = Alignment::new(0, String::new(), String::new());
+        for (q, t) in self.query_aligned.chars().zip(self.text_aligned.chars()) {
+            let keep = if q == GAP_CHAR {
+                start < query_pos && query_pos < end
+            } else {
+                query_pos += 1;
+                start < query_pos && query_pos <= end
+            };
+            if t != GAP_CHAR {
+                text_pos += 1;
+            }
+            if keep {
+                query_aligned.push(q);
+                text_aligned.push(t);
+                if q != GAP_CHAR {
+                    sliced.query_start += usize::from(query_pos <= self.query_start);
+                    sliced.query_end += usize::from(query_pos <= self.query_end);
+                }
+                if t != GAP_CHAR {
+                    sliced.text_start += usize::from(text_pos <= self.text_start);
+                    sliced.text_end += usize::from(text_pos <= self.text_end);
+                }
+            }
+        }
+        sliced.query_aligned = query_aligned;
+        sliced.text_aligned = text_aligned;
+        Ok(sliced)
+    }
+
+    /// Returns the regions of the alignment that contain mismatches or gaps, each surrounded
+    /// by up to context columns on both sides. Blocks that overlap or touch are merged, so the
+    /// columns that are left out of every block are always matches.
+    pub fn divergent_blocks(&self, context: usize) -> Vec<AlignmentBlock> {
+        let columns: Vec<(char, char)> = self
+            .query_aligned
+            .chars()
+            .zip(self.text_aligned.chars())
+            .collect();
+
+        // Ranges of columns, as start..end.
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (idx, (q, t)) in columns.iter().enumerate() {
+            if q == t {
+                continue;
+            }
+            let start = idx.saturating_sub(context);
+            let end = (idx + context + 1).min(columns.len());
+            match ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        let mut blocks = Vec::with_capacity(ranges.len());
+        let mut query_pos = 0;
+        let mut text_pos = 0;
+        let mut column = 0;
+        for (start, end) in ranges {
+            for (q, t) in &columns[column..start] {
+                query_pos += (*q != GAP_CHAR) as usize;
+                text_pos += (*t != GAP_CHAR) as usize;
+            }
+            let mut block = AlignmentBlock {
+                query_start: query_pos,
+                query_end: query_pos,
+                text_start: text_pos,
+                text_end: text_pos,
+                query_aligned: String::new(),
+                text_aligned: String::new(),
+            };
+            for (q, t) in &columns[start..end] {
+                query_pos += (*q != GAP_CHAR) as usize;
+                text_pos += (*t != GAP_CHAR) as usize;
+                block.query_aligned.push(*q);
+                block.text_aligned.push(*t);
+            }
+            block.query_end = query_pos;
+            block.text_end = text_pos;
+            blocks.push(block);
+            column = end;
+        }
+        blocks
+    }
+
+    /// Builds the aligned strings from the operations of an alignment of query and text.
+    /// Returns None if the operations don't consume exactly every char of query and text.
+    pub fn from_ops(score: u32, ops: &[AlignmentOp], query: &str, text: &str) -> Option<Alignment> {
+        let mut q_chars = query.chars();
+        let mut t_chars = text.chars();
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        for op in ops {
+            match op {
+                AlignmentOp::Match | AlignmentOp::Mismatch => {
+                    query_aligned.push(q_chars.next()?);
+                    text_aligned.push(t_chars.next()?);
+                }
+                AlignmentOp::Insertion => {
+                    query_aligned.push(q_chars.next()?);
+                    text_aligned.push(GAP_CHAR);
+                }
+                AlignmentOp::Deletion => {
+                    query_aligned.push(GAP_CHAR);
+                    text_aligned.push(t_chars.next()?);
+                }
+            }
+        }
+        if q_chars.next().is_some() || t_chars.next().is_some() {
+            return None;
+        }
+        Some(Alignment::new(score, query_aligned, text_aligned))
+    }
+
+    /// Returns the alignment with its gap runs in a canonical form: in each block of consecutive
+    /// gap columns, the insertions come first, then the deletions. Alignments that only differ
+    /// by the order of their adjacent insertions and deletions are then equal.
+    /// The score and coordinates are kept. Merging the runs of a block can remove gap openings,
+    /// but interleaved runs are never optimal with open_pen > 0.
+    pub fn normalize_gaps(&self) -> Alignment {
+        let mut query_aligned = String::with_capacity(self.query_aligned.len());
+        let mut text_aligned = String::with_capacity(self.text_aligned.len());
+        // Deletions of the current block, written after its insertions.
+        let mut deleted = String::new();
+        for (q, t) in self.query_aligned.chars().zip(self.text_aligned.chars()) {
+            if q == GAP_CHAR {
+                deleted.push(t);
+                continue;
+            }
+            if t != GAP_CHAR {
+                for d in deleted.drain(..) {
+                    query_aligned.push(GAP_CHAR);
+                    text_aligned.push(d);
+                }
+            }
+            query_aligned.push(q);
+            text_aligned.push(t);
+        }
+        for d in deleted.drain(..) {
+            query_aligned.push(GAP_CHAR);
+            text_aligned.push(d);
+        }
+        Alignment {
+            query_aligned,
+            text_aligned,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the differences between the query and the text as variants of the text, with
+    /// their positions shifted by text_offset, e.g. the position of the text in a chromosome.
+    /// Each block of consecutive gap columns gives an insertion followed by a deletion, as in
+    /// normalize_gaps, and each indel is shifted left through the matches before it as long as
+    /// it gives the same sequences: an indel in a homopolymer or a repeat is reported at its
+    /// leftmost position, as variant callers expect.
+    /// The free end gaps, outside of the coordinates, aren't variants.
+    pub fn to_variants(&self, text_offset: usize) -> Vec<Variant> {
+        let normalized = self.normalize_gaps();
+        let mut columns: Vec<(char, char)> = normalized
+            .query_aligned
+            .chars()
+            .zip(normalized.text_aligned.chars())
+            .collect();
+
+        // Left-aligns the gap runs, one column at a time.
+        let mut idx = 0;
+        while idx < columns.len() {
+            let (q, t) = columns[idx];
+            if q != GAP_CHAR && t != GAP_CHAR {
+                idx += 1;
+                continue;
+            }
+            let deletion = q == GAP_CHAR;
+            // The char of a gap column, or None for a column of another kind.
+            let gapped = |(q, t): (char, char)| match deletion {
+                true if q == GAP_CHAR => Some(t),
+                false if t == GAP_CHAR => Some(q),
+                _ => None,
+            };
+            let mut end = idx;
+            while end < columns.len() && gapped(columns[end]).is_some() {
+                end += 1;
+            }
+            let mut start = idx;
+            loop {
+                while start > 0 && gapped(columns[start - 1]).is_some() {
+                    start -= 1;
+                }
+                let (q, t) = match start {
+                    0 => break,
+                    _ => columns[start - 1],
+                };
+                if q != t || gapped(columns[end - 1]) != Some(q) {
+                    break;
+                }
+                // The match moves after the run, and the chars of the run shift by one.
+                columns[start - 1..end].rotate_left(1);
+                columns[start - 1..end - 1].rotate_right(1);
+                start -= 1;
+                end -= 1;
+            }
+            idx = end;
+        }
+
+        let mut variants = Vec::new();
+        let mut query_pos = 0;
+        let mut text_pos = 0;
+        for (q, t) in columns {
+            let pos = text_offset + text_pos;
+            if q == GAP_CHAR {
+                if (self.text_start..self.text_end).contains(&text_pos) {
+                    match variants.last_mut() {
+                        Some(Variant::Deletion { pos: start, len }) if *start + *len == pos => {
+                            *len += 1
+                        }
+                        _ => variants.push(Variant::Deletion { pos, len: 1 }),
+                    }
+                }
+                text_pos += 1;
+            } else if t == GAP_CHAR {
+                if (self.query_start..self.query_end).contains(&query_pos) {
+                    match variants.last_mut() {
+                        Some(Variant::Insertion { pos: at, seq }) if *at == pos => seq.push(q),
+                        _ => variants.push(Variant::Insertion {
+                            pos,
+                            seq: q.to_string(),
+                        }),
+                    }
+                }
+                query_pos += 1;
+            } else {
+                if q != t {
+                    variants.push(Variant::Snp {
+                        pos,
+                        ref_base: t,
+                        alt_base: q,
+                    });
+                }
+                query_pos += 1;
+                text_pos += 1;
+            }
+        }
+        variants
+    }
+
+    /// Returns the edits that turn the ungapped query into the ungapped text, in the order of
+    /// the columns: a column with a query gap inserts its text char, a column with a text gap
+    /// deletes its query char, and a mismatch substitutes the text char for the query char.
+    /// Each position is relative to the string the previous edits were applied to, so that the
+    /// script can be applied sequentially.
+    pub fn edit_script(&self) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        let mut pos = 0;
+        for column in self.columns() {
+            match column {
+                (None, Some(t)) => edits.push(Edit::Insert { pos, c: t }),
+                (Some(_), None) => {
+                    edits.push(Edit::Delete { pos });
+                    continue;
+                }
+                (Some(q), Some(t)) if q != t => edits.push(Edit::Substitute {
+                    pos,
+                    from: q,
+                    to: t,
+                }),
+                _ => (),
+            }
+            pos += 1;
+        }
+        edits
+    }
+
+    /// Appends other after self. The score of the result is the sum of both scores, which is
+    /// only exact if no gap runs across the junction of both alignments.
+    /// The aligned region starts at the start of self, and ends at the end of other.
+    pub fn concat(&self, other: &Alignment) -> Alignment {
+        Alignment {
+            score: self.score + other.score,
+            query_aligned: self.query_aligned.clone() + &other.query_aligned,
+            text_aligned: self.text_aligned.clone() + &other.text_aligned,
+            query_start: self.query_start,
+            query_end: self.query_len() + other.query_end,
+            text_start: self.text_start,
+            text_end: self.text_len() + other.text_end,
+        }
+    }
+}
+
+/// Error type, for alignment errors.
+#[derive(Debug, Eq, PartialEq)]
+pub enum AlignmentError {
+    /// Both strings should have at least 1 character.
+    ZeroLength(String),
+
+    /// query.len() needs to be <= to text.len()
+    QueryTooLong(String),
+
+    /// The input sequences couldn't be read, or a query line had no matching text line.
+    ReadFailure(String),
+
+    /// Anchors must be within both strings, and strictly increasing on both of them.
+    InvalidAnchors(String),
+
+    /// The band needed to find the optimal alignment is wider than the maximum allowed.
+    BandExceeded(String),
+
+    /// The alignment score could overflow with these penalties and strings.
+    ScoreOverflow(String),
+
+    /// A char can't be encoded, e.g. a non-ACGT char in a DNA sequence.
+    InvalidChar(String),
+
+    /// An option isn't supported by the selected algorithm, or with the other options.
+    Unsupported(String),
+
+    /// The penalties can't give a meaningful alignment, e.g. they are all 0.
+    DegeneratePenalties(String),
+
+    /// The backtrace couldn't find how a cell of the wavefronts or of the DP matrices was
+    /// reached.
+    BacktraceFailed(String),
+
+    /// A wavefront has more diagonals than can be stored.
+    WavefrontTooWide(String),
+
+    /// A checkpoint doesn't belong to the alignment it is resumed with.
+    InvalidCheckpoint(String),
+
+    /// Per-char data, e.g. base qualities, doesn't have the length of its sequence.
+    LengthMismatch(String),
+
+    /// A byte sequence that should be ASCII has a byte >= 0x80.
+    NonAscii(String),
+
+    /// A serialized tag doesn't encode any value, e.g. an AlignmentLayer tag > 2.
+    InvalidTag(String),
+
+    /// No alignment satisfies the constraints, e.g. a maximum gap length.
+    NoAlignment(String),
+
+    /// A range of positions isn't within its sequence, or its start is after its end.
+    InvalidRange(String),
+}
+
+/// Appends c to html, escaping it if it is an HTML-special char.
+fn push_html_escaped(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        c => html.push(c),
+    }
+}
+
+/// A DNA sequence, packed using 2 bits per base.
+/// Base i is stored in words[i / 32], at bits 2 * (i % 32) and 2 * (i % 32) + 1, so that the
+/// lowest bits hold the first bases: the first differing base of 2 words is found using
+/// trailing_zeros on their XOR.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Packed2Bit {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Packed2Bit {
+    const BASES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+    /// Packs a DNA sequence. Returns AlignmentError::InvalidChar if a char isn't one of ACGT.
+    pub fn new(seq: &str) -> Result<Packed2Bit, AlignmentError> {
+        let mut words = Vec::with_capacity(seq.len() / 32 + 1);
+        let mut len = 0;
+        for c in seq.chars() {
+            let code = match c {
+                'A' => 0,
+                'C' => 1,
+                'G' => 2,
+                'T' => 3,
+                _ => {
+                    return Err(AlignmentError::InvalidChar(format!(
+                        "The char {:?} at position {} isn't one of ACGT.",
+                        c, len
+                    )))
+                }
+            };
+            if len % 32 == 0 {
+                words.push(0);
+            }
+            words[len / 32] |= code << (2 * (len % 32));
+            len += 1;
+        }
+        Ok(Packed2Bit { words, len })
+    }
+
+    /// Number of bases of the sequence.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the base at position pos.
+    pub fn get(&self, pos: usize) -> Option<char> {
+        if pos < self.len {
+            Some(Self::BASES[((self.words[pos / 32] >> (2 * (pos % 32))) & 0b11) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the 32 bases starting at pos, packed in a word. The bases after the end of the
+    /// sequence are read as zeros.
+    pub(crate) fn word_at(&self, pos: usize) -> u64 {
+        let word = pos / 32;
+        let shift = 2 * (pos % 32);
+        let low = self.words.get(word).map_or(0, |w| w >> shift);
+        if shift == 0 {
+            low
+        } else {
+            low | self.words.get(word + 1).map_or(0, |w| w << (64 - shift))
+        }
+    }
+
+    /// Returns the number of equal bases of self and other, starting at self_pos and
+    /// other_pos respectively. Compares 32 bases at a time.
+    pub(crate) fn common_prefix(
+        &self,
+        self_pos: usize,
+        other: &Packed2Bit,
+        other_pos: usize,
+    ) -> usize {
+        let max = self
+            .len
+            .saturating_sub(self_pos)
+            .min(other.len.saturating_sub(other_pos));
+        let mut matched = 0;
+        while matched < max {
+            let diff = self.word_at(self_pos + matched) ^ other.word_at(other_pos + matched);
+            if diff == 0 {
+                matched += 32;
+            } else {
+                matched += (diff.trailing_zeros() / 2) as usize;
+                break;
+            }
+        }
+        matched.min(max)
+    }
+}
+
+impl core::fmt::Display for Packed2Bit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for pos in 0..self.len {
+            write!(f, "{}", self.get(pos).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// A vector of bits, packed in u64 words.
+/// Bit i is stored in words[i / 64], at bit i % 64, and the bits of the last word after the
+/// end of the vector are zeros, so that the words can be combined and counted directly.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVec {
+    /// Appends a bit to the end of the vector.
+    pub fn push(&mut self, bit: bool) {
+        if self.len % 64 == 0 {
+            self.words.push(0);
+        }
+        self.words[self.len / 64] |= (bit as u64) << (self.len % 64);
+        self.len += 1;
+    }
+
+    /// Returns the bit at position pos.
+    pub fn get(&self, pos: usize) -> Option<bool> {
+        if pos < self.len {
+            Some((self.words[pos / 64] >> (pos % 64)) & 1 == 1)
+        } else {
+            None
+        }
+    }
+
+    /// Number of bits of the vector.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bits set.
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns the words holding the bits.
+    pub fn as_words(&self) -> &[u64] {
+        &self.words
+    }
+}
+
+/// Value returned by every alignment function of this crate.
+pub type AlignResult = Result<Alignment, AlignmentError>;
+
+/// Signature shared by every alignment function of this crate.
+pub type AlignmentFunction = fn(&str, &str, &Penalties) -> AlignResult;
+
+impl AlignmentAlgorithm {
+    /// Returns the function implementing the algorithm, or None if it isn't implemented yet.
+    pub fn function(self) -> Option<AlignmentFunction> {
+        match self {
+            AlignmentAlgorithm::Wavefront => Some(crate::wavefront_alignment::wavefront_align),
+            AlignmentAlgorithm::WavefrontAdaptive => {
+                Some(crate::wavefront_alignment::wavefront_align_adaptive)
+            }
+            AlignmentAlgorithm::SWG => Some(crate::reference::affine_gap_align),
+        }
+    }
+}
+
+/// Aligns query and text using the given algorithm.
+/// Panics if the algorithm isn't implemented yet.
+pub fn align(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    algorithm: AlignmentAlgorithm,
+) -> AlignResult {
+    match algorithm.function() {
+        Some(align) => align(query, text, pens),
+        None => panic!("{} not yet implemented.", algorithm),
+    }
+}
+
+/// Length of the k-mers compared by auto_align to estimate the divergence of the strings.
+const AUTO_ALIGN_KMER_LENGTH: usize = 6;
+
+/// Product of the lengths of the strings up to which auto_align uses
+/// AUTO_ALIGN_MAX_KMER_DISTANCE_SHORT.
+const AUTO_ALIGN_SHORT_LENGTH_PRODUCT: usize = 100 * 100;
+
+/// k-mer distances above which auto_align uses SWG, for short and long strings. Calibrated
+/// with the crossover cases of benches/bench_compare.rs (random DNA, penalties 4, 6, 2:
+/// cargo bench --bench bench_compare -- crossover). The 6-mer distances at which WFA got
+/// slower than SWG were:
+/// - length 20: 0.83 (tie), length 50: between 0.93 and 0.97, length 100: between 0.83 and
+///   0.90;
+/// - length 300: between 0.74 and 0.81, length 1000: between 0.74 and 0.78.
+///
+/// SWG's O(n * m) grows faster with the lengths than the cost of the extra scores of WFA, so
+/// the threshold is lower for long strings.
+const AUTO_ALIGN_MAX_KMER_DISTANCE_SHORT: f64 = 0.9;
+const AUTO_ALIGN_MAX_KMER_DISTANCE: f64 = 0.76;
+
+/// Aligns query and text with the algorithm expected to be the fastest, and returns the
+/// alignment with the algorithm used. Both are exact, so the score is the same either way:
+/// WFA runs in O(n * s), where s is the score, while SWG runs in O(n * m) whatever the
+/// divergence, so WFA is used for similar strings and SWG for dissimilar ones. The divergence
+/// is estimated from the distance of the k-mers of the strings, without aligning them, and
+/// compared to a threshold that depends on the product of the lengths of the strings.
+/// SWG is also used if the query is longer than the text, since WFA doesn't support it.
+pub fn auto_align(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+) -> Result<(Alignment, AlignmentAlgorithm), AlignmentError> {
+    let (query_len, text_len) = (query.chars().count(), text.chars().count());
+    let max_distance = if query_len.saturating_mul(text_len) <= AUTO_ALIGN_SHORT_LENGTH_PRODUCT {
+        AUTO_ALIGN_MAX_KMER_DISTANCE_SHORT
+    } else {
+        AUTO_ALIGN_MAX_KMER_DISTANCE
+    };
+    let algorithm = if query_len > text_len
+        || kmer_distance(query, text, AUTO_ALIGN_KMER_LENGTH) > max_distance
+    {
+        AlignmentAlgorithm::SWG
+    } else {
+        AlignmentAlgorithm::Wavefront
+    };
+    align(query, text, pens, algorithm).map(|alignment| (alignment, algorithm))
+}
+
+/// Collects the options of an alignment, and dispatches it to the function implementing them.
+/// Options are set with the builder methods, the defaults give a global, unbanded WFA alignment:
+/// ```
+/// use lib::alignment_lib::*;
+/// let pens = Penalties { mismatch_pen: 4, open_pen: 6, extd_pen: 2 };
+/// let config = AlignmentConfig::new(pens)
+///     .algorithm(AlignmentAlgorithm::SWG)
+///     .ends(EndConfig::semi_global());
+/// assert_eq!(config.align("CAT", "GGCATGG").unwrap().score, 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlignmentConfig {
+    pens: Penalties,
+    algorithm: AlignmentAlgorithm,
+    band: Option<usize>,
+    ends: EndConfig,
+    wobble_pen: Option<u32>,
+    no_adjacent_indels: bool,
+    max_gap_len: Option<u32>,
+}
+
+impl AlignmentConfig {
+    pub fn new(pens: Penalties) -> AlignmentConfig {
+        AlignmentConfig {
+            pens,
+            algorithm: AlignmentAlgorithm::Wavefront,
+            band: None,
+            ends: EndConfig::global(),
+            wobble_pen: None,
+            no_adjacent_indels: false,
+            max_gap_len: None,
+        }
+    }
+
+    pub fn algorithm(mut self, algorithm: AlignmentAlgorithm) -> AlignmentConfig {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Only computes the diagonals at a distance <= band of the diagonals between the start
+    /// and the end of the alignment. Only supported for global alignments.
+    pub fn band(mut self, band: usize) -> AlignmentConfig {
+        self.band = Some(band);
+        self
+    }
+
+    /// Selects the free end gaps. Only supported by SWG.
+    pub fn ends(mut self, ends: EndConfig) -> AlignmentConfig {
+        self.ends = ends;
+        self
+    }
+
+    /// G-U and U-G pairs cost wobble_pen instead of mismatch_pen. Only supported by SWG.
+    pub fn wobble(mut self, wobble_pen: u32) -> AlignmentConfig {
+        self.wobble_pen = Some(wobble_pen);
+        self
+    }
+
+    /// Forbids insertions next to deletions. Only supported by SWG.
+    pub fn no_adjacent_indels(mut self, no_adjacent_indels: bool) -> AlignmentConfig {
+        self.no_adjacent_indels = no_adjacent_indels;
+        self
+    }
+
+    /// Forbids the gaps longer than max_gap_len chars. Only supported by SWG, and can't be
+    /// combined with the other options.
+    pub fn max_gap_len(mut self, max_gap_len: u32) -> AlignmentConfig {
+        self.max_gap_len = Some(max_gap_len);
+        self
+    }
+
+    /// Aligns query and text with the selected options.
+    /// Returns AlignmentError::Unsupported if the options can't be combined.
+    pub fn align(&self, query: &str, text: &str) -> AlignResult {
+        if self.band.is_some() && self.ends != EndConfig::global() {
+            return Err(AlignmentError::Unsupported(
+                "A band can only be used for global alignments.".to_string(),
+            ));
+        }
+        match self.algorithm {
+            AlignmentAlgorithm::Wavefront | AlignmentAlgorithm::WavefrontAdaptive => {
+                if self.ends != EndConfig::global()
+                    || self.wobble_pen.is_some()
+                    || self.no_adjacent_indels
+                    || self.max_gap_len.is_some()
+                {
+                    return Err(AlignmentError::Unsupported(
+                        "Free end gaps, wobble pairs, no_adjacent_indels and max_gap_len are only supported by SWG."
+                            .to_string(),
+                    ));
+                }
+                match (self.band, self.algorithm) {
+                    (Some(_), AlignmentAlgorithm::WavefrontAdaptive) => {
+                        Err(AlignmentError::Unsupported(
+                            "A band can't be used with the adaptive WFA.".to_string(),
+                        ))
+                    }
+                    (Some(band), _) => crate::wavefront_alignment::wavefront_align_banded(
+                        query, text, &self.pens, band,
+                    ),
+                    (None, AlignmentAlgorithm::WavefrontAdaptive) => {
+                        crate::wavefront_alignment::wavefront_align_adaptive(
+                            query, text, &self.pens,
+                        )
+                    }
+                    (None, _) => {
+                        crate::wavefront_alignment::wavefront_align(query, text, &self.pens)
+                    }
+                }
+            }
+            AlignmentAlgorithm::SWG => match self.max_gap_len {
+                Some(max_gap_len) => {
+                    if self.band.is_some()
+                        || self.ends != EndConfig::global()
+                        || self.wobble_pen.is_some()
+                        || self.no_adjacent_indels
+                    {
+                        return Err(AlignmentError::Unsupported(
+                            "max_gap_len can't be combined with the other options.".to_string(),
+                        ));
+                    }
+                    crate::reference::max_gap_align(query, text, &self.pens, max_gap_len)
+                }
+                None => crate::reference::configured_align(
+                    query,
+                    text,
+                    &self.pens,
+                    &self.ends,
+                    self.band,
+                    self.wobble_pen,
+                    self.no_adjacent_indels,
+                ),
+            },
+        }
+    }
+}
+
+/// Length of the query k-mers looked up in the text by score_lower_bound.
+const LOWER_BOUND_KMER_LENGTH: usize = 8;
+
+/// Returns a lower bound of the optimal alignment score of query and text, without aligning
+/// them. It is the maximum of 2 bounds, since a single gap can account for both:
+/// - the strings have different lengths, so a gap of at least the difference is needed.
+/// - the query is split into disjoint k-mers: each k-mer that isn't found in the text
+///   contains at least one edit, which costs at least min(mismatch_pen, extd_pen).
+pub fn score_lower_bound(query: &str, text: &str, pens: &Penalties) -> u32 {
+    let q_chars: Vec<char> = query.chars().collect();
+    let t_chars: Vec<char> = text.chars().collect();
+
+    let length_diff = q_chars.len().abs_diff(t_chars.len()) as u64;
+    let gap_bound = match length_diff {
+        0 => 0,
+        len => pens.open_pen as u64 + pens.extd_pen as u64 * len,
+    };
+
+    let k = LOWER_BOUND_KMER_LENGTH.min(q_chars.len()).max(1);
+    let text_kmers: BTreeSet<&[char]> = t_chars.windows(k).collect();
+    let missing_kmers = q_chars
+        .chunks_exact(k)
+        .filter(|kmer| !text_kmers.contains(kmer))
+        .count() as u64;
+    let kmer_bound = missing_kmers * pens.mismatch_pen.min(pens.extd_pen) as u64;
+
+    gap_bound.max(kmer_bound).min(u32::MAX as u64) as u32
 }
 
-/// Error type, for alignment errors.
-#[derive(Debug, Eq, PartialEq)]
-pub enum AlignmentError {
-    /// Both strings should have at least 1 character.
-    ZeroLength(String),
+/// Returns the Jaccard distance of the sets of k-mers of a and b: 1 - |intersection| / |union|.
+/// It doesn't align the strings, so it is a cheap estimate of their dissimilarity, e.g. to skip
+/// aligning distant pairs. 0 means that both have the same k-mers, 1 that they share none.
+/// If neither string has a k-mer (both are shorter than k), the distance is 0 if they are equal
+/// and 1 otherwise.
+/// Panics if k == 0.
+pub fn kmer_distance(a: &str, b: &str, k: usize) -> f64 {
+    assert!(k > 0, "The k-mer length must be > 0.");
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    let a_kmers: BTreeSet<&[char]> = a_chars.windows(k).collect();
+    let b_kmers: BTreeSet<&[char]> = b_chars.windows(k).collect();
+    let intersection = a_kmers.intersection(&b_kmers).count();
+    let union = a_kmers.len() + b_kmers.len() - intersection;
+    match union {
+        0 if a == b => 0.0,
+        0 => 1.0,
+        union => 1.0 - intersection as f64 / union as f64,
+    }
+}
 
-    /// query.len() needs to be <= to text.len()
-    QueryTooLong(String),
+/// Aligns the sequences read from a reader, using the given alignment function.
+/// The reader must yield alternating query/text lines, which are trimmed before aligning.
+#[cfg(feature = "std")]
+pub fn align_stream_with<'a, R: BufRead + 'a>(
+    reader: R,
+    pens: &'a Penalties,
+    align: AlignmentFunction,
+) -> AlignmentStream<'a, R> {
+    AlignmentStream {
+        lines: reader.lines(),
+        pens,
+        align,
+    }
+}
+
+/// Iterator returned by align_stream_with: reads 2 lines, aligns them, and so on.
+#[cfg(feature = "std")]
+pub struct AlignmentStream<'a, R> {
+    lines: Lines<R>,
+    pens: &'a Penalties,
+    align: AlignmentFunction,
+}
+
+#[cfg(feature = "std")]
+impl<R: BufRead> Iterator for AlignmentStream<'_, R> {
+    type Item = AlignResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let query = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(AlignmentError::ReadFailure(e.to_string()))),
+        };
+        let text = match self.lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => return Some(Err(AlignmentError::ReadFailure(e.to_string()))),
+            None => {
+                return Some(Err(AlignmentError::ReadFailure(format!(
+                    "The query {} has no matching text line.",
+                    query.trim()
+                ))))
+            }
+        };
+        Some((self.align)(query.trim(), text.trim(), self.pens))
+    }
+}
+
+/// Wraps an alignment function, and caches its results keyed on (query, text, penalties).
+/// The cache holds at most capacity alignments: when it is full, the least recently used one
+/// is evicted. Errors aren't cached.
+#[cfg(feature = "std")]
+pub struct CachingAligner {
+    align: AlignmentFunction,
+    capacity: usize,
+
+    /// Each alignment is stored with the time it was last used at.
+    entries: HashMap<(String, String, Penalties), (Alignment, u64)>,
+
+    /// Incremented on every lookup, used to find the least recently used entry.
+    clock: u64,
+
+    /// Number of alignments served from the cache.
+    hits: u64,
+}
+
+#[cfg(feature = "std")]
+impl CachingAligner {
+    pub fn new(align: AlignmentFunction, capacity: usize) -> CachingAligner {
+        CachingAligner {
+            align,
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+        }
+    }
+
+    /// Aligns query and text, or returns the cached alignment if they were already aligned
+    /// with these penalties.
+    pub fn align(&mut self, query: &str, text: &str, pens: &Penalties) -> AlignResult {
+        self.clock += 1;
+        let key = (query.to_string(), text.to_string(), pens.clone());
+        if let Some((alignment, last_used)) = self.entries.get_mut(&key) {
+            *last_used = self.clock;
+            self.hits += 1;
+            return Ok(alignment.clone());
+        }
+
+        let alignment = (self.align)(query, text, pens)?;
+        if self.capacity > 0 {
+            if self.entries.len() >= self.capacity {
+                // Finding the least recently used entry is linear in the capacity, which is
+                // negligible next to an alignment.
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.entries.insert(key, (alignment.clone(), self.clock));
+        }
+        Ok(alignment)
+    }
+
+    /// Number of alignments served from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of cached alignments.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 /// Alignment layers. Used for tracking back.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AlignmentLayer {
     Matches,
     Inserts,
     Deletes,
 }
 
+/// Stable numeric tag of a layer, for serialization: Matches = 0, Inserts = 1, Deletes = 2.
+/// The mapping doesn't depend on the order of the variants.
+impl From<AlignmentLayer> for u8 {
+    fn from(layer: AlignmentLayer) -> u8 {
+        match layer {
+            AlignmentLayer::Matches => 0,
+            AlignmentLayer::Inserts => 1,
+            AlignmentLayer::Deletes => 2,
+        }
+    }
+}
+
+/// Reads a tag written by From<AlignmentLayer>. Returns AlignmentError::InvalidTag for the
+/// tags > 2.
+impl TryFrom<u8> for AlignmentLayer {
+    type Error = AlignmentError;
+
+    fn try_from(tag: u8) -> Result<AlignmentLayer, AlignmentError> {
+        match tag {
+            0 => Ok(AlignmentLayer::Matches),
+            1 => Ok(AlignmentLayer::Inserts),
+            2 => Ok(AlignmentLayer::Deletes),
+            tag => Err(AlignmentError::InvalidTag(format!(
+                "{} isn't the tag of an alignment layer.",
+                tag
+            ))),
+        }
+    }
+}
+
 /// The methods for every wavefront type.
 pub(crate) trait Wavefront {
     fn extend(&mut self);
-    fn next(&mut self);
+    fn next(&mut self) -> Result<(), AlignmentError>;
     fn increment_score(&mut self);
     fn is_finished(&self) -> bool;
-    fn backtrace(&self) -> Result<Alignment, AlignmentError>;
+    fn backtrace(&self) -> AlignResult;
+}
+
+/// Read access to the cells of an aligner, for traceback. A cell belongs to a layer and is at
+/// a (query chars, text chars) position of the alignment matrix, however the aligner stores it.
+/// Each layer tag stored in a cell is the layer it was computed from, for both aligners.
+pub(crate) trait TracebackCells {
+    type Cell: Copy;
+
+    /// Returns the cell that cell was computed from, or None for the start of the alignment.
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError>;
+
+    /// Returns the layer of cell, with the number of query and text chars aligned up to it.
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize);
+
+    /// Returns whether the first column of the step from previous to the match cell cell can
+    /// be a mismatch. The other columns of the step must be matches.
+    fn can_mismatch(&self, previous: Self::Cell, cell: Self::Cell) -> bool;
+
+    /// Returns whether the query char q matches the text char t.
+    fn chars_match(&self, q: char, t: char) -> bool {
+        q == t
+    }
+}
+
+/// Walks back from end to the start of the alignment, calling emit on every column of the
+/// alignment (in reverse order) with its query and text chars. A None char is a gap.
+/// The columns between a cell and its predecessor follow from the layer of the cell:
+/// - an insertion (deletion) cell is one query (text) char after its predecessor.
+/// - a match cell is on the same diagonal as its predecessor, any number of (mis)matches
+///   after it: one for a DP matrix, the mismatch and the extension for the wavefronts, or
+///   none when closing a gap. Only the first column can be a mismatch, if can_mismatch
+///   allows it.
+///
+/// Returns AlignmentError::BacktraceFailed if a cell doesn't follow its predecessor this way,
+/// e.g. if the chars of a match column differ, or if the walk doesn't end at the start of
+/// both strings.
+pub(crate) fn traceback<C: TracebackCells, F: FnMut(Option<char>, Option<char>)>(
+    cells: &C,
+    end: C::Cell,
+    query: &[char],
+    text: &[char],
+    mut emit: F,
+) -> Result<(), AlignmentError> {
+    let mut cell = end;
+    let (mut layer, mut i, mut j) = cells.position(cell);
+    while let Some(previous) = cells.predecessor(cell)? {
+        let (previous_layer, previous_i, previous_j) = cells.position(previous);
+        match layer {
+            AlignmentLayer::Matches
+                if previous_i <= i && previous_j <= j && i - previous_i == j - previous_j =>
+            {
+                let len = i - previous_i;
+                let can_mismatch = cells.can_mismatch(previous, cell);
+                for k in 1..=len {
+                    let (q, t) = (query[i - k], text[j - k]);
+                    if !(cells.chars_match(q, t) || k == len && can_mismatch) {
+                        return Err(AlignmentError::BacktraceFailed(format!(
+                            "The chars {:?} and {:?} at ({}, {}) don't match.",
+                            q,
+                            t,
+                            i - k,
+                            j - k
+                        )));
+                    }
+                    emit(Some(q), Some(t));
+                }
+            }
+            AlignmentLayer::Inserts if (previous_i + 1, previous_j) == (i, j) => {
+                emit(Some(query[previous_i]), None)
+            }
+            AlignmentLayer::Deletes if (previous_i, previous_j + 1) == (i, j) => {
+                emit(None, Some(text[previous_j]))
+            }
+            _ => {
+                return Err(AlignmentError::BacktraceFailed(format!(
+                    "The {:?} cell at ({}, {}) can't follow the {:?} cell at ({}, {}).",
+                    layer, i, j, previous_layer, previous_i, previous_j
+                )))
+            }
+        }
+        cell = previous;
+        (layer, i, j) = (previous_layer, previous_i, previous_j);
+    }
+    if (i, j) != (0, 0) {
+        return Err(AlignmentError::BacktraceFailed(format!(
+            "The backtrace stopped at ({}, {}) instead of the start of the alignment.",
+            i, j
+        )));
+    }
+    Ok(())
 }
 
 /// Used to store and access wavefronts efficiently.
 /// T is the type used to store the number of chars matched.
-/// U is the type used for diagonals.
-#[derive(Debug, Eq, PartialEq)]
+/// Diagonals are stored as i64s, so that they can't overflow for very long sequences.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct WavefrontGrid {
     /// The vec of (lowest valid diag, highest valid diag) for each score.
     /// Lowest is always a negative value, stored using an unsigned type.
-    diags: Vec<(i32, i32)>,
+    diags: Vec<(i64, i64)>,
 
     /// The vec that stores the offset at which each layer starts in the vector.
     /// Each layer corresponds to a score.
@@ -87,6 +2001,18 @@ pub(crate) struct WavefrontGrid {
     deletes: Vec<Option<(u32, AlignmentLayer)>>,
 }
 
+/// The layers of a WavefrontGrid from the score first_score, returned by
+/// WavefrontGrid::window. The cells are stored as in the grid.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct GridWindow {
+    pub(crate) first_score: u32,
+    diags: Vec<(i64, i64)>,
+    matches: Vec<Option<(u32, AlignmentLayer)>>,
+    inserts: Vec<Option<(u32, AlignmentLayer)>>,
+    deletes: Vec<Option<(u32, AlignmentLayer)>>,
+}
+
 /// Make a new wavefront grid with the first diagonal of (lo, hi)
 /// lo and hi = 0 for a 1-element initial diagonal.
 pub(crate) fn new_wavefront_grid() -> WavefrontGrid {
@@ -123,19 +2049,35 @@ pub(crate) fn new_wavefront_grid() -> WavefrontGrid {
 
 impl WavefrontGrid {
     /// Add a new layer to the wavefronts.
-    /// lo and hi are the lowest/highest diagonals for this new layer.
-    pub(crate) fn add_layer(&mut self, lo: i32, hi: i32) {
-        self.diags.push((lo, hi));
+    /// lo and hi are the lowest/highest diagonals for this new layer: hi = lo - 1 adds an empty
+    /// layer. Returns AlignmentError::WavefrontTooWide if the number of diagonals, or the total
+    /// number of cells, doesn't fit in a usize. The grid is then left unchanged.
+    pub(crate) fn add_layer(&mut self, lo: i64, hi: i64) -> Result<(), AlignmentError> {
+        let too_wide = || {
+            AlignmentError::WavefrontTooWide(format!(
+                "The wavefront of diagonals {} to {} can't be stored.",
+                lo, hi
+            ))
+        };
+        let new_width = hi
+            .checked_sub(lo)
+            .and_then(|width| width.checked_add(1))
+            .and_then(|width| usize::try_from(width).ok())
+            .ok_or_else(too_wide)?;
+        let new_offset = self.offsets[self.offsets.len() - 1]
+            .checked_add(new_width)
+            .ok_or_else(too_wide)?;
 
-        let new_width: usize = (hi - lo + 1) as usize;
-        self.offsets
-            .push(self.offsets[self.offsets.len() - 1] + new_width);
+        self.diags.push((lo, hi));
+        self.offsets.push(new_offset);
 
-        for _ in lo..=hi {
-            self.matches.push(None);
-            self.inserts.push(None);
-            self.deletes.push(None);
-        }
+        self.matches.reserve(new_width);
+        self.inserts.reserve(new_width);
+        self.deletes.reserve(new_width);
+        self.matches.extend(iter::repeat(None).take(new_width));
+        self.inserts.extend(iter::repeat(None).take(new_width));
+        self.deletes.extend(iter::repeat(None).take(new_width));
+        Ok(())
     }
 
     /// Get a value.
@@ -143,7 +2085,7 @@ impl WavefrontGrid {
         &self,
         layer: AlignmentLayer,
         score: u32,
-        diag: i32,
+        diag: i64,
     ) -> Option<(u32, AlignmentLayer)> {
         let score = score as usize;
         if score >= self.offsets.len() || diag < self.diags[score].0 || diag > self.diags[score].1 {
@@ -165,7 +2107,7 @@ impl WavefrontGrid {
         &mut self,
         layer: AlignmentLayer,
         score: u32,
-        diag: i32,
+        diag: i64,
         value: Option<(u32, AlignmentLayer)>,
     ) {
         let score = score as usize;
@@ -180,11 +2122,106 @@ impl WavefrontGrid {
         }
     }
 
-    pub(crate) fn get_diag_range(&self, score: u32) -> Option<&(i32, i32)> {
+    /// Returns the (diagonal, offset) of the diagonals reached in the matches layer of a
+    /// score, by increasing diagonal. Empty if the score wasn't computed.
+    pub(crate) fn live_diagonals(&self, score: u32) -> impl Iterator<Item = (i64, u32)> + '_ {
+        let score = score as usize;
+        let (lo, cells) = match self.diags.get(score) {
+            Some(&(lo, _)) => (
+                lo,
+                &self.matches[self.offsets[score]..self.offsets[score + 1]],
+            ),
+            None => (0, &[][..]),
+        };
+        cells
+            .iter()
+            .zip(lo..)
+            .filter_map(|(cell, diag)| cell.map(|(offset, _)| (diag, offset)))
+    }
+
+    /// Number of layers, i.e. of scores computed.
+    pub(crate) fn num_layers(&self) -> usize {
+        self.diags.len()
+    }
+
+    /// Number of cells allocated in each layer type, over every score. Also used outside of
+    /// the tests, for the cells_computed statistic of the adaptive WFA.
+    pub(crate) fn num_cells(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Number of diagonals of the widest layer.
+    pub(crate) fn max_width(&self) -> usize {
+        self.diags
+            .iter()
+            .map(|(lo, hi)| (hi - lo + 1) as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Restricts the last layer to the diagonals lo..=hi, which must be within its range.
+    /// The cells of the removed diagonals are dropped.
+    pub(crate) fn restrict_last_layer(&mut self, lo: i64, hi: i64) {
+        let score = self.diags.len() - 1;
+        let (old_lo, old_hi) = self.diags[score];
+        let start = self.offsets[score];
+        let removed_lo = (lo - old_lo) as usize;
+        let removed_hi = (old_hi - hi) as usize;
+        for cells in [&mut self.matches, &mut self.inserts, &mut self.deletes] {
+            cells.truncate(cells.len() - removed_hi);
+            cells.drain(start..start + removed_lo);
+        }
+        self.diags[score] = (lo, hi);
+        self.offsets[score + 1] -= removed_lo + removed_hi;
+    }
+
+    /// Returns a copy of the layers from the score first_score to the last one.
+    pub(crate) fn window(&self, first_score: u32) -> GridWindow {
+        let first = (first_score as usize).min(self.diags.len());
+        let start = self.offsets[first];
+        let end = self.offsets[self.diags.len()];
+        GridWindow {
+            first_score: first as u32,
+            diags: self.diags[first..].to_vec(),
+            matches: self.matches[start..end].to_vec(),
+            inserts: self.inserts[start..end].to_vec(),
+            deletes: self.deletes[start..end].to_vec(),
+        }
+    }
+
+    /// Builds a grid holding the layers of window, the layers of the lower scores being empty.
+    /// Returns None if the cells of window don't fill its layers, e.g. if it was corrupted
+    /// while stored.
+    pub(crate) fn from_window(window: &GridWindow) -> Option<WavefrontGrid> {
+        let mut diags = vec![(0, -1); window.first_score as usize];
+        diags.extend_from_slice(&window.diags);
+        let mut offsets: Vec<usize> = vec![0; window.first_score as usize + 1];
+        for (lo, hi) in &window.diags {
+            let width = usize::try_from(hi.checked_sub(*lo)?.checked_add(1)?).ok()?;
+            offsets.push(offsets[offsets.len() - 1].checked_add(width)?);
+        }
+        let num_cells = offsets[offsets.len() - 1];
+        if window.diags.is_empty()
+            || [&window.matches, &window.inserts, &window.deletes]
+                .iter()
+                .any(|cells| cells.len() != num_cells)
+        {
+            return None;
+        }
+        Some(WavefrontGrid {
+            diags,
+            offsets,
+            matches: window.matches.clone(),
+            inserts: window.inserts.clone(),
+            deletes: window.deletes.clone(),
+        })
+    }
+
+    pub(crate) fn get_diag_range(&self, score: u32) -> Option<&(i64, i64)> {
         self.diags.get(score as usize)
     }
 
-    pub(crate) fn increment(&mut self, score: u32, diag: i32) {
+    pub(crate) fn increment(&mut self, score: u32, diag: i64) {
         let score = score as usize;
         let position = self.offsets[score] + (diag - self.diags[score].0) as usize;
         self.matches[position] = match self.matches[position] {
@@ -194,6 +2231,966 @@ impl WavefrontGrid {
     }
 }
 
+#[cfg(test)]
+mod tests_alignment {
+    use super::*;
+
+    #[test]
+    fn test_slice_query() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let alignment = Alignment::new(11, "ACG--TAC-GT".to_string(), "ACGTTTTCAGT".to_string());
+        assert_eq!(alignment.recompute_score(&pens), 11);
+        let sliced = alignment.slice_query(2, 6).unwrap();
+        assert_eq!(
+            sliced,
+            Alignment::new(0, "G--TAC".to_string(), "GTTTTC".to_string())
+        );
+        assert_eq!(sliced.recompute_score(&pens), 8);
+        // The range starts right after a gap, which isn't kept.
+        let sliced = alignment.slice_query(3, 8).unwrap();
+        assert_eq!(
+            sliced,
+            Alignment::new(0, "TAC-GT".to_string(), "TTCAGT".to_string())
+        );
+        assert_eq!(sliced.recompute_score(&pens), 7);
+        let whole = alignment.slice_query(0, 8).unwrap();
+        assert_eq!(Alignment { score: 11, ..whole }, alignment);
+        assert_eq!(alignment.slice_query(4, 4).unwrap().query_aligned, "");
+        for (start, end) in [(5, 4), (0, 9), (9, 9)] {
+            assert!(matches!(
+                alignment.slice_query(start, end),
+                Err(AlignmentError::InvalidRange(_))
+            ));
+        }
+        // The chars skipped by free end gaps stay outside of the coordinates of the slice.
+        let overlap = Alignment::new(0, "GGACGT".to_string(), "--ACGT".to_string())
+            .with_free_ends(&EndConfig::overlap());
+        assert_eq!((overlap.query_start, overlap.query_end), (2, 6));
+        let sliced = overlap.slice_query(1, 4).unwrap();
+        assert_eq!(sliced.query_aligned, "GAC");
+        assert_eq!((sliced.query_start, sliced.query_end), (1, 3));
+        assert_eq!((sliced.text_start, sliced.text_end), (0, 2));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_deserialize_without_coordinates() {
+        let alignment: Alignment = serde_json::from_str(
+            r#"{"score":8,"query_aligned":"GAT-ACA","text_aligned":"GATTACA"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            alignment,
+            Alignment::new(8, "GAT-ACA".to_string(), "GATTACA".to_string())
+        );
+        let overlap = Alignment::new(0, "GGACGT".to_string(), "--ACGT".to_string())
+            .with_free_ends(&EndConfig::overlap());
+        let serialized = serde_json::to_string(&overlap).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Alignment>(&serialized).unwrap(),
+            overlap
+        );
+    }
+
+    #[test]
+    fn test_divergent_blocks() {
+        let alignment = Alignment::new(
+            0,
+            "AAAAACAAAAAAAAAAAAA-AAAAA".to_string(),
+            "AAAAAGAAAAAAAAAAAAAAAAAAA".to_string(),
+        );
+        assert_eq!(
+            alignment.divergent_blocks(2),
+            vec![
+                AlignmentBlock {
+                    query_start: 3,
+                    query_end: 8,
+                    text_start: 3,
+                    text_end: 8,
+                    query_aligned: "AACAA".to_string(),
+                    text_aligned: "AAGAA".to_string(),
+                },
+                AlignmentBlock {
+                    query_start: 17,
+                    query_end: 21,
+                    text_start: 17,
+                    text_end: 22,
+                    query_aligned: "AA-AA".to_string(),
+                    text_aligned: "AAAAA".to_string(),
+                },
+            ]
+        );
+        // With a wider context, both blocks overlap and are merged.
+        let merged = alignment.divergent_blocks(7);
+        assert_eq!(merged.len(), 1);
+        assert_eq!((merged[0].query_start, merged[0].query_end), (0, 24));
+        assert_eq!((merged[0].text_start, merged[0].text_end), (0, 25));
+        assert!(alignment
+            .divergent_blocks(0)
+            .iter()
+            .all(|b| b.query_aligned.len() == 1));
+    }
+
+    #[test]
+    fn test_rescore_many() {
+        let alignment = Alignment::new(0, "ACG--TAC-GTT".to_string(), "ACGTTTTCAG--".to_string());
+        let pen_sets = [
+            Penalties {
+                mismatch_pen: 4,
+                open_pen: 2,
+                extd_pen: 1,
+            },
+            Penalties {
+                mismatch_pen: 1,
+                open_pen: 10,
+                extd_pen: 3,
+            },
+            Penalties {
+                mismatch_pen: 7,
+                open_pen: 0,
+                extd_pen: 5,
+            },
+            Penalties {
+                mismatch_pen: 1,
+                open_pen: u32::MAX,
+                extd_pen: 1,
+            },
+        ];
+        let expected: Vec<u32> = pen_sets
+            .iter()
+            .map(|pens| alignment.recompute_score(pens))
+            .collect();
+        assert_eq!(alignment.rescore_many(&pen_sets), expected);
+        assert!(alignment.rescore_many(&[]).is_empty());
+        // The 3 gaps cost more than u32::MAX: the scores saturate, but the alignment isn't
+        // consistent with a score of u32::MAX.
+        assert_eq!(expected[3], u32::MAX);
+        let saturated = Alignment {
+            score: u32::MAX,
+            ..alignment
+        };
+        assert!(!saturated.is_score_consistent(&pen_sets[3]));
+    }
+
+    #[test]
+    fn test_packed_2bit() {
+        let seq = "ACGTTGCAACGTACGTACGTACGTACGTACGTAAGGCT";
+        let packed = Packed2Bit::new(seq).unwrap();
+        assert_eq!(packed.len(), seq.len());
+        assert_eq!(packed.to_string(), seq);
+        assert_eq!(packed.get(2), Some('G'));
+        assert_eq!(packed.get(seq.len()), None);
+        assert!(matches!(
+            Packed2Bit::new("ACGN"),
+            Err(AlignmentError::InvalidChar(_))
+        ));
+
+        let other = Packed2Bit::new("TTACGTTGCAACGTACGTACGTACGTACGTACGTAAGCCT").unwrap();
+        assert_eq!(packed.common_prefix(0, &other, 2), 35);
+        assert_eq!(packed.common_prefix(36, &other, 38), 2);
+        assert_eq!(packed.common_prefix(0, &other, 0), 0);
+        assert_eq!(packed.common_prefix(seq.len(), &other, 0), 0);
+    }
+
+    #[test]
+    fn test_align() {
+        let pens = Penalties {
+            mismatch_pen: 3,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let wfa = align("GATTACA", "GCATTAGA", &pens, AlignmentAlgorithm::Wavefront).unwrap();
+        let swg = align("GATTACA", "GCATTAGA", &pens, AlignmentAlgorithm::SWG).unwrap();
+        assert_eq!(wfa.score, swg.score);
+        let adaptive = align(
+            "GATTACA",
+            "GCATTAGA",
+            &pens,
+            AlignmentAlgorithm::WavefrontAdaptive,
+        )
+        .unwrap();
+        assert!(adaptive.score >= wfa.score);
+    }
+
+    #[test]
+    fn test_auto_align() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let similar = (
+            "GATTACAGATTACACCGTAGGCTAGGATC",
+            "GATTACAGATTTACACCGTAGGCTAGGAAC",
+        );
+        let dissimilar = (
+            "GATTACAGATTACACCGTAGGCTAGGATC",
+            "CTGGCATATCGCAATCGGACTATTGCAGT",
+        );
+        let longer_query = ("GATTACAGATTTACA", "GATTACAGATTACA");
+        // The same divergence is only worth SWG for longer strings.
+        let diverged = (
+            "GATTACAGATTACACCGTAGGCTAGGATC",
+            "GATTGCAGATTACTCCGTAGGGTAGGATCA",
+        );
+        let diverged_long = (diverged.0.repeat(10), diverged.1.repeat(10));
+        assert!(kmer_distance(diverged.0, diverged.1, 6) > AUTO_ALIGN_MAX_KMER_DISTANCE);
+        assert!(
+            kmer_distance(&diverged_long.0, &diverged_long.1, 6)
+                <= AUTO_ALIGN_MAX_KMER_DISTANCE_SHORT
+        );
+        for ((query, text), expected) in [
+            (similar, AlignmentAlgorithm::Wavefront),
+            (dissimilar, AlignmentAlgorithm::SWG),
+            (longer_query, AlignmentAlgorithm::SWG),
+            (diverged, AlignmentAlgorithm::Wavefront),
+            (
+                (diverged_long.0.as_str(), diverged_long.1.as_str()),
+                AlignmentAlgorithm::SWG,
+            ),
+        ] {
+            let (alignment, algorithm) = auto_align(query, text, &pens).unwrap();
+            assert_eq!(algorithm, expected);
+            assert_eq!(alignment, align(query, text, &pens, algorithm).unwrap());
+            assert_eq!(
+                alignment.score,
+                crate::reference::affine_gap_align(query, text, &pens)
+                    .unwrap()
+                    .score
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_caching_aligner() {
+        let pens = Penalties {
+            mismatch_pen: 3,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let mut aligner = CachingAligner::new(crate::wavefront_alignment::wavefront_align, 2);
+        let first = aligner.align("GATTACA", "GCATTAGA", &pens).unwrap();
+        assert_eq!(aligner.hits(), 0);
+        assert_eq!(aligner.align("GATTACA", "GCATTAGA", &pens).unwrap(), first);
+        assert_eq!(aligner.hits(), 1);
+
+        // Other penalties are another key.
+        let other_pens = Penalties {
+            mismatch_pen: 1,
+            ..pens.clone()
+        };
+        aligner.align("GATTACA", "GCATTAGA", &other_pens).unwrap();
+        assert_eq!(aligner.hits(), 1);
+        assert_eq!(aligner.len(), 2);
+
+        // The cache is full: the least recently used entry (other_pens) is evicted.
+        aligner.align("GATTACA", "GCATTAGA", &pens).unwrap();
+        assert_eq!(aligner.hits(), 2);
+        aligner.align("CAT", "CATS", &pens).unwrap();
+        assert_eq!(aligner.len(), 2);
+        aligner.align("GATTACA", "GCATTAGA", &pens).unwrap();
+        assert_eq!(aligner.hits(), 3);
+        aligner.align("GATTACA", "GCATTAGA", &other_pens).unwrap();
+        assert_eq!(aligner.hits(), 3);
+
+        assert!(aligner.align("", "CAT", &pens).is_err());
+    }
+
+    #[test]
+    fn test_swap() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let alignment = Alignment::new(11, "ACG--TAC-GT".to_string(), "ACGTTTTCAGT".to_string());
+        let swapped = alignment.swap();
+        assert_eq!(swapped.query_aligned, "ACGTTTTCAGT");
+        assert_eq!(swapped.text_aligned, "ACG--TAC-GT");
+        assert!(swapped.is_score_consistent(&pens));
+        assert_eq!(swapped.swap(), alignment);
+    }
+
+    #[test]
+    fn test_to_html() {
+        let alignment = Alignment::new(12, "GAT-ACA".to_string(), "GCTTAC<".to_string());
+        let html = alignment.to_html(0);
+        assert!(html.starts_with("<div class=\"alignment\"><style>"));
+        assert!(html.contains(".alignment .match{color:green}"));
+        assert!(html.contains(
+            "<pre><span class=\"match\">G</span><span class=\"mismatch\">A</span>\
+             <span class=\"match\">T</span><span class=\"gap\">-</span>\
+             <span class=\"match\">AC</span><span class=\"mismatch\">A</span>\n"
+        ));
+        // The text row, with the < escaped.
+        assert!(html.contains("<span class=\"mismatch\">&lt;</span>\n</pre></div>"));
+
+        // Blocks of 4 columns, separated by an empty line.
+        let html = alignment.to_html(4);
+        assert!(html.contains("<span class=\"gap\">T</span>\n\n<span class=\"match\">AC</span>"));
+        assert_eq!(html.matches("<span class=\"gap\">").count(), 2);
+    }
+
+    #[test]
+    fn test_to_blocks() {
+        let alignment = Alignment::new(
+            0,
+            "GATTACA---GATTACAGAT".to_string(),
+            "GATCACAGGGGATTACA---".to_string(),
+        );
+        // 2 digits per position: 9 chars of left margin and 3 of right margin, so that blocks
+        // of 8 columns fit in 20 chars.
+        assert_eq!(
+            alignment.to_blocks(20, false),
+            "Query  1 GATTACA- 7\n\
+             \x20        |||.|||\n\
+             Text   1 GATCACAG 8\n\
+             \n\
+             Query  8 --GATTAC 13\n\
+             \x20          ||||||\n\
+             Text   9 GGGATTAC 16\n\
+             \n\
+             Query 14 AGAT 17\n\
+             \x20        |\n\
+             Text  17 A--- 17\n"
+        );
+        let with_ruler = alignment.to_blocks(20, true);
+        let lines: Vec<&str> = with_ruler.lines().collect();
+        assert_eq!(lines[0], "             .");
+        assert_eq!(lines[5], "         10    .");
+        assert_eq!(lines[10], "           20");
+        assert_eq!(lines[11], "Query 14 AGAT 17");
+        // A line of gaps has the position of the previous char at both ends.
+        let gaps = Alignment::new(0, "CAT---".to_string(), "CATGGG".to_string());
+        assert!(gaps.to_blocks(13, false).contains("Query 3 --- 3\n"));
+        // A single block when the lines are wide enough.
+        assert_eq!(alignment.to_blocks(100, false).lines().count(), 3);
+    }
+
+    #[test]
+    fn test_to_variants() {
+        let snp = Alignment::new(4, "GATTACA".to_string(), "GATCACA".to_string());
+        assert_eq!(
+            snp.to_variants(100),
+            vec![Variant::Snp {
+                pos: 103,
+                ref_base: 'C',
+                alt_base: 'T',
+            }]
+        );
+        // Insertions in a homopolymer are reported at its start.
+        let insertion = Alignment::new(4, "GAAAAT".to_string(), "GAA--T".to_string());
+        assert_eq!(
+            insertion.to_variants(0),
+            vec![Variant::Insertion {
+                pos: 1,
+                seq: "AA".to_string(),
+            }]
+        );
+        // Deletions in a repeat too.
+        let deletion = Alignment::new(4, "TCA--G".to_string(), "TCACAG".to_string());
+        assert_eq!(
+            deletion.to_variants(0),
+            vec![Variant::Deletion { pos: 1, len: 2 }]
+        );
+        // Adjacent gaps of both kinds give an insertion and a deletion at the same position.
+        let both = Alignment::new(6, "AC-GT".to_string(), "A-TGT".to_string());
+        assert_eq!(
+            both.to_variants(0),
+            vec![
+                Variant::Insertion {
+                    pos: 1,
+                    seq: "C".to_string(),
+                },
+                Variant::Deletion { pos: 1, len: 1 },
+            ]
+        );
+        // The free end gaps aren't variants.
+        let semi_global = Alignment {
+            text_start: 2,
+            text_end: 6,
+            ..Alignment::new(4, "--GATC--".to_string(), "AAGAACTT".to_string())
+        };
+        assert_eq!(
+            semi_global.to_variants(0),
+            vec![Variant::Snp {
+                pos: 4,
+                ref_base: 'A',
+                alt_base: 'T',
+            }]
+        );
+        assert_eq!(
+            Alignment::new(0, "GATTACA".to_string(), "GATTACA".to_string()).to_variants(0),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_normalize_gaps() {
+        use crate::reference::affine_gap_align;
+        use crate::wavefront_alignment::wavefront_align;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let alignment = Alignment::new(0, "A-C-G".to_string(), "-B-D-".to_string());
+        let normalized = alignment.normalize_gaps();
+        assert_eq!(normalized.query_aligned, "ACG--");
+        assert_eq!(normalized.text_aligned, "---BD");
+        assert_eq!(
+            Alignment::new(0, "-AC".to_string(), "B-C".to_string()).normalize_gaps(),
+            Alignment::new(0, "A-C".to_string(), "-BC".to_string())
+        );
+        assert_eq!(normalized.normalize_gaps(), normalized);
+
+        // With a prohibitive mismatch_pen and no common char, the optimal alignments are an
+        // insertion and a deletion of everything, in any order.
+        let pens = Penalties {
+            mismatch_pen: 1000,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        let mut rng = StdRng::seed_from_u64(640);
+        for _ in 0..100 {
+            let query_length = rng.gen_range(1..20);
+            let text_length = query_length + rng.gen_range(0..5);
+            let query: String = (0..query_length)
+                .map(|_| ['A', 'C'][rng.gen_range(0..2)])
+                .collect();
+            let text: String = (0..text_length)
+                .map(|_| ['G', 'T'][rng.gen_range(0..2)])
+                .collect();
+            let wfa = wavefront_align(&query, &text, &pens)
+                .unwrap()
+                .normalize_gaps();
+            let swg = affine_gap_align(&query, &text, &pens)
+                .unwrap()
+                .normalize_gaps();
+            assert_eq!(wfa, swg);
+            assert_eq!(wfa.query_aligned, query.clone() + &"-".repeat(text_length));
+            assert!(wfa.is_score_consistent(&pens));
+        }
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let alignment = Alignment::new(11, "ACG--TAC-GT".to_string(), "ACGTTTTCAGT".to_string());
+        assert_eq!((alignment.query_start, alignment.query_end), (0, 8));
+        assert_eq!((alignment.text_start, alignment.text_end), (0, 11));
+        assert_eq!(alignment.aligned_len(), 11);
+
+        let semi_global = Alignment::new(0, "--CAT--".to_string(), "GGCATGG".to_string())
+            .with_free_ends(&EndConfig::semi_global());
+        assert_eq!((semi_global.text_start, semi_global.text_end), (2, 5));
+        assert_eq!(semi_global.aligned_len(), 3);
+        let swapped = semi_global.swap();
+        assert_eq!((swapped.query_start, swapped.query_end), (2, 5));
+        assert_eq!((swapped.text_start, swapped.text_end), (0, 3));
+        // The end gaps aren't skipped in a global alignment.
+        let global = Alignment::new(0, "--CAT--".to_string(), "GGCATGG".to_string())
+            .with_free_ends(&EndConfig::global());
+        assert_eq!((global.text_start, global.text_end), (0, 7));
+        // Both runs of end gaps are skipped when both ends are free.
+        let free_ends = EndConfig {
+            query_start_free: true,
+            query_end_free: true,
+            text_start_free: true,
+            text_end_free: true,
+        };
+        let both_runs = Alignment::new(0, "--AACAT-".to_string(), "GG--CATG".to_string())
+            .with_free_ends(&free_ends);
+        assert_eq!((both_runs.query_start, both_runs.query_end), (2, 5));
+        assert_eq!((both_runs.text_start, both_runs.text_end), (2, 5));
+        assert_eq!(both_runs.aligned_len(), 3);
+
+        let joined = semi_global.concat(&alignment);
+        assert_eq!((joined.query_start, joined.query_end), (0, 11));
+        assert_eq!((joined.text_start, joined.text_end), (2, 18));
+    }
+
+    #[test]
+    fn test_normalized_score() {
+        let alignment = Alignment::new(11, "ACG--TAC-GT".to_string(), "ACGTTTTCAGT".to_string());
+        assert_eq!(alignment.normalized_score(), 1.0);
+        let alignment = Alignment::new(3, "GATTACA".to_string(), "GATCACA".to_string());
+        assert_eq!(alignment.normalized_score(), 3.0 / 7.0);
+        let empty = Alignment::new(0, String::new(), String::new());
+        assert_eq!(empty.normalized_score(), 0.0);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_edit_script() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use crate::wavefront_alignment::wavefront_align;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        fn apply(query: &str, edits: &[Edit]) -> String {
+            let mut chars: Vec<char> = query.chars().collect();
+            for edit in edits {
+                match *edit {
+                    Edit::Insert { pos, c } => chars.insert(pos, c),
+                    Edit::Delete { pos } => {
+                        chars.remove(pos);
+                    }
+                    Edit::Substitute { pos, from, to } => {
+                        assert_eq!(chars[pos], from);
+                        chars[pos] = to;
+                    }
+                }
+            }
+            chars.into_iter().collect()
+        }
+
+        let alignment = Alignment::new(0, "ACG--TAC-GTT".to_string(), "ACGTTTTCAG--".to_string());
+        assert_eq!(
+            alignment.edit_script(),
+            vec![
+                Edit::Insert { pos: 3, c: 'T' },
+                Edit::Insert { pos: 4, c: 'T' },
+                Edit::Substitute {
+                    pos: 6,
+                    from: 'A',
+                    to: 'T'
+                },
+                Edit::Insert { pos: 8, c: 'A' },
+                Edit::Delete { pos: 10 },
+                Edit::Delete { pos: 10 },
+            ]
+        );
+        assert_eq!(apply("ACGTACGTT", &alignment.edit_script()), "ACGTTTTCAG");
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mut rng = StdRng::seed_from_u64(665);
+        for _ in 0..200 {
+            let text_length = rng.gen_range(1..60);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let query_length = rng.gen_range(1..=text.len());
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let alignment = wavefront_align(&query, &text, &pens).unwrap();
+            assert_eq!(apply(&query, &alignment.edit_script()), text);
+        }
+    }
+
+    #[test]
+    fn test_layer_tags() {
+        let layers = [
+            AlignmentLayer::Matches,
+            AlignmentLayer::Inserts,
+            AlignmentLayer::Deletes,
+        ];
+        for (tag, layer) in layers.into_iter().enumerate() {
+            assert_eq!(u8::from(layer), tag as u8);
+            assert_eq!(AlignmentLayer::try_from(tag as u8), Ok(layer));
+        }
+        for tag in [3, 4, u8::MAX] {
+            assert!(matches!(
+                AlignmentLayer::try_from(tag),
+                Err(AlignmentError::InvalidTag(_))
+            ));
+        }
+    }
+
+    /// A path of cells, each one the predecessor of the next one.
+    struct Path {
+        cells: Vec<(AlignmentLayer, usize, usize)>,
+        can_mismatch: bool,
+    }
+
+    impl TracebackCells for Path {
+        type Cell = usize;
+
+        fn predecessor(&self, cell: usize) -> Result<Option<usize>, AlignmentError> {
+            Ok(cell.checked_sub(1))
+        }
+
+        fn position(&self, cell: usize) -> (AlignmentLayer, usize, usize) {
+            self.cells[cell]
+        }
+
+        fn can_mismatch(&self, _previous: usize, _cell: usize) -> bool {
+            self.can_mismatch
+        }
+    }
+
+    #[test]
+    fn test_traceback_checks_matches() {
+        let run = |query: &str, text: &str, can_mismatch: bool| {
+            let path = Path {
+                cells: vec![
+                    (AlignmentLayer::Matches, 0, 0),
+                    (AlignmentLayer::Matches, 2, 2),
+                ],
+                can_mismatch,
+            };
+            let (query, text): (Vec<char>, Vec<char>) =
+                (query.chars().collect(), text.chars().collect());
+            let mut columns = Vec::new();
+            traceback(&path, 1, &query, &text, |q, t| columns.push((q, t))).map(|_| columns)
+        };
+        assert_eq!(
+            run("GA", "GA", false),
+            Ok(vec![(Some('A'), Some('A')), (Some('G'), Some('G'))])
+        );
+        // Only the first column of the step can be a mismatch.
+        assert_eq!(
+            run("CA", "GA", true),
+            Ok(vec![(Some('A'), Some('A')), (Some('C'), Some('G'))])
+        );
+        assert!(matches!(
+            run("CA", "GA", false),
+            Err(AlignmentError::BacktraceFailed(_))
+        ));
+        assert!(matches!(
+            run("AC", "AG", true),
+            Err(AlignmentError::BacktraceFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_columns() {
+        let alignment = Alignment::new(0, "AC-GT".to_string(), "A-TGA".to_string());
+        assert_eq!(
+            alignment.columns().collect::<Vec<_>>(),
+            vec![
+                (Some('A'), Some('A')),
+                (Some('C'), None),
+                (None, Some('T')),
+                (Some('G'), Some('G')),
+                (Some('T'), Some('A')),
+            ]
+        );
+        let empty = Alignment::new(0, String::new(), String::new());
+        assert_eq!(empty.columns().count(), 0);
+    }
+
+    #[test]
+    fn test_identity() {
+        let alignment = Alignment::new(0, "GATTACA".to_string(), "GATCACA".to_string());
+        assert_eq!(alignment.identity(), 6.0 / 7.0);
+        let alignment = Alignment::new(0, "ACG--TAC-GT".to_string(), "ACGTTTTCAGT".to_string());
+        assert_eq!(alignment.identity(), 7.0 / 11.0);
+        // The columns outside of the coordinates aren't part of the aligned region.
+        let alignment = Alignment {
+            text_start: 2,
+            text_end: 5,
+            ..Alignment::new(0, "--CAT--".to_string(), "GGCTTGG".to_string())
+        };
+        assert_eq!(alignment.identity(), 2.0 / 3.0);
+        let empty = Alignment::new(0, String::new(), String::new());
+        assert_eq!(empty.identity(), 0.0);
+    }
+
+    #[test]
+    fn test_to_bitvecs() {
+        let alignment = Alignment::new(0, "ACG--TAC-GTT".to_string(), "ACGTTTTCAG--".to_string());
+        let bits = alignment.to_bitvecs();
+        assert_eq!(bits.matches.len(), 12);
+        assert_eq!(bits.matches.count_ones(), 6);
+        assert_eq!(bits.query_gaps.count_ones(), 3);
+        assert_eq!(bits.text_gaps.count_ones(), 2);
+        // The mismatch is the only column without a bit set.
+        assert_eq!(
+            (0..12)
+                .filter(|&i| ![&bits.matches, &bits.query_gaps, &bits.text_gaps]
+                    .iter()
+                    .any(|v| v.get(i).unwrap()))
+                .collect::<Vec<_>>(),
+            vec![6]
+        );
+        assert_eq!(bits.matches.as_words(), &[0b10_1010_0111]);
+        assert_eq!(bits.matches.get(12), None);
+        // Columns span several words.
+        let long = Alignment::new(0, "A".repeat(100), "A".repeat(99) + "C");
+        let bits = long.to_bitvecs();
+        assert_eq!(bits.matches.as_words().len(), 2);
+        assert_eq!(bits.matches.count_ones(), 99);
+        assert_eq!(bits.matches.get(99), Some(false));
+        assert!(Alignment::new(0, String::new(), String::new())
+            .to_bitvecs()
+            .matches
+            .is_empty());
+    }
+
+    #[test]
+    fn test_to_cigar() {
+        let alignment = Alignment::new(0, "ACG--TAC-GTT".to_string(), "ACGTTTTCAG--".to_string());
+        assert_eq!(alignment.to_cigar(), "3M2D3M1D1M2I");
+        assert_eq!(alignment.to_cigar_extended(), "3=2D1=1X1=1D1=2I");
+        assert_eq!(
+            Alignment::new(0, String::new(), String::new()).to_cigar(),
+            ""
+        );
+        // A column of 2 gaps aligns nothing.
+        let double_gap = Alignment::new(0, "A-C".to_string(), "A-C".to_string());
+        assert_eq!(double_gap.to_cigar(), "2M");
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let split_gap = Alignment::new(0, "AA-C".to_string(), "A--C".to_string());
+        assert_eq!(split_gap.recompute_score(&pens), 8);
+        assert_eq!(
+            double_gap
+                .with_free_ends(&EndConfig::semi_global())
+                .to_cigar(),
+            "2M"
+        );
+    }
+
+    #[test]
+    fn test_rendered() {
+        let alignment = Alignment::new(0, "ACG--TAC-GTT".to_string(), "ACGTTTTCAG--".to_string());
+        assert_eq!(
+            alignment.rendered(false),
+            (
+                alignment.query_aligned.clone(),
+                alignment.text_aligned.clone()
+            )
+        );
+        assert_eq!(
+            alignment.rendered(true),
+            ("ACg--tAc-gTT".to_string(), "ACgTTtTcAg--".to_string())
+        );
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_score_lower_bound() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(score_lower_bound("GATTACA", "GATTACA", &pens), 0);
+        assert_eq!(score_lower_bound("GATTACA", "GATTACATT", &pens), 10);
+        assert_eq!(
+            score_lower_bound("AAAAAAAACCCCCCCC", "TTTTTTTTGGGGGGGG", &pens),
+            4
+        );
+
+        let mut rng = StdRng::seed_from_u64(608);
+        for _ in 0..300 {
+            let length = rng.gen_range(1..60);
+            let text = random_sequence(&mut rng, length, &DNA_BASES);
+            // Drops or substitutes about 1 char in 10.
+            let mut query = String::new();
+            for c in text.chars() {
+                match rng.gen_range(0..20) {
+                    0 => (),
+                    1 => query.push_str(&random_sequence(&mut rng, 1, &DNA_BASES)),
+                    _ => query.push(c),
+                }
+            }
+            if query.is_empty() {
+                query = random_sequence(&mut rng, 1, &DNA_BASES);
+            }
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let optimal = crate::wavefront_alignment::wavefront_align(&query, &text, &pens)
+                .unwrap()
+                .score;
+            assert!(score_lower_bound(&query, &text, &pens) <= optimal);
+        }
+    }
+
+    #[test]
+    fn test_kmer_distance() {
+        assert_eq!(kmer_distance("GATTACA", "GATTACA", 3), 0.0);
+        assert_eq!(kmer_distance("AAAAAA", "CCCCCC", 3), 1.0);
+        assert_eq!(kmer_distance("ACGTAC", "TACGT", 6), 1.0);
+        assert_eq!(kmer_distance("ACG", "ACG", 6), 0.0);
+        // AAC, ACG, CGT vs ACG, CGT, GTT: 2 shared k-mers out of 4.
+        assert_eq!(kmer_distance("AACGT", "ACGTT", 3), 0.5);
+    }
+}
+
+#[cfg(test)]
+mod tests_config {
+    use super::*;
+    use crate::reference::{
+        affine_gap_align_banded, align_ends, max_gap_align, strict_indels_align, wobble_align,
+    };
+    use crate::wavefront_alignment::{wavefront_align, wavefront_align_banded};
+
+    const PENS: Penalties = Penalties {
+        mismatch_pen: 4,
+        open_pen: 6,
+        extd_pen: 2,
+    };
+
+    #[test]
+    fn test_is_valid_for() {
+        assert_eq!(PENS.is_valid_for(100, 120), Ok(()));
+        assert!(matches!(
+            Penalties {
+                extd_pen: 0,
+                ..PENS
+            }
+            .is_valid_for(100, 120),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // The worst case of 2 chars is u32::MAX - 1, that of 3 chars doesn't fit.
+        let large = Penalties {
+            mismatch_pen: u32::MAX / 2,
+            open_pen: u32::MAX / 2,
+            extd_pen: 1,
+        };
+        assert_eq!(large.is_valid_for(2, 2), Ok(()));
+        assert!(matches!(
+            large.is_valid_for(3, 3),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+        // The aligners refine the bound with the chars: equal strings score 0.
+        assert_eq!(wavefront_align("GAT", "GAT", &large).unwrap().score, 0);
+        assert_eq!(
+            crate::reference::affine_gap_align("GAT", "GAT", &large)
+                .unwrap()
+                .score,
+            0
+        );
+        assert!(matches!(
+            wavefront_align("GAT", "CTA", &large),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+        assert!(matches!(
+            crate::reference::affine_gap_align("GAT", "CTA", &large),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_from_error_model() {
+        assert_eq!(
+            Penalties::from_error_model(0.02, 0.01, 2.5),
+            Penalties {
+                mismatch_pen: 17,
+                open_pen: 22,
+                extd_pen: 2,
+            }
+        );
+        // The more frequent an error, the cheaper it is.
+        let rare = Penalties::from_error_model(0.02, 0.001, 3.0);
+        let frequent = Penalties::from_error_model(0.02, 0.05, 3.0);
+        assert!(frequent.open_pen < rare.open_pen);
+        assert!(frequent.open_extd_pen() < rare.open_extd_pen());
+        assert_eq!(frequent.mismatch_pen, rare.mismatch_pen);
+        assert!(
+            Penalties::from_error_model(0.1, 0.01, 3.0).mismatch_pen
+                < Penalties::from_error_model(0.01, 0.01, 3.0).mismatch_pen
+        );
+        // Long indels are cheaper to extend.
+        let long = Penalties::from_error_model(0.02, 0.01, 20.0);
+        assert!(long.extd_pen <= frequent.extd_pen);
+        assert!(long.extd_pen >= 1);
+        assert!(std::panic::catch_unwind(|| Penalties::from_error_model(0.0, 0.01, 3.0)).is_err());
+        assert!(std::panic::catch_unwind(|| Penalties::from_error_model(0.02, 0.01, 1.0)).is_err());
+    }
+
+    #[test]
+    fn test_config_wavefront() {
+        let config = AlignmentConfig::new(PENS);
+        assert_eq!(
+            config.align("GATTACA", "GCATTAGA"),
+            wavefront_align("GATTACA", "GCATTAGA", &PENS)
+        );
+        assert_eq!(
+            config.clone().band(1).align("AAACGT", "CGTCCC"),
+            wavefront_align_banded("AAACGT", "CGTCCC", &PENS, 1)
+        );
+        assert!(matches!(
+            config
+                .clone()
+                .ends(EndConfig::semi_global())
+                .align("CAT", "GGCATGG"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+        assert!(matches!(
+            config.clone().max_gap_len(2).align("GAT", "GAT"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+        assert!(matches!(
+            config.wobble(1).align("GAU", "GAG"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+        let adaptive = AlignmentConfig::new(PENS).algorithm(AlignmentAlgorithm::WavefrontAdaptive);
+        assert_eq!(
+            adaptive.align("GATTACA", "GCATTAGA"),
+            crate::wavefront_alignment::wavefront_align_adaptive("GATTACA", "GCATTAGA", &PENS)
+        );
+        assert!(matches!(
+            adaptive.band(1).align("CAT", "CAT"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_swg() {
+        let config = AlignmentConfig::new(PENS).algorithm(AlignmentAlgorithm::SWG);
+        assert_eq!(
+            config.clone().band(1).align("AAACGT", "CGTCCC"),
+            affine_gap_align_banded("AAACGT", "CGTCCC", &PENS, 1)
+        );
+        assert_eq!(
+            config
+                .clone()
+                .ends(EndConfig::overlap())
+                .align("GATTACA", "TACAGG"),
+            align_ends("GATTACA", "TACAGG", &PENS, &EndConfig::overlap())
+        );
+        assert_eq!(
+            config.clone().wobble(1).align("GGAUCU", "GUAUCG"),
+            wobble_align("GGAUCU", "GUAUCG", &PENS, true, 1)
+        );
+        assert_eq!(
+            config
+                .clone()
+                .no_adjacent_indels(true)
+                .align("GATTACA", "GCTTACA"),
+            strict_indels_align("GATTACA", "GCTTACA", &PENS, true)
+        );
+        assert_eq!(
+            config.clone().max_gap_len(1).align("GATTACA", "GATTTTACA"),
+            max_gap_align("GATTACA", "GATTTTACA", &PENS, 1)
+        );
+        assert!(matches!(
+            config.clone().max_gap_len(1).wobble(1).align("GAU", "GAG"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+        assert!(matches!(
+            config
+                .band(4)
+                .ends(EndConfig::semi_global())
+                .align("CAT", "GGCATGG"),
+            Err(AlignmentError::Unsupported(_))
+        ));
+
+        // Combined options: free end gaps with wobble pairs.
+        let alignment = AlignmentConfig::new(PENS)
+            .algorithm(AlignmentAlgorithm::SWG)
+            .ends(EndConfig::semi_global())
+            .wobble(1)
+            .align("GAU", "CCGAGCC")
+            .unwrap();
+        assert_eq!(alignment.score, 1);
+    }
+}
+
 #[cfg(test)]
 mod tests_wfgrid {
     use super::*;
@@ -212,7 +3209,7 @@ mod tests_wfgrid {
     #[test]
     fn test_add_layer() {
         let mut grid: WavefrontGrid = new_wavefront_grid();
-        grid.add_layer(-3, 3);
+        grid.add_layer(-3, 3).unwrap();
         assert_eq!(grid.diags[0], (0, 0));
         assert_eq!(grid.diags[1], (-3, 3));
         assert_eq!(grid.offsets[0], 0);
@@ -222,4 +3219,21 @@ mod tests_wfgrid {
         assert_eq!(grid.inserts.len(), 8);
         assert_eq!(grid.deletes.len(), 8);
     }
+
+    #[test]
+    fn test_add_layer_overflow() {
+        let mut grid: WavefrontGrid = new_wavefront_grid();
+        let unchanged = grid.clone();
+        for (lo, hi) in [(i64::MIN, i64::MAX), (i64::MIN, 0), (3, 1)] {
+            assert!(matches!(
+                grid.add_layer(lo, hi),
+                Err(AlignmentError::WavefrontTooWide(_))
+            ));
+            assert_eq!(grid, unchanged);
+        }
+        // An empty layer.
+        grid.add_layer(1, 0).unwrap();
+        assert_eq!(grid.offsets, vec![0, 1, 1]);
+        assert_eq!(grid.matches.len(), 1);
+    }
 }
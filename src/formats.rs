@@ -0,0 +1,129 @@
+//! Text formats of the batch aligner: FASTA records as input, PAF lines as output.
+//!
+//! PAF lines have the 12 mandatory columns, the text being the target, followed by the CIGAR
+//! of the alignment (cg:Z) and its penalty score (sc:i, a custom tag since PAF scores are
+//! similarities).
+
+use crate::alignment_lib::*;
+use std::io::{BufRead, Lines};
+
+/// A record of a FASTA file: its name is the first word of its header line, and its sequence
+/// the concatenation of the lines that follow, trimmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FastaRecord {
+    pub name: String,
+    pub seq: String,
+}
+
+/// Reads the records of a FASTA file one by one, without loading the whole file.
+/// Blank lines are skipped. A sequence line before the first header yields an
+/// AlignmentError::ReadFailure, as do the I/O errors of the reader.
+pub fn read_fasta<R: BufRead>(reader: R) -> FastaReader<R> {
+    FastaReader {
+        lines: reader.lines(),
+        header: None,
+    }
+}
+
+/// Iterator returned by read_fasta.
+pub struct FastaReader<R> {
+    lines: Lines<R>,
+    /// Header of the next record, read while reading the sequence of the previous one.
+    header: Option<String>,
+}
+
+impl<R: BufRead> Iterator for FastaReader<R> {
+    type Item = Result<FastaRecord, AlignmentError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => loop {
+                match self.lines.next()? {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) if line.starts_with('>') => break line,
+                    Ok(line) => {
+                        return Some(Err(AlignmentError::ReadFailure(format!(
+                            "The sequence line {} comes before any FASTA header.",
+                            line.trim()
+                        ))))
+                    }
+                    Err(e) => return Some(Err(AlignmentError::ReadFailure(e.to_string()))),
+                }
+            },
+        };
+        let mut seq = String::new();
+        for line in self.lines.by_ref() {
+            match line {
+                Ok(line) if line.starts_with('>') => {
+                    self.header = Some(line);
+                    break;
+                }
+                Ok(line) => seq.push_str(line.trim()),
+                Err(e) => return Some(Err(AlignmentError::ReadFailure(e.to_string()))),
+            }
+        }
+        let name = header[1..].split_whitespace().next().unwrap_or("");
+        Some(Ok(FastaRecord {
+            name: name.to_string(),
+            seq,
+        }))
+    }
+}
+
+/// Formats alignment as a PAF line, without its newline. The query and text are named
+/// query_name and text_name, and the coordinates are those of the alignment.
+pub fn to_paf(alignment: &Alignment, query_name: &str, text_name: &str) -> String {
+    let matches = alignment
+        .columns()
+        .filter(|(q, t)| q.is_some() && q == t)
+        .count();
+    format!(
+        "{}\t{}\t{}\t{}\t+\t{}\t{}\t{}\t{}\t{}\t{}\t255\tcg:Z:{}\tsc:i:{}",
+        query_name,
+        alignment.query_len(),
+        alignment.query_start,
+        alignment.query_end,
+        text_name,
+        alignment.text_len(),
+        alignment.text_start,
+        alignment.text_end,
+        matches,
+        alignment.aligned_len(),
+        alignment.to_cigar(),
+        alignment.score
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_fasta() {
+        let fasta = ">first sequence\nGATT\nACA\n\n>second\nCAT\n>empty\n";
+        let records: Vec<FastaRecord> = read_fasta(fasta.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["first", "second", "empty"]);
+        assert_eq!(records[0].seq, "GATTACA");
+        assert_eq!(records[1].seq, "CAT");
+        assert_eq!(records[2].seq, "");
+
+        assert_eq!(read_fasta("".as_bytes()).count(), 0);
+        assert!(matches!(
+            read_fasta("GATTACA\n>first\nCAT\n".as_bytes()).next(),
+            Some(Err(AlignmentError::ReadFailure(_)))
+        ));
+    }
+
+    #[test]
+    fn test_to_paf() {
+        let alignment = Alignment::new(8, "GAT-ACA".to_string(), "GATTACA".to_string());
+        assert_eq!(
+            to_paf(&alignment, "query", "text"),
+            "query\t6\t0\t6\t+\ttext\t7\t0\t7\t6\t7\t255\tcg:Z:3M1D3M\tsc:i:8"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Random sequence generation, used by the validation binary and for score statistics.
+
+use crate::alignment_lib::*;
+use crate::wavefront_alignment::wavefront_align;
+use alloc::string::String;
+use alloc::vec::Vec;
+use rand::distributions::{Alphanumeric, Distribution, Standard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The DNA bases, drawn uniformly by null_score_distribution.
+pub const DNA_BASES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+/// Seed of the rng of null_score_distribution, so that it returns the same samples every time.
+const NULL_DISTRIBUTION_SEED: u64 = 0;
+
+enum MutationType {
+    Insertion,
+    Deletion,
+    Substitution,
+}
+
+// Allows to randomly generate a MutationType.
+impl Distribution<MutationType> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> MutationType {
+        match rng.gen_range(0..=2) {
+            0 => MutationType::Insertion,
+            1 => MutationType::Deletion,
+            _ => MutationType::Substitution,
+        }
+    }
+}
+
+/// Returns a random alphanumeric string of length min_length..max_length.
+pub fn random_string<R: Rng + ?Sized>(rng: &mut R, min_length: usize, max_length: usize) -> String {
+    let length = rng.gen_range(min_length..max_length);
+
+    rng.sample_iter(Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Returns a string of length chars drawn uniformly from alphabet.
+pub fn random_sequence<R: Rng + ?Sized>(rng: &mut R, length: usize, alphabet: &[char]) -> String {
+    (0..length)
+        .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+        .collect()
+}
+
+fn gen_new_char<R: Rng + ?Sized>(rng: &mut R) -> char {
+    char::from(rng.sample(Alphanumeric))
+}
+
+fn gen_new_char_different<R: Rng + ?Sized>(rng: &mut R, a: char) -> char {
+    loop {
+        let c = gen_new_char(rng);
+        if c != a {
+            return c;
+        }
+    }
+}
+
+/// Applies random insertions, deletions and substitutions of alphanumeric chars to text. The
+/// number of mutations is a percentage of the length of text, drawn in min_error..max_error.
+pub fn mutate<R: Rng + ?Sized>(rng: &mut R, text: &str, min_error: i32, max_error: i32) -> String {
+    let mut mutated: Vec<char> = text.chars().collect();
+    let error_rate: i32 = rng.gen_range(min_error..max_error);
+    let final_err_count: i32 = (error_rate * (mutated.len() as i32)) / 100;
+
+    for _ in 0..final_err_count {
+        let position: usize = rng.gen_range(0..mutated.len());
+        let mutation: MutationType = rng.gen();
+        if let MutationType::Insertion = mutation {
+            mutated.insert(position, gen_new_char(rng));
+        }
+        if let MutationType::Deletion = mutation {
+            mutated.remove(position);
+        }
+        if let MutationType::Substitution = mutation {
+            mutated[position] = gen_new_char_different(rng, mutated[position]);
+        }
+    }
+    mutated.into_iter().collect()
+}
+
+/// Aligns samples pairs of independent random DNA sequences of lengths query_len and text_len
+/// with wavefront_align, and returns their scores: the distribution of the score under the
+/// null hypothesis of unrelated sequences. The p-value of an observed score is the fraction of
+/// the scores <= to it. The rng is seeded, so the same samples are returned on every call.
+/// Returns AlignmentError::ZeroLength if a length is 0.
+pub fn null_score_distribution(
+    query_len: usize,
+    text_len: usize,
+    pens: &Penalties,
+    samples: usize,
+) -> Result<Vec<u32>, AlignmentError> {
+    let mut rng = StdRng::seed_from_u64(NULL_DISTRIBUTION_SEED);
+    // The score doesn't depend on which string is the query, but WFA needs the shortest one.
+    let (short_len, long_len) = (query_len.min(text_len), query_len.max(text_len));
+    (0..samples)
+        .map(|_| {
+            let short = random_sequence(&mut rng, short_len, &DNA_BASES);
+            let long = random_sequence(&mut rng, long_len, &DNA_BASES);
+            Ok(wavefront_align(&short, &long, pens)?.score)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_null_score_distribution() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let scores = null_score_distribution(100, 100, &pens, 100).unwrap();
+        assert_eq!(scores.len(), 100);
+        assert_eq!(
+            null_score_distribution(100, 100, &pens, 100).unwrap(),
+            scores
+        );
+        // Unrelated sequences are far from identical, but aligning them beats mismatching every
+        // char, which costs 4 * 75 on average.
+        let mean = scores.iter().sum::<u32>() as f64 / scores.len() as f64;
+        assert!(mean > 200.0 && mean < 300.0, "mean score {}", mean);
+        // A related pair scores far below the distribution.
+        let text = random_sequence(&mut StdRng::seed_from_u64(667), 100, &DNA_BASES);
+        let mut query: Vec<char> = text.chars().collect();
+        query[50] = if query[50] == 'A' { 'C' } else { 'A' };
+        let query: String = query.into_iter().collect();
+        let observed = wavefront_align(&query, &text, &pens).unwrap().score;
+        assert!(scores.iter().all(|score| *score > observed));
+
+        assert_eq!(null_score_distribution(5, 10, &pens, 0), Ok(vec![]));
+        assert!(matches!(
+            null_score_distribution(0, 10, &pens, 3),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+}
@@ -1,3 +1,27 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+//! The alignment kernels only need alloc: without the std feature, the crate is no_std.
+
+extern crate alloc;
+
 pub mod alignment_lib;
+#[cfg(feature = "std")]
+pub mod formats;
+#[cfg(feature = "rand")]
+pub mod generation;
 pub mod reference;
+#[cfg(feature = "std")]
+pub mod storage;
+pub mod translation;
 pub mod wavefront_alignment;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// Re-exports the types and functions needed for most alignments.
+pub mod prelude {
+    pub use crate::alignment_lib::{
+        align, AlignResult, Alignment, AlignmentAlgorithm, AlignmentConfig, AlignmentError,
+        EndConfig, Penalties,
+    };
+    pub use crate::reference::affine_gap_align;
+    pub use crate::wavefront_alignment::wavefront_align;
+}
@@ -1,6 +1,8 @@
 use clap::Parser;
-use lib::{alignment_lib::AlignmentAlgorithm, reference::affine_gap_align, wavefront_alignment};
-use std::io::{stdin, BufRead};
+use lib::alignment_lib::{align_stream_with, AlignmentFunction};
+use lib::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::stdin;
 use std::time::Instant;
 
 /// Struct used for parsing CLI args with clap.
@@ -15,65 +17,229 @@ struct MainArgs {
     /// Alignment algorithm that will be used. Possible values: Wavefront, SWG.
     algorithm: AlignmentAlgorithm,
 
-    #[clap(short, long)]
+    #[clap(short, long, allow_hyphen_values = true, parse(try_from_str = parse_penalty))]
     /// Penalty for mismatching 2 chars.
     mismatch_pen: u32,
 
-    #[clap(short, long)]
+    #[clap(short, long, allow_hyphen_values = true, parse(try_from_str = parse_penalty))]
     /// Penalty for opening a gap.
     open_pen: u32,
 
-    #[clap(short, long)]
+    #[clap(short, long, allow_hyphen_values = true, parse(try_from_str = parse_penalty))]
     /// Penalty for extending a gap by 1. Is also applied once when the gap is opened.
     extd_pen: u32,
 
     #[clap(short, long)]
     /// Whether to print how long it took to align.
     bench: bool,
+
+    #[clap(long)]
+    /// Whether to print the score divided by the length of the alignment after the score.
+    normalize: bool,
+
+    #[clap(long)]
+    /// Whether to print each alignment as a single line JSON object, instead of 3 lines.
+    json: bool,
+
+    #[clap(long)]
+    /// Wraps the alignment in blocks of lines of at most width chars, with the positions in
+    /// the margins and a line marking the matches.
+    width: Option<usize>,
+
+    #[clap(long)]
+    /// Prints the wrapped alignment with a column ruler above each block. Without --width,
+    /// the lines are as wide as the terminal.
+    ruler: bool,
+}
+
+/// Width of the wrapped alignments when --width isn't set: the COLUMNS environment variable,
+/// or 80 if it isn't set.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Machine-readable summary of an alignment, printed with --json.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct AlignmentSummary {
+    score: u32,
+    /// Only set with --normalize.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normalized_score: Option<f64>,
+    query_aligned: String,
+    text_aligned: String,
+    cigar: String,
+}
+
+impl AlignmentSummary {
+    fn new(alignment: &Alignment, normalize: bool) -> AlignmentSummary {
+        AlignmentSummary {
+            score: alignment.score,
+            normalized_score: normalize.then(|| alignment.normalized_score()),
+            query_aligned: alignment.query_aligned.clone(),
+            text_aligned: alignment.text_aligned.clone(),
+            cigar: alignment.to_cigar(),
+        }
+    }
+}
+
+/// Formats an alignment the way it's printed, newline included.
+fn format_alignment(alignment: &Alignment, args: &MainArgs) -> String {
+    if args.json {
+        let summary = AlignmentSummary::new(alignment, args.normalize);
+        // Serializing a struct of strings and numbers can't fail.
+        return serde_json::to_string(&summary).unwrap() + "\n";
+    }
+    let score = match args.normalize {
+        true => format!("{} {}\n", alignment.score, alignment.normalized_score()),
+        false => format!("{}\n", alignment.score),
+    };
+    if args.width.is_some() || args.ruler {
+        let width = args.width.unwrap_or_else(terminal_width);
+        return score + &alignment.to_blocks(width, args.ruler);
+    }
+    format!(
+        "{}{}\n{}\n",
+        score, alignment.query_aligned, alignment.text_aligned
+    )
+}
+
+/// Parses a penalty, with a clear error for negative values, which clap would otherwise
+/// report as an unexpected flag or an invalid digit.
+fn parse_penalty(value: &str) -> Result<u32, String> {
+    match value.parse::<i64>() {
+        Ok(penalty) if penalty < 0 => Err(format!("penalties can't be negative, got {}", penalty)),
+        _ => value.parse::<u32>().map_err(|e| e.to_string()),
+    }
 }
 
 fn main() {
     // parse CLI args
     let args = MainArgs::parse();
 
-    // read alignment strings from stdin
-    let mut query: String = String::new();
-    let mut text: String = String::new();
-    stdin().lock().read_line(&mut query).unwrap();
-    stdin().lock().read_line(&mut text).unwrap();
-    query = query.trim().to_string();
-    text = text.trim().to_string();
-
-    let pens = lib::alignment_lib::Penalties {
+    let pens = Penalties {
         mismatch_pen: args.mismatch_pen,
         open_pen: args.open_pen,
         extd_pen: args.extd_pen,
     };
 
-    let before = if args.bench {
-        Some(Instant::now())
-    } else {
-        None
+    let align: AlignmentFunction = match args.algorithm.function() {
+        Some(align) => align,
+        None => panic!("{} not yet implemented.", args.algorithm),
     };
 
-    let alignment = match args.algorithm {
-        AlignmentAlgorithm::Wavefront => wavefront_alignment::wavefront_align(&query, &text, &pens),
-        AlignmentAlgorithm::WavefrontAdaptive => {
-            panic!("WFA-adaptive not yet implemented.");
-        }
-        AlignmentAlgorithm::SWG => affine_gap_align(&query, &text, &pens),
-    };
+    // read alignment strings from stdin, 2 lines at a time
+    let mut alignments = align_stream_with(stdin().lock(), &pens, align);
+    loop {
+        let before = if args.bench {
+            Some(Instant::now())
+        } else {
+            None
+        };
 
-    if let Some(t) = before {
-        let elapsed = t.elapsed();
-        println!("Aligned in {:.2?}", elapsed);
-    };
+        let alignment = match alignments.next() {
+            Some(alignment) => alignment,
+            None => break,
+        };
 
-    match alignment {
-        Ok(alignment) => print!(
-            "{}\n{}\n{}\n",
-            alignment.score, alignment.query_aligned, alignment.text_aligned
-        ),
-        Err(e) => panic!("Alignment returned an error: {:?}", e),
-    };
+        if let Some(t) = before {
+            let elapsed = t.elapsed();
+            println!("Aligned in {:.2?}", elapsed);
+        };
+
+        match alignment {
+            Ok(alignment) => print!("{}", format_alignment(&alignment, &args)),
+            Err(e) => panic!("Alignment returned an error: {:?}", e),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negative_penalties_rejected() {
+        let parse = |args: &[&str]| MainArgs::try_parse_from(["rust_wfa"].iter().chain(args));
+        let args = parse(&["-m", "4", "-o", "6", "-e", "2"]).unwrap();
+        assert_eq!((args.mismatch_pen, args.open_pen, args.extd_pen), (4, 6, 2));
+
+        let error = parse(&["-m", "-1", "-o", "6", "-e", "2"]).unwrap_err();
+        assert!(error.to_string().contains("penalties can't be negative"));
+        assert!(parse(&["-m", "4", "-o", "6", "--extd-pen=-2"]).is_err());
+        assert!(parse(&["-m", "4", "-o", "six", "-e", "2"]).is_err());
+    }
+
+    #[test]
+    fn json_output() {
+        let parse = |args: &[&str]| MainArgs::try_parse_from(["rust_wfa"].iter().chain(args));
+        let alignment = Alignment::new(8, "GA-AT".to_string(), "GAAAT".to_string());
+
+        let human = parse(&["-m", "4", "-o", "6", "-e", "2"]).unwrap();
+        assert_eq!(format_alignment(&alignment, &human), "8\nGA-AT\nGAAAT\n");
+
+        let json = parse(&["-m", "4", "-o", "6", "-e", "2", "--json"]).unwrap();
+        let output = format_alignment(&alignment, &json);
+        assert_eq!(output.lines().count(), 1);
+        let summary: AlignmentSummary = serde_json::from_str(&output).unwrap();
+        assert_eq!(
+            summary,
+            AlignmentSummary {
+                score: 8,
+                normalized_score: None,
+                query_aligned: "GA-AT".to_string(),
+                text_aligned: "GAAAT".to_string(),
+                cigar: alignment.to_cigar(),
+            }
+        );
+        assert!(!output.contains("normalized_score"));
+
+        let normalized = parse(&["-m", "4", "-o", "6", "-e", "2", "--json", "--normalize"]);
+        let output = format_alignment(&alignment, &normalized.unwrap());
+        let summary: AlignmentSummary = serde_json::from_str(&output).unwrap();
+        assert_eq!(summary.normalized_score, Some(alignment.normalized_score()));
+    }
+
+    #[test]
+    fn wrapped_output() {
+        let parse = |args: &[&str]| MainArgs::try_parse_from(["rust_wfa"].iter().chain(args));
+        let query = "GATTACA".repeat(6);
+        let text = query.replace("TAC", "TC");
+        let alignment = wavefront_align(
+            &text,
+            &query,
+            &Penalties {
+                mismatch_pen: 4,
+                open_pen: 6,
+                extd_pen: 2,
+            },
+        )
+        .unwrap();
+
+        let args = parse(&["-m", "4", "-o", "6", "-e", "2", "--width", "30"]).unwrap();
+        let output = format_alignment(&alignment, &args);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], alignment.score.to_string());
+        // 42 columns, in blocks of 30 - 12 chars of margins.
+        assert_eq!(lines.len(), 1 + 3 * 3 + 2);
+        assert!(lines.iter().all(|line| line.len() <= 30));
+        assert_eq!(lines[1], "Query  1 GATT-CAGATT-CAGATT 16");
+        assert_eq!(lines[3], "Text   1 GATTACAGATTACAGATT 18");
+        assert_eq!(lines[5], "Query 17 -CAGATT-CAGATT-CAG 31");
+        assert_eq!(lines[11], "Text  37 ATTACA 42");
+
+        let args = parse(&["-m", "4", "-o", "6", "-e", "2", "--width", "30", "--ruler"]).unwrap();
+        let lines: Vec<String> = format_alignment(&alignment, &args)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(lines[1], "             .   10    .");
+        assert_eq!(lines[2], "Query  1 GATT-CAGATT-CAGATT 16");
+
+        // Without the flags, the output is unchanged.
+        let args = parse(&["-m", "4", "-o", "6", "-e", "2"]).unwrap();
+        assert_eq!(format_alignment(&alignment, &args).lines().count(), 3);
+    }
 }
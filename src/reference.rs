@@ -1,7 +1,17 @@
 //! Implements the reference gap-affine (SWG) alignment algorithm.
 
 use crate::alignment_lib::*;
-use std::cmp::min;
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::cmp::{min, Reverse};
+use core::iter;
+use core::marker::PhantomData;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
 
 #[derive(Debug)]
 struct AlignMat {
@@ -10,356 +20,4188 @@ struct AlignMat {
     deletes: Vec<Vec<(Option<u32>, Option<AlignmentLayer>)>>,
 }
 
-/// Performs the SWG alignment of two &str.
-pub fn affine_gap_align(a: &str, b: &str, pens: &Penalties) -> Result<Alignment, AlignmentError> {
-    let align_mat = affine_gap_mat(a, b, pens);
-    trace_back(&align_mat, a, b)
+/// The scores of the SWG alignment matrices, as returned by affine_gap_matrices.
+/// Each matrix is indexed by [i][j], where i is the number of chars of a and j the number of
+/// chars of b consumed. A cell is None if it can't be reached.
+/// matches holds the best score of each cell, whatever its last operation is, and inserts and
+/// deletes the best score of the paths ending with a gap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffineGapMatrices {
+    pub matches: Vec<Vec<Option<i32>>>,
+    pub inserts: Vec<Vec<Option<i32>>>,
+    pub deletes: Vec<Vec<Option<i32>>>,
 }
 
-fn affine_gap_mat(a: &str, b: &str, pens: &Penalties) -> AlignMat {
-    let mut result = new_mat(a, b, pens);
+/// Returns AlignmentError::ScoreOverflow if a score doesn't fit in an i32: the scores off the
+/// optimal path can be far above the optimal one.
+impl TryFrom<&AlignMat> for AffineGapMatrices {
+    type Error = AlignmentError;
+
+    fn try_from(mat: &AlignMat) -> Result<AffineGapMatrices, AlignmentError> {
+        let to_i32 = |score: u32| {
+            i32::try_from(score).map_err(|_| {
+                AlignmentError::ScoreOverflow(format!("The score {} doesn't fit in an i32.", score))
+            })
+        };
+        let scores = |layer: &Vec<Vec<(Option<u32>, Option<AlignmentLayer>)>>| {
+            layer
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|cell| cell.0.map(to_i32).transpose())
+                        .collect::<Result<Vec<Option<i32>>, AlignmentError>>()
+                })
+                .collect::<Result<Vec<Vec<Option<i32>>>, AlignmentError>>()
+        };
+        Ok(AffineGapMatrices {
+            matches: scores(&mat.matches)?,
+            inserts: scores(&mat.inserts)?,
+            deletes: scores(&mat.deletes)?,
+        })
+    }
+}
+
+/// Performs the SWG alignment of two &str. Equal strings are returned as the all-match
+/// alignment without filling the matrices.
+pub fn affine_gap_align(a: &str, b: &str, pens: &Penalties) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_valid_for(
+        &a.chars().collect::<Vec<char>>(),
+        &b.chars().collect::<Vec<char>>(),
+    )?;
+    if a == b {
+        return Ok(Alignment::new(0, a.to_string(), b.to_string()));
+    }
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions::default(),
+    );
+    trace_back(&align_mat, a, b, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of two &str, reporting the gaps relative to reference_is: the
+/// reference string is the text of the alignment, the other one the query. Either string can
+/// be the longest. Both settings give the same alignment, with the roles of the strings and of
+/// insertions and deletions swapped.
+pub fn affine_gap_align_with_reference(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    reference_is: Reference,
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_align_with_reference had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_valid_for(
+        &a.chars().collect::<Vec<char>>(),
+        &b.chars().collect::<Vec<char>>(),
+    )?;
+    // The borders of the matrices are the same for both strings: the gaps along a and b are
+    // only given their roles by the traceback.
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions::default(),
+    );
+    trace_back_against(&align_mat, a, b, &EndConfig::global(), false, reference_is)
+}
+
+/// Returns the scores of the matrices filled by affine_gap_align, to inspect the DP.
+/// Returns AlignmentError::ScoreOverflow if a score of the matrices doesn't fit in an i32.
+pub fn affine_gap_matrices(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+) -> Result<AffineGapMatrices, AlignmentError> {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_matrices had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_valid_for(
+        &a.chars().collect::<Vec<char>>(),
+        &b.chars().collect::<Vec<char>>(),
+    )?;
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions::default(),
+    );
+    AffineGapMatrices::try_from(&align_mat)
+}
+
+/// Performs the SWG alignment of two &str, calling on_row(i, score) once each row i of the
+/// matrices is filled, from 1 to the length of a. score is the lowest score of the row: that of
+/// the best alignment of the first i chars of a with a prefix of b. It only gives an idea of
+/// the progress, as the final alignment doesn't necessarily go through it.
+pub fn affine_gap_align_with_progress(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    mut on_row: impl FnMut(usize, u32),
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_align_with_progress had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions {
+            on_row: Some(&mut on_row),
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, a, b, &EndConfig::global(), false)
+}
+
+/// Performs a banded SWG alignment of two &str: only the diagonals at a distance <= band of
+/// the diagonals between the start and the end of the alignment are computed.
+/// The band is the same as wavefront_align_banded's, so both give the same score.
+pub fn affine_gap_align_banded(a: &str, b: &str, pens: &Penalties, band: usize) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_align_banded had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions {
+            band: Some(band_diagonals(a, b, band)),
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, a, b, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of two &str in linear space, using the divide and conquer
+/// algorithm of Hirschberg extended to affine gaps by Myers and Miller: only O(min(n, m))
+/// scores are stored, at the cost of computing each cell about twice.
+/// The score is the same as affine_gap_align's, but the alignment can differ between
+/// alignments of equal score.
+pub fn affine_gap_align_linear_space(a: &str, b: &str, pens: &Penalties) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to affine_gap_align_linear_space had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
     let chars_a: Vec<char> = a.chars().collect();
     let chars_b: Vec<char> = b.chars().collect();
-    for i in 1..chars_a.len() + 1 {
-        for j in 1..chars_b.len() + 1 {
-            result.inserts[i][j] = match (result.inserts[i - 1][j].0, result.matches[i - 1][j].0) {
-                (Some(a), Some(b)) => {
-                    if min(a + pens.extd_pen, b + pens.extd_pen + pens.open_pen)
-                        == a + pens.extd_pen
-                    {
-                        (Some(a + pens.extd_pen), Some(AlignmentLayer::Inserts))
-                    } else {
-                        (
-                            Some(b + pens.extd_pen + pens.open_pen),
-                            Some(AlignmentLayer::Matches),
-                        )
-                    }
+    // The rows of scores are as long as the second string: it must be the shortest.
+    if chars_b.len() > chars_a.len() {
+        return affine_gap_align_linear_space(b, a, pens).map(|alignment| alignment.swap());
+    }
+    let mut result = Alignment::new(0, String::new(), String::new());
+    let open_pen = pens.open_pen as u64;
+    let score = linear_space_diff(&chars_a, &chars_b, pens, open_pen, open_pen, &mut result);
+    let score = u32::try_from(score).map_err(|_| {
+        AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        ))
+    })?;
+    Ok(Alignment::new(
+        score,
+        result.query_aligned,
+        result.text_aligned,
+    ))
+}
+
+/// Cost of a gap of length len, in the linear space alignment.
+fn linear_space_gap(pens: &Penalties, len: usize) -> u64 {
+    match len {
+        0 => 0,
+        len => pens.open_pen as u64 + pens.extd_pen as u64 * len as u64,
+    }
+}
+
+/// Aligns a and b, pushing the columns of the alignment to result, and returns its score.
+/// start_open and end_open are the costs of opening a gap of b (deleting chars of a) at the
+/// start and at the end: 0 if it continues a gap of the enclosing alignment, else open_pen.
+fn linear_space_diff(
+    a: &[char],
+    b: &[char],
+    pens: &Penalties,
+    start_open: u64,
+    end_open: u64,
+    result: &mut Alignment,
+) -> u64 {
+    let push = |result: &mut Alignment, q: Option<char>, t: Option<char>| {
+        result.query_aligned.push(q.unwrap_or(GAP_CHAR));
+        result.text_aligned.push(t.unwrap_or(GAP_CHAR));
+    };
+    let sub_cost = |x: char, y: char| if x == y { 0 } else { pens.mismatch_pen as u64 };
+    let extd_pen = pens.extd_pen as u64;
+
+    if b.is_empty() {
+        for &x in a {
+            push(result, Some(x), None);
+        }
+        return match a.len() {
+            0 => 0,
+            len => start_open.min(end_open) + extd_pen * len as u64,
+        };
+    }
+    if a.is_empty() {
+        for &y in b {
+            push(result, None, Some(y));
+        }
+        return linear_space_gap(pens, b.len());
+    }
+    if a.len() == 1 {
+        // Either a[0] is aligned with a char of b, or it is deleted and b is inserted.
+        let mut best_cost = start_open.min(end_open) + extd_pen + linear_space_gap(pens, b.len());
+        let mut best_j = None;
+        for (j, &y) in b.iter().enumerate() {
+            let cost = linear_space_gap(pens, j)
+                + sub_cost(a[0], y)
+                + linear_space_gap(pens, b.len() - j - 1);
+            if cost < best_cost {
+                best_cost = cost;
+                best_j = Some(j);
+            }
+        }
+        match best_j {
+            Some(best_j) => {
+                for (j, &y) in b.iter().enumerate() {
+                    push(result, (j == best_j).then_some(a[0]), Some(y));
                 }
-                (Some(a), None) => (Some(a + pens.extd_pen), Some(AlignmentLayer::Inserts)),
-                (None, Some(a)) => (
-                    Some(a + pens.extd_pen + pens.open_pen),
-                    Some(AlignmentLayer::Matches),
-                ),
-                (None, None) => panic!("(None, None), results.inserts"),
-            };
+            }
+            None if start_open <= end_open => {
+                push(result, Some(a[0]), None);
+                for &y in b {
+                    push(result, None, Some(y));
+                }
+            }
+            None => {
+                for &y in b {
+                    push(result, None, Some(y));
+                }
+                push(result, Some(a[0]), None);
+            }
+        }
+        return best_cost;
+    }
 
-            result.deletes[i][j] = match (result.deletes[i][j - 1].0, result.matches[i][j - 1].0) {
-                (Some(a), Some(b)) => {
-                    if min(a + pens.extd_pen, b + pens.extd_pen + pens.open_pen)
-                        == a + pens.extd_pen
-                    {
-                        (Some(a + pens.extd_pen), Some(AlignmentLayer::Deletes))
-                    } else {
-                        (
-                            Some(b + pens.extd_pen + pens.open_pen),
-                            Some(AlignmentLayer::Matches),
-                        )
-                    }
+    let mid = a.len() / 2;
+    // Forward scores of the row mid: best score of the alignments of a[..mid] and b[..j], and
+    // best score of those ending with a deletion.
+    let (forward, forward_del) =
+        linear_space_last_row(a[..mid].iter(), b.iter(), pens, start_open, &sub_cost);
+    // Same, for the alignments of a[mid..] and b[j..], computed backwards.
+    let (mut backward, mut backward_del) = linear_space_last_row(
+        a[mid..].iter().rev(),
+        b.iter().rev(),
+        pens,
+        end_open,
+        &sub_cost,
+    );
+    backward.reverse();
+    backward_del.reverse();
+
+    // The optimal alignment either goes through (mid, j), or has a deletion of a[mid - 1] and
+    // a[mid] going through the row mid: that gap was opened twice.
+    let mut best = (forward[0] + backward[0], 0, false);
+    for j in 0..=b.len() {
+        let through = forward[j] + backward[j];
+        if through < best.0 {
+            best = (through, j, false);
+        }
+        let deleting = forward_del[j] + backward_del[j] - pens.open_pen as u64;
+        if deleting < best.0 {
+            best = (deleting, j, true);
+        }
+    }
+    let (score, j, deleting) = best;
+    let open_pen = pens.open_pen as u64;
+    if deleting {
+        linear_space_diff(&a[..mid - 1], &b[..j], pens, start_open, 0, result);
+        push(result, Some(a[mid - 1]), None);
+        push(result, Some(a[mid]), None);
+        linear_space_diff(&a[mid + 1..], &b[j..], pens, 0, end_open, result);
+    } else {
+        linear_space_diff(&a[..mid], &b[..j], pens, start_open, open_pen, result);
+        linear_space_diff(&a[mid..], &b[j..], pens, open_pen, end_open, result);
+    }
+    score
+}
+
+/// Computes the last row of the SWG matrices of the alignment of a and b, using a single row
+/// of each. Returns the best scores of each cell of the row, and the best scores of the paths
+/// ending with a deletion. start_open is the cost of opening a deletion in the first column.
+fn linear_space_last_row<'a>(
+    a: impl Iterator<Item = &'a char>,
+    b: impl Iterator<Item = &'a char> + Clone,
+    pens: &Penalties,
+    start_open: u64,
+    sub_cost: &dyn Fn(char, char) -> u64,
+) -> (Vec<u64>, Vec<u64>) {
+    let open_pen = pens.open_pen as u64;
+    let extd_pen = pens.extd_pen as u64;
+    let mut best: Vec<u64> = vec![0];
+    let mut gap = open_pen;
+    for _ in b.clone() {
+        gap += extd_pen;
+        best.push(gap);
+    }
+    // A deletion from the first row must be opened.
+    let mut deletes: Vec<u64> = best.iter().map(|score| score + open_pen).collect();
+
+    let mut first_column = start_open;
+    for &x in a {
+        let mut diagonal = best[0];
+        first_column += extd_pen;
+        best[0] = first_column;
+        let mut current = first_column;
+        let mut insert = first_column + open_pen;
+        for (j, &y) in b.clone().enumerate() {
+            insert = insert.min(current + open_pen) + extd_pen;
+            let delete = deletes[j + 1].min(best[j + 1] + open_pen) + extd_pen;
+            current = (diagonal + sub_cost(x, y)).min(delete).min(insert);
+            diagonal = best[j + 1];
+            best[j + 1] = current;
+            deletes[j + 1] = delete;
+        }
+    }
+    deletes[0] = best[0];
+    (best, deletes)
+}
+
+/// Performs the SWG alignment of two &str, where the gaps at the ends selected by ends are free.
+pub fn align_ends(a: &str, b: &str, pens: &Penalties, ends: &EndConfig) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_ends had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions {
+            ends: *ends,
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, a, b, ends, false)
+}
+
+/// Aligns the successive windows of window chars of query to text, in semi-global mode: each
+/// window is aligned entirely to a part of text. The windows start every step chars, and only
+/// the windows that fit entirely in query are aligned.
+/// Returns the start of each window in query, in chars, with its alignment.
+/// Panics if window or step is 0.
+pub fn align_windows(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    window: usize,
+    step: usize,
+) -> Vec<(usize, AlignResult)> {
+    assert!(window > 0 && step > 0, "The window and step must be > 0.");
+    let q_chars: Vec<char> = query.chars().collect();
+    let ends = EndConfig::semi_global();
+    (0..q_chars.len().saturating_sub(window - 1))
+        .step_by(step)
+        .map(|start| {
+            let window: String = q_chars[start..start + window].iter().collect();
+            (start, align_ends(&window, text, pens, &ends))
+        })
+        .collect()
+}
+
+/// Aligns every ordered pair (i, j) of distinct sequences in overlap mode, the end of seqs[i]
+/// to the start of seqs[j], and returns the overlaps of score at most max_score that span at
+/// least min_overlap chars of both sequences, by increasing (i, j).
+/// The overlap mode always has an alignment of score 0, that doesn't overlap at all: the
+/// min_overlap filter is what keeps only the actual overlaps. Sequences aren't aligned with
+/// themselves, and the pairs that can't be aligned (e.g. an empty sequence) are skipped.
+pub fn all_vs_all(
+    seqs: &[&str],
+    pens: &Penalties,
+    max_score: u32,
+    min_overlap: usize,
+) -> Vec<(usize, usize, Alignment)> {
+    let ends = EndConfig::overlap();
+    let mut overlaps = Vec::new();
+    for (i, query) in seqs.iter().enumerate() {
+        for (j, text) in seqs.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            if let Ok(alignment) = align_ends(query, text, pens, &ends) {
+                let overlap = min(
+                    alignment.query_end - alignment.query_start,
+                    alignment.text_end - alignment.text_start,
+                );
+                if alignment.score <= max_score && overlap >= min_overlap {
+                    overlaps.push((i, j, alignment));
                 }
-                (Some(a), None) => (Some(a + pens.extd_pen), Some(AlignmentLayer::Deletes)),
-                (None, Some(a)) => (
-                    Some(a + pens.extd_pen + pens.open_pen),
-                    Some(AlignmentLayer::Matches),
-                ),
-                (None, None) => panic!("(None, None), results.deletes"),
-            };
+            }
+        }
+    }
+    overlaps
+}
 
-            let mismatch = if chars_a[i - 1] == chars_b[j - 1] {
-                0
-            } else {
-                pens.mismatch_pen
+/// Aligns query and text without indels: the shortest string is slid along the longest one,
+/// and the shift with the fewest mismatches is kept, the lowest one on ties. The parts of the
+/// longest string before and after the shortest one are free end gaps, so the score is
+/// mismatch_pen times the number of mismatches.
+/// Returns the offset of the start of query relative to the start of text, which is negative
+/// if query is longer than text, with the alignment. This is O(n * m), and differs from a
+/// banded alignment of band 0, which can still open gaps at the ends.
+pub fn ungapped_best_offset(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+) -> Result<(isize, Alignment), AlignmentError> {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to ungapped_best_offset had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    let q_chars: Vec<char> = query.chars().collect();
+    let t_chars: Vec<char> = text.chars().collect();
+    let query_shorter = q_chars.len() <= t_chars.len();
+    let (short, long) = match query_shorter {
+        true => (&q_chars, &t_chars),
+        false => (&t_chars, &q_chars),
+    };
+    let mut best: Option<(usize, usize)> = None;
+    for shift in 0..=long.len() - short.len() {
+        let mismatches = short
+            .iter()
+            .zip(&long[shift..])
+            .filter(|(a, b)| a != b)
+            .count();
+        if best.map_or(true, |(_, fewest)| mismatches < fewest) {
+            best = Some((shift, mismatches));
+        }
+    }
+    let (shift, mismatches) = best.unwrap();
+    let score = mismatches as u64 * pens.mismatch_pen as u64;
+    let score = u32::try_from(score).map_err(|_| {
+        AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        ))
+    })?;
+    let padded: String = iter::repeat(GAP_CHAR)
+        .take(shift)
+        .chain(short.iter().copied())
+        .chain(iter::repeat(GAP_CHAR).take(long.len() - short.len() - shift))
+        .collect();
+    let long: String = long.iter().collect();
+    Ok(match query_shorter {
+        true => (
+            shift as isize,
+            Alignment::new(score, padded, long).with_free_ends(&EndConfig::semi_global()),
+        ),
+        false => {
+            let ends = EndConfig {
+                query_start_free: true,
+                query_end_free: true,
+                ..EndConfig::default()
             };
+            (
+                -(shift as isize),
+                Alignment::new(score, long, padded).with_free_ends(&ends),
+            )
+        }
+    })
+}
 
-            result.matches[i][j] = match (
-                result.matches[i - 1][j - 1].0,
-                result.deletes[i][j].0,
-                result.inserts[i][j].0,
-            ) {
-                (Some(a), Some(b), Some(c)) => {
-                    if a + mismatch < b {
-                        if a + mismatch < c {
-                            (Some(a + mismatch), Some(AlignmentLayer::Matches))
-                        } else {
-                            (Some(c), Some(AlignmentLayer::Inserts))
-                        }
-                    } else if b <= c {
-                        (Some(b), Some(AlignmentLayer::Deletes))
-                    } else {
-                        (Some(c), Some(AlignmentLayer::Inserts))
+/// Performs the SWG alignment of two RNA &str. If wobble is set, G-U and U-G pairs cost
+/// wobble_pen instead of the full mismatch_pen.
+pub fn wobble_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    wobble: bool,
+    wobble_pen: u32,
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to wobble_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let wobble_pen = if wobble {
+        wobble_pen
+    } else {
+        pens.mismatch_pen
+    };
+    let sub_cost = wobble_cost(a, b, pens, wobble_pen);
+    let align_mat = affine_gap_mat(a, b, pens, &sub_cost, MatOptions::default());
+    trace_back(&align_mat, a, b, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of query and text, where no query char can be aligned with the
+/// text chars of the forbidden regions: the alignment has to delete them.
+/// Each region is a half-open (start, end) interval of text positions, in chars. Positions
+/// past the end of text are ignored.
+pub fn align_masked(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    forbidden: &[(usize, usize)],
+) -> AlignResult {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_masked had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let cost = mismatch_cost(query, text, pens);
+    let masked_cost = |i: usize, j: usize| {
+        if forbidden
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&j))
+        {
+            None
+        } else {
+            cost(i, j)
+        }
+    };
+    let align_mat = affine_gap_mat(query, text, pens, &masked_cost, MatOptions::default());
+    trace_back(&align_mat, query, text, &EndConfig::global(), false)
+}
+
+/// Quality from which a mismatch costs the full mismatch_pen in align_with_qualities.
+pub const MAX_QUALITY: u8 = 40;
+
+/// Performs the SWG alignment of query and text, where a mismatch with the char i of query
+/// costs mismatch_pen * min(query_quals[i], MAX_QUALITY) / MAX_QUALITY, rounded down: the
+/// less a base can be trusted, the cheaper its mismatches. The qualities are Phred scores, not
+/// their ASCII encodings.
+/// Returns AlignmentError::LengthMismatch if there isn't one quality per char of query.
+pub fn align_with_qualities(
+    query: &str,
+    text: &str,
+    query_quals: &[u8],
+    pens: &Penalties,
+) -> AlignResult {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_with_qualities had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    let query_chars: Vec<char> = query.chars().collect();
+    if query_quals.len() != query_chars.len() {
+        return Err(AlignmentError::LengthMismatch(format!(
+            "{} qualities were given for a query of {} chars.",
+            query_quals.len(),
+            query_chars.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let text_chars: Vec<char> = text.chars().collect();
+    let quality_cost = |i: usize, j: usize| {
+        if query_chars[i] == text_chars[j] {
+            Some(0)
+        } else {
+            let quality = min(query_quals[i], MAX_QUALITY) as u64;
+            Some((pens.mismatch_pen as u64 * quality / MAX_QUALITY as u64) as u32)
+        }
+    };
+    let align_mat = affine_gap_mat(query, text, pens, &quality_cost, MatOptions::default());
+    trace_back(&align_mat, query, text, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of query and text with position-specific gap penalties along the
+/// text, as in profile alignments: deleting the text char j costs extd_profile[j], plus
+/// open_profile[j] if it opens the gap. The insertions between the text chars j and j + 1 have
+/// the penalties of j, like the insert states of a profile HMM, and those before the first
+/// text char have the penalties of 0. A mismatch costs mismatch_pen everywhere.
+/// Returns AlignmentError::LengthMismatch if the profiles don't have one penalty per char of
+/// text, and AlignmentError::DegeneratePenalties if a penalty of extd_profile is 0.
+pub fn align_with_gap_profile(
+    query: &str,
+    text: &str,
+    open_profile: &[u32],
+    extd_profile: &[u32],
+    mismatch_pen: u32,
+) -> AlignResult {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_with_gap_profile had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    let text_length = text.chars().count();
+    if open_profile.len() != text_length || extd_profile.len() != text_length {
+        return Err(AlignmentError::LengthMismatch(format!(
+            "Gap profiles of lengths {} (open) and {} (extension) were given for a text of {} chars.",
+            open_profile.len(),
+            extd_profile.len(),
+            text_length
+        )));
+    }
+    // As with Penalties::check_degenerate, an extension penalty of 0 would make the gaps
+    // through its position arbitrarily long.
+    if let Some(j) = extd_profile.iter().position(|pen| *pen == 0) {
+        return Err(AlignmentError::DegeneratePenalties(format!(
+            "The extension penalty of the text char {} is 0: gaps of any length have the same cost there.",
+            j
+        )));
+    }
+    // The gap penalties are replaced by the profiles.
+    let pens = Penalties {
+        mismatch_pen,
+        open_pen: 0,
+        extd_pen: 0,
+    };
+    let align_mat = affine_gap_mat(
+        query,
+        text,
+        &pens,
+        &mismatch_cost(query, text, &pens),
+        MatOptions {
+            gap_profile: Some((open_profile, extd_profile)),
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, query, text, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of query against a consensus, where the text char j is replaced by
+/// the set of chars allowed at the column j: a query char in the set of its column is a match,
+/// any other char a mismatch.
+/// In the returned alignment, a matched column shows the query char it matched, and the other
+/// columns the smallest char of their set, or '*' for an empty set.
+#[cfg(feature = "std")]
+pub fn align_to_consensus(query: &str, columns: &[HashSet<char>], pens: &Penalties) -> AlignResult {
+    if query.is_empty() || columns.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "The query or the consensus passed to align_to_consensus had a length of zero.
+                        Length of query:     {}
+                        Length of consensus: {}",
+            query.len(),
+            columns.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let query_chars: Vec<char> = query.chars().collect();
+    let text: String = columns
+        .iter()
+        .map(|column| column.iter().min().copied().unwrap_or('*'))
+        .collect();
+    let consensus_cost = |i: usize, j: usize| {
+        if columns[j].contains(&query_chars[i]) {
+            Some(0)
+        } else {
+            Some(pens.mismatch_pen)
+        }
+    };
+    let align_mat = affine_gap_mat(query, &text, pens, &consensus_cost, MatOptions::default());
+    let mut alignment = trace_back(&align_mat, query, &text, &EndConfig::global(), false)?;
+    let mut column = 0;
+    alignment.text_aligned = alignment
+        .query_aligned
+        .chars()
+        .zip(alignment.text_aligned.chars())
+        .map(|(q, t)| {
+            if t == GAP_CHAR {
+                return t;
+            }
+            column += 1;
+            match q != GAP_CHAR && columns[column - 1].contains(&q) {
+                true => q,
+                false => t,
+            }
+        })
+        .collect();
+    Ok(alignment)
+}
+
+/// Performs the global alignment of a and b where a gap of length l costs gaps.gap_cost(l), for
+/// any gap model. If gap_cost is affine (open_cost + l * extend_cost), the matrices of
+/// affine_gap_align are used, and the alignment is the one of affine_gap_align. Otherwise, a
+/// gap is priced as a whole, so every length of the last gap of each cell is tried: this runs
+/// in O(n * m * (n + m)), against O(n * m) for affine_gap_align.
+pub fn gap_model_align<G: GapModel>(a: &str, b: &str, mismatch_pen: u32, gaps: &G) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to gap_model_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    if mismatch_pen == 0 && gaps.gap_cost(1) == 0 {
+        return Err(AlignmentError::DegeneratePenalties(
+            "The mismatches and gaps are free: all the alignments have a score of 0.".to_string(),
+        ));
+    }
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let max_gap_len = chars_a.len().max(chars_b.len());
+    if is_affine(gaps, max_gap_len) {
+        let pens = Penalties {
+            mismatch_pen,
+            open_pen: gaps.open_cost(),
+            extd_pen: gaps.extend_cost(),
+        };
+        pens.check_score_overflow(&chars_a, &chars_b)?;
+        let align_mat = affine_gap_mat(
+            a,
+            b,
+            gaps,
+            &mismatch_cost(a, b, &pens),
+            MatOptions::default(),
+        );
+        return trace_back(&align_mat, a, b, &EndConfig::global(), false);
+    }
+    let gap_pen = |len: usize| gaps.gap_cost(len as u32) as u64;
+    RunMat::whole_gaps(&chars_a, &chars_b, mismatch_pen, &gap_pen, max_gap_len)
+        .align(&chars_a, &chars_b)
+        .map(|(alignment, _)| alignment)
+}
+
+/// Returns whether gaps.gap_cost(l) is open_cost + l * extend_cost for every l up to max_len:
+/// the gaps can then be priced one char at a time.
+fn is_affine<G: GapModel>(gaps: &G, max_len: usize) -> bool {
+    (1..=max_len as u64).all(|len| {
+        gaps.gap_cost(len as u32) as u64
+            == gaps.open_cost() as u64 + gaps.extend_cost() as u64 * len
+    })
+}
+
+/// Matrices where each cell ends a run of columns of its layer: a whole gap (see
+/// RunMat::whole_gaps), or a transposition, i.e. 2 (mis)match columns, for damerau_align. Each
+/// cell is the best score of the alignments of a[..i] and b[..j] ending in its layer (u64::MAX
+/// if there are none), with the length of its run and the layer of the cell the run follows.
+struct RunMat {
+    matches: Vec<Vec<(u64, usize, AlignmentLayer)>>,
+    inserts: Vec<Vec<(u64, usize, AlignmentLayer)>>,
+    deletes: Vec<Vec<(u64, usize, AlignmentLayer)>>,
+}
+
+impl RunMat {
+    /// The matrices of strings of a_len and b_len chars, where only the origin is reachable.
+    fn new(a_len: usize, b_len: usize) -> RunMat {
+        const NONE: (u64, usize, AlignmentLayer) = (u64::MAX, 0, AlignmentLayer::Matches);
+        let mut mat = RunMat {
+            matches: vec![vec![NONE; b_len + 1]; a_len + 1],
+            inserts: vec![vec![NONE; b_len + 1]; a_len + 1],
+            deletes: vec![vec![NONE; b_len + 1]; a_len + 1],
+        };
+        mat.matches[0][0].0 = 0;
+        mat
+    }
+
+    /// Fills the matrices of a and b where each gap is priced as a whole: a mismatch costs
+    /// mismatch_pen and a gap of length l costs gap_pen(l), l being at most max_gap_len. A gap
+    /// follows a (mis)match or a gap of the other kind, but not a gap of its kind: both would
+    /// form a single longer gap. Every length of the last gap of each cell is tried, so this
+    /// runs in O(n * m * max_gap_len).
+    fn whole_gaps(
+        a: &[char],
+        b: &[char],
+        mismatch_pen: u32,
+        gap_pen: &dyn Fn(usize) -> u64,
+        max_gap_len: usize,
+    ) -> RunMat {
+        let mut mat = RunMat::new(a.len(), b.len());
+        for i in 0..=a.len() {
+            for j in 0..=b.len() {
+                if i > 0 && j > 0 {
+                    let (score, layer) = mat.best_at(i - 1, j - 1);
+                    let sub = match a[i - 1] == b[j - 1] {
+                        true => 0,
+                        false => mismatch_pen as u64,
+                    };
+                    if score != u64::MAX {
+                        mat.matches[i][j] = (score + sub, 1, layer);
                     }
                 }
-                (Some(a), Some(b), None) => {
-                    if a + mismatch < b {
-                        (Some(a + mismatch), Some(AlignmentLayer::Matches))
-                    } else {
-                        (Some(b), Some(AlignmentLayer::Deletes))
+                for len in 1..=max_gap_len.min(i) {
+                    let (score, layer) = best_of(&[
+                        (mat.matches[i - len][j].0, AlignmentLayer::Matches),
+                        (mat.deletes[i - len][j].0, AlignmentLayer::Deletes),
+                    ]);
+                    let score = score.saturating_add(gap_pen(len));
+                    if score < mat.inserts[i][j].0 {
+                        mat.inserts[i][j] = (score, len, layer);
                     }
                 }
-                (Some(a), None, Some(c)) => {
-                    if a + mismatch < c {
-                        (Some(a + mismatch), Some(AlignmentLayer::Matches))
-                    } else {
-                        (Some(c), Some(AlignmentLayer::Inserts))
+                for len in 1..=max_gap_len.min(j) {
+                    let (score, layer) = best_of(&[
+                        (mat.matches[i][j - len].0, AlignmentLayer::Matches),
+                        (mat.inserts[i][j - len].0, AlignmentLayer::Inserts),
+                    ]);
+                    let score = score.saturating_add(gap_pen(len));
+                    if score < mat.deletes[i][j].0 {
+                        mat.deletes[i][j] = (score, len, layer);
                     }
                 }
-                (None, Some(b), Some(c)) => {
-                    if b < c {
-                        (Some(b), Some(AlignmentLayer::Deletes))
-                    } else {
-                        (Some(c), Some(AlignmentLayer::Inserts))
-                    }
+            }
+        }
+        mat
+    }
+
+    fn layer(&self, layer: AlignmentLayer) -> &Vec<Vec<(u64, usize, AlignmentLayer)>> {
+        match layer {
+            AlignmentLayer::Matches => &self.matches,
+            AlignmentLayer::Inserts => &self.inserts,
+            AlignmentLayer::Deletes => &self.deletes,
+        }
+    }
+
+    /// The best score at (i, j), with its layer.
+    fn best_at(&self, i: usize, j: usize) -> (u64, AlignmentLayer) {
+        best_of(&[
+            (self.matches[i][j].0, AlignmentLayer::Matches),
+            (self.inserts[i][j].0, AlignmentLayer::Inserts),
+            (self.deletes[i][j].0, AlignmentLayer::Deletes),
+        ])
+    }
+
+    fn cells(
+        &self,
+    ) -> RunCells<AlignmentLayer, impl Fn(AlignmentLayer, usize, usize) -> Run<AlignmentLayer> + '_>
+    {
+        RunCells::new(|layer, i, j| {
+            let (_, len, previous) = self.layer(layer)[i][j];
+            (layer, len, previous)
+        })
+    }
+
+    /// Returns the best alignment of a and b, whose matrices are self, with the cell it ends on.
+    /// Returns AlignmentError::NoAlignment if a gap limit of the fill prevents any alignment.
+    fn align(
+        &self,
+        a: &[char],
+        b: &[char],
+    ) -> Result<(Alignment, RunCell<AlignmentLayer>), AlignmentError> {
+        let (score, layer) = self.best_at(a.len(), b.len());
+        if score == u64::MAX {
+            return Err(AlignmentError::NoAlignment(format!(
+                "Strings of lengths {} and {} can't be aligned with the gap length limit.",
+                a.len(),
+                b.len()
+            )));
+        }
+        let score = u32::try_from(score).map_err(|_| {
+            AlignmentError::ScoreOverflow(format!(
+                "The alignment score is {}, which doesn't fit in a u32.",
+                score
+            ))
+        })?;
+        let cells = self.cells();
+        let end = cells.cell(layer, a.len(), b.len());
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        traceback(&cells, end, a, b, |q, t| {
+            query_aligned.push(q.unwrap_or(GAP_CHAR));
+            text_aligned.push(t.unwrap_or(GAP_CHAR));
+        })?;
+        let alignment = Alignment::new(
+            score,
+            query_aligned.chars().rev().collect(),
+            text_aligned.chars().rev().collect(),
+        );
+        Ok((alignment, end))
+    }
+}
+
+/// Returns the lowest score of cells, with its layer. Ties are broken in favor of the first cell.
+fn best_of(cells: &[(u64, AlignmentLayer)]) -> (u64, AlignmentLayer) {
+    cells
+        .iter()
+        .copied()
+        .min_by_key(|(score, _)| *score)
+        .unwrap()
+}
+
+/// The run of columns ending at a cell: (layer, length, state of the cell it follows).
+type Run<S> = (AlignmentLayer, usize, S);
+
+/// Traceback of the DPs whose cells end a run of columns of a layer, like a whole gap, a
+/// transposition or a copy of a repeat unit. run(state, i, j) returns the run ending at the
+/// cell of state at (i, j), a length of 0 marking an unreachable cell.
+struct RunCells<S, F: Fn(S, usize, usize) -> Run<S>> {
+    run: F,
+    states: PhantomData<S>,
+}
+
+/// A cell of RunCells, in the run of layer that ends remaining columns later (1 at its end),
+/// and follows a cell of the state previous.
+#[derive(Debug, Clone, Copy)]
+struct RunCell<S> {
+    layer: AlignmentLayer,
+    i: usize,
+    j: usize,
+    remaining: usize,
+    previous: S,
+}
+
+impl<S, F: Fn(S, usize, usize) -> Run<S>> RunCells<S, F> {
+    fn new(run: F) -> Self {
+        RunCells {
+            run,
+            states: PhantomData,
+        }
+    }
+
+    /// The cell of state at (i, j), which ends its run.
+    fn cell(&self, state: S, i: usize, j: usize) -> RunCell<S> {
+        let (layer, remaining, previous) = (self.run)(state, i, j);
+        RunCell {
+            layer,
+            i,
+            j,
+            remaining,
+            previous,
+        }
+    }
+}
+
+impl<S: Copy, F: Fn(S, usize, usize) -> Run<S>> TracebackCells for RunCells<S, F> {
+    type Cell = RunCell<S>;
+
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError> {
+        let RunCell {
+            layer,
+            i,
+            j,
+            remaining,
+            previous,
+        } = cell;
+        if (i, j) == (0, 0) {
+            return Ok(None);
+        }
+        if remaining == 0 {
+            return Err(AlignmentError::BacktraceFailed(format!(
+                "The {:?} cell at ({}, {}) is unreachable.",
+                layer, i, j
+            )));
+        }
+        let (i, j) = match layer {
+            AlignmentLayer::Matches => (i - 1, j - 1),
+            AlignmentLayer::Inserts => (i - 1, j),
+            AlignmentLayer::Deletes => (i, j - 1),
+        };
+        Ok(Some(match remaining {
+            1 => self.cell(previous, i, j),
+            _ => RunCell {
+                i,
+                j,
+                remaining: remaining - 1,
+                ..cell
+            },
+        }))
+    }
+
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize) {
+        (cell.layer, cell.i, cell.j)
+    }
+
+    /// Each column of a run is stepped over separately, so any (mis)match step is a single
+    /// column, which can be a mismatch.
+    fn can_mismatch(&self, _previous: Self::Cell, _cell: Self::Cell) -> bool {
+        true
+    }
+}
+
+/// An operation of an alignment returned by damerau_align.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamerauOp {
+    /// A single column, as in every other alignment.
+    Column(AlignmentOp),
+
+    /// 2 adjacent chars, swapped between the strings: ab is aligned with ba.
+    Transposition,
+}
+
+/// Performs the SWG alignment of two &str where swapping 2 adjacent chars costs transpose_pen,
+/// as in the Damerau edit distance: the DP also looks back 2 cells diagonally, when
+/// a[i - 1] == b[j] and a[i] == b[j - 1]. A transposition can't overlap other operations.
+/// Returns the alignment, where a transposition is 2 mismatched columns, with its operations,
+/// where it is a single DamerauOp::Transposition. The score counts transpose_pen for each
+/// transposition, so it differs from recompute_score when there are any.
+pub fn damerau_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    transpose_pen: u32,
+) -> Result<(Alignment, Vec<DamerauOp>), AlignmentError> {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to damerau_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let gap_open = pens.open_pen as u64 + pens.extd_pen as u64;
+    let gap_extd = pens.extd_pen as u64;
+
+    // The runs of the matches are a (mis)match, or a transposition of 2 columns.
+    let mut mat = RunMat::new(chars_a.len(), chars_b.len());
+    for i in 0..=chars_a.len() {
+        for j in 0..=chars_b.len() {
+            if i > 0 && j > 0 {
+                let (score, layer) = mat.best_at(i - 1, j - 1);
+                let sub = match chars_a[i - 1] == chars_b[j - 1] {
+                    true => 0,
+                    false => pens.mismatch_pen as u64,
+                };
+                if score != u64::MAX {
+                    mat.matches[i][j] = (score + sub, 1, layer);
+                }
+            }
+            if i > 1
+                && j > 1
+                && chars_a[i - 1] != chars_a[i - 2]
+                && chars_a[i - 1] == chars_b[j - 2]
+                && chars_a[i - 2] == chars_b[j - 1]
+            {
+                let (score, layer) = mat.best_at(i - 2, j - 2);
+                let score = score.saturating_add(transpose_pen as u64);
+                if score < mat.matches[i][j].0 {
+                    mat.matches[i][j] = (score, 2, layer);
+                }
+            }
+            if i > 0 {
+                let (score, layer) = best_of(&[
+                    (
+                        mat.matches[i - 1][j].0.saturating_add(gap_open),
+                        AlignmentLayer::Matches,
+                    ),
+                    (
+                        mat.inserts[i - 1][j].0.saturating_add(gap_extd),
+                        AlignmentLayer::Inserts,
+                    ),
+                    (
+                        mat.deletes[i - 1][j].0.saturating_add(gap_open),
+                        AlignmentLayer::Deletes,
+                    ),
+                ]);
+                mat.inserts[i][j] = (score, 1, layer);
+            }
+            if j > 0 {
+                let (score, layer) = best_of(&[
+                    (
+                        mat.matches[i][j - 1].0.saturating_add(gap_open),
+                        AlignmentLayer::Matches,
+                    ),
+                    (
+                        mat.inserts[i][j - 1].0.saturating_add(gap_open),
+                        AlignmentLayer::Inserts,
+                    ),
+                    (
+                        mat.deletes[i][j - 1].0.saturating_add(gap_extd),
+                        AlignmentLayer::Deletes,
+                    ),
+                ]);
+                mat.deletes[i][j] = (score, 1, layer);
+            }
+        }
+    }
+
+    let (alignment, end) = mat.align(&chars_a, &chars_b)?;
+    // Walks the cells again to find the transpositions, each being a run of 2 columns.
+    let cells = mat.cells();
+    let mut ops = Vec::new();
+    let mut cell = end;
+    let mut in_transposition = false;
+    while let Some(previous) = cells.predecessor(cell)? {
+        let RunCell { layer, i, j, .. } = cell;
+        if in_transposition {
+            in_transposition = false;
+        } else if (layer, cell.remaining) == (AlignmentLayer::Matches, 2) {
+            ops.push(DamerauOp::Transposition);
+            in_transposition = true;
+        } else {
+            let (q, t) = match layer {
+                AlignmentLayer::Matches => (Some(chars_a[i - 1]), Some(chars_b[j - 1])),
+                AlignmentLayer::Inserts => (Some(chars_a[i - 1]), None),
+                AlignmentLayer::Deletes => (None, Some(chars_b[j - 1])),
+            };
+            ops.extend(AlignmentOp::from_column(q, t).map(DamerauOp::Column));
+        }
+        cell = previous;
+    }
+    ops.reverse();
+    Ok((alignment, ops))
+}
+
+/// Performs the SWG alignment of two &str where no gap is longer than max_gap_len chars: two
+/// gaps of the same kind must be separated by another column. The length of the gap ending at
+/// each cell is kept in the matrices (see RunMat::whole_gaps), so this runs in
+/// O(n * m * max_gap_len).
+/// Returns AlignmentError::NoAlignment if the strings can't be aligned under the limit, e.g.
+/// strings of different lengths with a max_gap_len of 0.
+pub fn max_gap_align(a: &str, b: &str, pens: &Penalties, max_gap_len: u32) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to max_gap_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let max_gap_len = chars_a.len().max(chars_b.len()).min(max_gap_len as usize);
+    let gap_pen = |len: usize| pens.open_pen as u64 + pens.extd_pen as u64 * len as u64;
+    RunMat::whole_gaps(&chars_a, &chars_b, pens.mismatch_pen, &gap_pen, max_gap_len)
+        .align(&chars_a, &chars_b)
+        .map(|(alignment, _)| alignment)
+}
+
+/// States of the DP of align_with_repeat_units: the layers of SWG, and the gaps of a whole
+/// repeat unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RepeatState {
+    Matches,
+    Inserts,
+    Deletes,
+    UnitInsert,
+    UnitDelete,
+}
+
+/// Performs the SWG alignment of query and text, where a copy of unit that is missing from one
+/// of the strings in a tandem repeat costs open_pen + extd_pen, the penalty of a gap of 1 char,
+/// instead of the penalty of a gap of unit.len() chars: copy-number differences of a repeat
+/// are penalized per copy rather than per char.
+/// A copy is only gapped as a unit if it is next to another copy of unit in its string. Unit
+/// gaps are separate gaps: a regular gap next to one is opened again.
+/// Returns AlignmentError::ZeroLength if unit is empty.
+pub fn align_with_repeat_units(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    unit: &str,
+) -> AlignResult {
+    if query.is_empty() || text.is_empty() || unit.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_with_repeat_units had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}
+                        Length of unit:  {}",
+            query.len(),
+            text.len(),
+            unit.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = query.chars().collect();
+    let chars_b: Vec<char> = text.chars().collect();
+    let unit: Vec<char> = unit.chars().collect();
+    let unit_len = unit.len();
+    let gap_open = pens.open_pen as u64 + pens.extd_pen as u64;
+    let gap_extd = pens.extd_pen as u64;
+
+    // Whether the copy of unit ending at each position of s (exclusive) can be gapped as a unit.
+    let unit_gaps = |s: &[char]| -> Vec<bool> {
+        let copy_at = |start: usize| s.get(start..start + unit_len) == Some(&unit[..]);
+        (0..=s.len())
+            .map(|end| {
+                end >= unit_len
+                    && copy_at(end - unit_len)
+                    && ((end >= 2 * unit_len && copy_at(end - 2 * unit_len)) || copy_at(end))
+            })
+            .collect()
+    };
+    let unit_gaps_a = unit_gaps(&chars_a);
+    let unit_gaps_b = unit_gaps(&chars_b);
+
+    // Best score of the alignments of a[..i] and b[..j] ending in each state, with the state
+    // of the previous operation.
+    const STATES: [RepeatState; 5] = [
+        RepeatState::Matches,
+        RepeatState::Inserts,
+        RepeatState::Deletes,
+        RepeatState::UnitInsert,
+        RepeatState::UnitDelete,
+    ];
+    let mut cells =
+        vec![
+            vec![vec![(u64::MAX, RepeatState::Matches); chars_b.len() + 1]; chars_a.len() + 1];
+            STATES.len()
+        ];
+    cells[RepeatState::Matches as usize][0][0].0 = 0;
+    // The best state at (i, j), with the cost of the operation that follows it.
+    let best_at = |cells: &Vec<Vec<Vec<(u64, RepeatState)>>>,
+                   i: usize,
+                   j: usize,
+                   cost: &dyn Fn(RepeatState) -> u64| {
+        STATES
+            .iter()
+            .map(|state| {
+                let score = cells[*state as usize][i][j].0;
+                (score.saturating_add(cost(*state)), *state)
+            })
+            .min_by_key(|(score, _)| *score)
+            .unwrap()
+    };
+    for i in 0..=chars_a.len() {
+        for j in 0..=chars_b.len() {
+            if i > 0 && j > 0 {
+                let sub = match chars_a[i - 1] == chars_b[j - 1] {
+                    true => 0,
+                    false => pens.mismatch_pen as u64,
+                };
+                cells[RepeatState::Matches as usize][i][j] =
+                    best_at(&cells, i - 1, j - 1, &|_| sub);
+            }
+            if i > 0 {
+                cells[RepeatState::Inserts as usize][i][j] =
+                    best_at(&cells, i - 1, j, &|state| match state {
+                        RepeatState::Inserts => gap_extd,
+                        _ => gap_open,
+                    });
+            }
+            if j > 0 {
+                cells[RepeatState::Deletes as usize][i][j] =
+                    best_at(&cells, i, j - 1, &|state| match state {
+                        RepeatState::Deletes => gap_extd,
+                        _ => gap_open,
+                    });
+            }
+            if unit_gaps_a[i] {
+                cells[RepeatState::UnitInsert as usize][i][j] =
+                    best_at(&cells, i - unit_len, j, &|_| gap_open);
+            }
+            if unit_gaps_b[j] {
+                cells[RepeatState::UnitDelete as usize][i][j] =
+                    best_at(&cells, i, j - unit_len, &|_| gap_open);
+            }
+        }
+    }
+
+    let (score, state) = best_at(&cells, chars_a.len(), chars_b.len(), &|_| 0);
+    let score = u32::try_from(score).map_err(|_| {
+        AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        ))
+    })?;
+    // A unit gap is a run of unit_len gap columns.
+    let traceback_cells = RunCells::new(|state: RepeatState, i, j| {
+        let (score, previous) = cells[state as usize][i][j];
+        let (layer, len) = match state {
+            RepeatState::Matches => (AlignmentLayer::Matches, 1),
+            RepeatState::Inserts => (AlignmentLayer::Inserts, 1),
+            RepeatState::Deletes => (AlignmentLayer::Deletes, 1),
+            RepeatState::UnitInsert => (AlignmentLayer::Inserts, unit_len),
+            RepeatState::UnitDelete => (AlignmentLayer::Deletes, unit_len),
+        };
+        match score {
+            u64::MAX => (layer, 0, previous),
+            _ => (layer, len, previous),
+        }
+    });
+    let end = traceback_cells.cell(state, chars_a.len(), chars_b.len());
+    let mut query_aligned = String::new();
+    let mut text_aligned = String::new();
+    traceback(&traceback_cells, end, &chars_a, &chars_b, |q, t| {
+        query_aligned.push(q.unwrap_or(GAP_CHAR));
+        text_aligned.push(t.unwrap_or(GAP_CHAR));
+    })?;
+    Ok(Alignment::new(
+        score,
+        query_aligned.chars().rev().collect(),
+        text_aligned.chars().rev().collect(),
+    ))
+}
+
+/// Finds the best alignment of query and text with the given gap columns. gap_mask has one
+/// entry per column of the alignment: true for a column aligning a char with a gap, false for
+/// a column aligning two chars (a match or a mismatch). Whether each gap is in the query or in
+/// the text is chosen to minimize the score, which determines the pairs of the other columns.
+/// Returns AlignmentError::LengthMismatch if no alignment of the strings has these columns: the
+/// strings must have at least as many chars as there are false columns, and the extra chars
+/// must fill the true columns.
+pub fn score_with_gap_mask(
+    query: &str,
+    text: &str,
+    gap_mask: &[bool],
+    pens: &Penalties,
+) -> AlignResult {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to score_with_gap_mask had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_q: Vec<char> = query.chars().collect();
+    let chars_t: Vec<char> = text.chars().collect();
+    let pairs = gap_mask.iter().filter(|gap| !**gap).count();
+    if chars_q.len() < pairs
+        || chars_t.len() < pairs
+        || chars_q.len() + chars_t.len() != gap_mask.len() + pairs
+    {
+        return Err(AlignmentError::LengthMismatch(format!(
+            "The gap mask has {} columns with {} pairs, which doesn't fit strings of lengths {} and {}.",
+            gap_mask.len(),
+            pairs,
+            chars_q.len(),
+            chars_t.len()
+        )));
+    }
+    let open = pens.open_pen as u64 + pens.extd_pen as u64;
+    let extd = pens.extd_pen as u64;
+
+    // cells[k][i] holds the best scores of the first k columns, aligning i query chars, ending
+    // in each layer, with the layer they follow. The number of text chars is then fixed by
+    // the mask: the pairs of the first k columns plus the gaps that aren't in the query.
+    const NONE: (u64, AlignmentLayer) = (u64::MAX, AlignmentLayer::Matches);
+    let mut cells = vec![vec![[NONE; 3]; chars_q.len() + 1]; gap_mask.len() + 1];
+    cells[0][0][AlignmentLayer::Matches as usize].0 = 0;
+    let mut pairs_before = 0;
+    for (k, &gap) in gap_mask.iter().enumerate() {
+        for i in 0..=chars_q.len().min(pairs_before + k) {
+            let j = pairs_before + k - i;
+            if j > chars_t.len() {
+                continue;
+            }
+            let from = cells[k][i];
+            let mut relax =
+                |i: usize, layer: AlignmentLayer, cost: &dyn Fn(AlignmentLayer) -> u64| {
+                    for previous in [
+                        AlignmentLayer::Matches,
+                        AlignmentLayer::Inserts,
+                        AlignmentLayer::Deletes,
+                    ] {
+                        let score = from[previous as usize].0;
+                        if score != u64::MAX
+                            && score + cost(previous) < cells[k + 1][i][layer as usize].0
+                        {
+                            cells[k + 1][i][layer as usize] = (score + cost(previous), previous);
+                        }
+                    }
+                };
+            match gap {
+                false if i < chars_q.len() && j < chars_t.len() => {
+                    let sub = match chars_q[i] == chars_t[j] {
+                        true => 0,
+                        false => pens.mismatch_pen as u64,
+                    };
+                    relax(i + 1, AlignmentLayer::Matches, &|_| sub);
+                }
+                false => (),
+                true => {
+                    if i < chars_q.len() {
+                        relax(i + 1, AlignmentLayer::Inserts, &|previous| match previous {
+                            AlignmentLayer::Inserts => extd,
+                            _ => open,
+                        });
+                    }
+                    if j < chars_t.len() {
+                        relax(i, AlignmentLayer::Deletes, &|previous| match previous {
+                            AlignmentLayer::Deletes => extd,
+                            _ => open,
+                        });
+                    }
+                }
+            }
+        }
+        if !gap {
+            pairs_before += 1;
+        }
+    }
+
+    let (score, layer) = [
+        AlignmentLayer::Matches,
+        AlignmentLayer::Inserts,
+        AlignmentLayer::Deletes,
+    ]
+    .into_iter()
+    .map(|layer| {
+        (
+            cells[gap_mask.len()][chars_q.len()][layer as usize].0,
+            layer,
+        )
+    })
+    .min_by_key(|(score, _)| *score)
+    .unwrap();
+    let score = u32::try_from(score).map_err(|_| {
+        AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        ))
+    })?;
+    let mut pairs_before = vec![0];
+    for &gap in gap_mask {
+        pairs_before.push(pairs_before.last().unwrap() + !gap as usize);
+    }
+    let traceback_cells = GapMaskCells {
+        cells: &cells,
+        pairs_before,
+    };
+    let mut query_aligned = String::new();
+    let mut text_aligned = String::new();
+    let end = (layer, gap_mask.len(), chars_q.len());
+    traceback(&traceback_cells, end, &chars_q, &chars_t, |q, t| {
+        query_aligned.push(q.unwrap_or(GAP_CHAR));
+        text_aligned.push(t.unwrap_or(GAP_CHAR));
+    })?;
+    Ok(Alignment::new(
+        score,
+        query_aligned.chars().rev().collect(),
+        text_aligned.chars().rev().collect(),
+    ))
+}
+
+/// The cells of score_with_gap_mask are (layer, k, i): the best alignment of the first k columns
+/// of the mask, aligning i query chars. pairs_before[k] is the number of pairs of the first k
+/// columns, which gives the number of text chars aligned.
+struct GapMaskCells<'a> {
+    cells: &'a Vec<Vec<[(u64, AlignmentLayer); 3]>>,
+    pairs_before: Vec<usize>,
+}
+
+impl TracebackCells for GapMaskCells<'_> {
+    type Cell = (AlignmentLayer, usize, usize);
+
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError> {
+        let (layer, k, i) = cell;
+        if k == 0 {
+            return Ok(None);
+        }
+        let (score, previous) = self.cells[k][i][layer as usize];
+        if score == u64::MAX {
+            return Err(AlignmentError::BacktraceFailed(format!(
+                "The {:?} cell of the column {} at {} is unreachable.",
+                layer, k, i
+            )));
+        }
+        Ok(Some(match layer {
+            AlignmentLayer::Deletes => (previous, k - 1, i),
+            _ => (previous, k - 1, i - 1),
+        }))
+    }
+
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize) {
+        let (layer, k, i) = cell;
+        (layer, i, self.pairs_before[k] + k - i)
+    }
+
+    /// Each column of the mask is a single step.
+    fn can_mismatch(&self, _previous: Self::Cell, _cell: Self::Cell) -> bool {
+        true
+    }
+}
+
+/// Finds the non-overlapping local alignments of a and b with a similarity of at least
+/// min_score, best first, using the Waterman-Eggert procedure: the best local alignment is
+/// found with the Smith-Waterman DP, its cells are masked so that no later alignment goes
+/// through them, and the DP is recomputed, until no alignment reaches min_score or
+/// max_results alignments are found.
+/// The similarity rewards each match with match_reward, and subtracts the penalties of the
+/// mismatches and gaps. It is returned with each alignment, whose score is the penalty of its
+/// local region. As with free end gaps, the aligned strings span both strings, the chars
+/// outside of the local region being aligned with gaps, and the coordinates delimit the region.
+pub fn local_align_all(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    match_reward: u32,
+    min_score: u32,
+    max_results: usize,
+) -> Result<Vec<(u32, Alignment)>, AlignmentError> {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to local_align_all had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let mut masked = vec![vec![false; chars_b.len() + 1]; chars_a.len() + 1];
+    let mut results = Vec::new();
+    while results.len() < max_results {
+        let (similarity, start_a, start_b, region) =
+            match best_local_alignment(&chars_a, &chars_b, pens, match_reward, &masked)? {
+                Some(best) if best.0 >= min_score.max(1) as i64 => best,
+                _ => break,
+            };
+        let (end_a, end_b) = (start_a + region.query_end, start_b + region.text_end);
+        // Every cell of the path is masked, including those of the gaps.
+        let (mut i, mut j) = (start_a, start_b);
+        for (q, t) in region.columns() {
+            i += q.is_some() as usize;
+            j += t.is_some() as usize;
+            masked[i][j] = true;
+        }
+        let gaps = |len: usize| iter::repeat(GAP_CHAR).take(len);
+        let query_aligned: String = chars_a[..start_a]
+            .iter()
+            .copied()
+            .chain(gaps(start_b))
+            .chain(region.query_aligned.chars())
+            .chain(chars_a[end_a..].iter().copied())
+            .chain(gaps(chars_b.len() - end_b))
+            .collect();
+        let text_aligned: String = gaps(start_a)
+            .chain(chars_b[..start_b].iter().copied())
+            .chain(region.text_aligned.chars())
+            .chain(gaps(chars_a.len() - end_a))
+            .chain(chars_b[end_b..].iter().copied())
+            .collect();
+        let mut alignment =
+            Alignment::new(region.recompute_score(pens), query_aligned, text_aligned);
+        (alignment.query_start, alignment.query_end) = (start_a, end_a);
+        (alignment.text_start, alignment.text_end) = (start_b, end_b);
+        results.push((similarity as u32, alignment));
+    }
+    Ok(results)
+}
+
+/// Runs the Smith-Waterman DP of local_align_all, where no path goes through a masked cell.
+/// Returns the best similarity, with the positions in a and b where its local alignment starts
+/// and the alignment of its region, or None if every cell is masked.
+fn best_local_alignment(
+    chars_a: &[char],
+    chars_b: &[char],
+    pens: &Penalties,
+    match_reward: u32,
+    masked: &[Vec<bool>],
+) -> Result<Option<(i64, usize, usize, Alignment)>, AlignmentError> {
+    let gap_open = pens.open_pen as i64 + pens.extd_pen as i64;
+    let gap_extd = pens.extd_pen as i64;
+    // Best similarity of the local alignments ending at [i][j] in each layer, with the layer
+    // they follow, None for a match cell starting the alignment. A local alignment never
+    // starts or ends with a gap, which would only lower its similarity.
+    const NONE: (i64, Option<AlignmentLayer>) = (i64::MIN, None);
+    let mut matches = vec![vec![NONE; chars_b.len() + 1]; chars_a.len() + 1];
+    let mut inserts = matches.clone();
+    let mut deletes = matches.clone();
+    // Ties are broken in favor of the last cell: starting the alignment, rather than extending
+    // it with chars of similarity 0.
+    let best_of =
+        |cells: &[(i64, Option<AlignmentLayer>)]| *cells.iter().max_by_key(|cell| cell.0).unwrap();
+    let sub = |score: i64, cost: i64| match score {
+        i64::MIN => i64::MIN,
+        score => score - cost,
+    };
+    let mut best: Option<(i64, usize, usize)> = None;
+    for i in 1..=chars_a.len() {
+        for j in 1..=chars_b.len() {
+            if masked[i][j] {
+                continue;
+            }
+            let similarity = match chars_a[i - 1] == chars_b[j - 1] {
+                true => match_reward as i64,
+                false => -(pens.mismatch_pen as i64),
+            };
+            let (previous, layer) = best_of(&[
+                (matches[i - 1][j - 1].0, Some(AlignmentLayer::Matches)),
+                (inserts[i - 1][j - 1].0, Some(AlignmentLayer::Inserts)),
+                (deletes[i - 1][j - 1].0, Some(AlignmentLayer::Deletes)),
+                (0, None),
+            ]);
+            matches[i][j] = (previous + similarity, layer);
+            if best.map_or(true, |(score, _, _)| matches[i][j].0 > score) {
+                best = Some((matches[i][j].0, i, j));
+            }
+            inserts[i][j] = best_of(&[
+                (
+                    sub(matches[i - 1][j].0, gap_open),
+                    Some(AlignmentLayer::Matches),
+                ),
+                (
+                    sub(inserts[i - 1][j].0, gap_extd),
+                    Some(AlignmentLayer::Inserts),
+                ),
+                (
+                    sub(deletes[i - 1][j].0, gap_open),
+                    Some(AlignmentLayer::Deletes),
+                ),
+            ]);
+            deletes[i][j] = best_of(&[
+                (
+                    sub(matches[i][j - 1].0, gap_open),
+                    Some(AlignmentLayer::Matches),
+                ),
+                (
+                    sub(inserts[i][j - 1].0, gap_open),
+                    Some(AlignmentLayer::Inserts),
+                ),
+                (
+                    sub(deletes[i][j - 1].0, gap_extd),
+                    Some(AlignmentLayer::Deletes),
+                ),
+            ]);
+        }
+    }
+
+    let Some((similarity, end_a, end_b)) = best else {
+        return Ok(None);
+    };
+    let mut cells = LocalCells {
+        matches: &matches,
+        inserts: &inserts,
+        deletes: &deletes,
+        origin: (0, 0),
+    };
+    let end = (Some(AlignmentLayer::Matches), end_a, end_b);
+    // The region starts where the path does: the traceback is relative to it.
+    let mut start = end;
+    while let Some(previous) = cells.predecessor(start)? {
+        start = previous;
+    }
+    cells.origin = (start.1, start.2);
+    let mut query_aligned = String::new();
+    let mut text_aligned = String::new();
+    traceback(
+        &cells,
+        end,
+        &chars_a[start.1..],
+        &chars_b[start.2..],
+        |q, t| {
+            query_aligned.push(q.unwrap_or(GAP_CHAR));
+            text_aligned.push(t.unwrap_or(GAP_CHAR));
+        },
+    )?;
+    let region = Alignment::new(
+        0,
+        query_aligned.chars().rev().collect(),
+        text_aligned.chars().rev().collect(),
+    );
+    Ok(Some((similarity, start.1, start.2, region)))
+}
+
+/// The cells of the Smith-Waterman DP of best_local_alignment are (layer, i, j), a None layer
+/// being the start of the local alignment, before its first (mis)match. Positions are
+/// relative to origin, the start of the local alignment, so that the traceback ends at (0, 0).
+struct LocalCells<'a> {
+    matches: &'a Vec<Vec<(i64, Option<AlignmentLayer>)>>,
+    inserts: &'a Vec<Vec<(i64, Option<AlignmentLayer>)>>,
+    deletes: &'a Vec<Vec<(i64, Option<AlignmentLayer>)>>,
+    origin: (usize, usize),
+}
+
+impl TracebackCells for LocalCells<'_> {
+    type Cell = (Option<AlignmentLayer>, usize, usize);
+
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError> {
+        let (layer, i, j) = cell;
+        Ok(match layer {
+            None => None,
+            Some(AlignmentLayer::Matches) => Some((self.matches[i][j].1, i - 1, j - 1)),
+            Some(AlignmentLayer::Inserts) => Some((self.inserts[i][j].1, i - 1, j)),
+            Some(AlignmentLayer::Deletes) => Some((self.deletes[i][j].1, i, j - 1)),
+        })
+    }
+
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize) {
+        let (layer, i, j) = cell;
+        (
+            layer.unwrap_or(AlignmentLayer::Matches),
+            i - self.origin.0,
+            j - self.origin.1,
+        )
+    }
+
+    /// Each (mis)match of the DP is a single column.
+    fn can_mismatch(&self, _previous: Self::Cell, _cell: Self::Cell) -> bool {
+        true
+    }
+}
+
+/// Performs the SWG alignment of two &str without substitutions: differing chars can't be
+/// aligned together, so every change is an insertion or a deletion. This is the LCS alignment
+/// of the strings, with affine gaps. pens.mismatch_pen is ignored.
+pub fn indel_only_align(a: &str, b: &str, pens: &Penalties) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to indel_only_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    Penalties {
+        mismatch_pen: 0,
+        ..pens.clone()
+    }
+    .check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let match_cost = |i: usize, j: usize| (chars_a[i] == chars_b[j]).then_some(0);
+    let align_mat = affine_gap_mat(a, b, pens, &match_cost, MatOptions::default());
+    trace_back(&align_mat, a, b, &EndConfig::global(), false)
+}
+
+/// Performs the SWG alignment of two &str. If no_adjacent_indels is set, an insertion can't be
+/// directly followed or preceded by a deletion: a gap can only be opened after a (mis)match
+/// or at the start of the alignment.
+pub fn strict_indels_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    no_adjacent_indels: bool,
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to strict_indels_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &mismatch_cost(a, b, pens),
+        MatOptions {
+            no_adjacent_indels,
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, a, b, &EndConfig::global(), no_adjacent_indels)
+}
+
+/// Performs the SWG alignment of two &str with every option of the other SWG functions.
+/// Used by AlignmentConfig: the options must have been checked to be compatible, the band
+/// is only supported for global alignments.
+pub(crate) fn configured_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    ends: &EndConfig,
+    band: Option<usize>,
+    wobble_pen: Option<u32>,
+    no_adjacent_indels: bool,
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to AlignmentConfig::align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let sub_cost: Box<dyn Fn(usize, usize) -> Option<u32>> = match wobble_pen {
+        Some(wobble_pen) => Box::new(wobble_cost(a, b, pens, wobble_pen)),
+        None => Box::new(mismatch_cost(a, b, pens)),
+    };
+    let align_mat = affine_gap_mat(
+        a,
+        b,
+        pens,
+        &sub_cost,
+        MatOptions {
+            ends: *ends,
+            band: band.map(|band| band_diagonals(a, b, band)),
+            no_adjacent_indels,
+            ..MatOptions::default()
+        },
+    );
+    trace_back(&align_mat, a, b, ends, no_adjacent_indels)
+}
+
+/// Returns the diagonals of the band used by affine_gap_align_banded.
+fn band_diagonals(a: &str, b: &str, band: usize) -> (isize, isize) {
+    let final_diagonal = a.chars().count() as isize - b.chars().count() as isize;
+    let band = band.min(isize::MAX as usize / 2) as isize;
+    (final_diagonal.min(0) - band, final_diagonal.max(0) + band)
+}
+
+/// Returns the substitution cost function of RNA alignments: G-U and U-G pairs cost
+/// wobble_pen, the other mismatches mismatch_pen.
+fn wobble_cost(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    wobble_pen: u32,
+) -> impl Fn(usize, usize) -> Option<u32> {
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let mismatch_pen = pens.mismatch_pen;
+    move |i, j| match (chars_a[i], chars_b[j]) {
+        (x, y) if x == y => Some(0),
+        ('G', 'U') | ('U', 'G') => Some(wobble_pen),
+        _ => Some(mismatch_pen),
+    }
+}
+
+/// Returns the default substitution cost function: 0 for equal chars, else mismatch_pen.
+fn mismatch_cost(a: &str, b: &str, pens: &Penalties) -> impl Fn(usize, usize) -> Option<u32> {
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let mismatch_pen = pens.mismatch_pen;
+    move |i, j| {
+        if chars_a[i] == chars_b[j] {
+            Some(0)
+        } else {
+            Some(mismatch_pen)
+        }
+    }
+}
+
+/// Options of affine_gap_mat. The default fills the whole matrices of a global alignment, with
+/// the gap penalties of its gap model.
+#[derive(Default)]
+struct MatOptions<'a> {
+    /// The end gaps that are free.
+    ends: EndConfig,
+
+    /// If Some((lo, hi)), only the cells whose diagonal (i - j) is within lo..=hi are
+    /// computed. Unreachable cells are left to None.
+    band: Option<(isize, isize)>,
+
+    /// If set, gaps are only opened from cells reached by a (mis)match, so that an insertion
+    /// is never next to a deletion.
+    no_adjacent_indels: bool,
+
+    /// If Some, called with the index and the lowest score of each row once it is filled,
+    /// rows with no reachable cell excepted.
+    on_row: Option<&'a mut dyn FnMut(usize, u32)>,
+
+    /// If Some((open, extd)), the gap penalties of the gap model are replaced by
+    /// position-specific ones: see align_with_gap_profile.
+    gap_profile: Option<(&'a [u32], &'a [u32])>,
+}
+
+/// Fills the alignment matrices.
+/// sub_cost(i, j) is the cost of aligning a[i] with b[j], or None if they can't be aligned.
+/// The gaps are priced one char at a time with the open_cost and extend_cost of gaps, so its
+/// gap_cost must be affine (see is_affine).
+/// The scores saturate at u32::MAX: a cell off the optimal path, e.g. a gap opened with a huge
+/// open_pen, can exceed the optimal score. The optimal score itself must fit in a u32, which
+/// check_valid_for ensures.
+fn affine_gap_mat<G: GapModel>(
+    a: &str,
+    b: &str,
+    gaps: &G,
+    sub_cost: &dyn Fn(usize, usize) -> Option<u32>,
+    options: MatOptions,
+) -> AlignMat {
+    let MatOptions {
+        ends,
+        band,
+        no_adjacent_indels,
+        mut on_row,
+        gap_profile,
+    } = options;
+    // (open_pen, extd_pen) of the gaps in the column of b[j]: the deletion of b[j], and the
+    // insertions after it.
+    let gap_pens = |j: usize| match gap_profile {
+        Some((open, extd)) => (open[j], extd[j]),
+        None => (gaps.open_cost(), gaps.extend_cost()),
+    };
+    let mut result = new_mat(a, b, &gap_pens, &ends);
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    // Score of the best path reaching each cell with a (mis)match, or from the origin.
+    let mut diagonals: Vec<Vec<Option<u32>>> =
+        vec![vec![None; chars_b.len() + 1]; chars_a.len() + 1];
+    diagonals[0][0] = Some(0);
+    // Score of the cell a gap can be opened from.
+    let gap_source = |result: &AlignMat, diagonals: &Vec<Vec<Option<u32>>>, i: usize, j: usize| {
+        if no_adjacent_indels {
+            diagonals[i][j]
+        } else {
+            result.matches[i][j].0
+        }
+    };
+    let in_band = |i: usize, j: usize| match band {
+        Some((lo, hi)) => (lo..=hi).contains(&(i as isize - j as isize)),
+        None => true,
+    };
+    if band.is_some() {
+        for i in 0..chars_a.len() + 1 {
+            for j in 0..chars_b.len() + 1 {
+                if !in_band(i, j) {
+                    result.inserts[i][j] = (None, None);
+                    result.matches[i][j] = (None, None);
+                    result.deletes[i][j] = (None, None);
+                }
+            }
+        }
+    }
+    for i in 1..chars_a.len() + 1 {
+        for j in 1..chars_b.len() + 1 {
+            if !in_band(i, j) {
+                continue;
+            }
+            let (open_pen, extd_pen) = gap_pens(j - 1);
+            let open_extd_pen = open_pen.saturating_add(extd_pen);
+            result.inserts[i][j] = match (
+                result.inserts[i - 1][j].0,
+                gap_source(&result, &diagonals, i - 1, j),
+            ) {
+                (Some(a), Some(b)) => {
+                    if min(a.saturating_add(extd_pen), b.saturating_add(open_extd_pen))
+                        == a.saturating_add(extd_pen)
+                    {
+                        (
+                            Some(a.saturating_add(extd_pen)),
+                            Some(AlignmentLayer::Inserts),
+                        )
+                    } else {
+                        (
+                            Some(b.saturating_add(open_extd_pen)),
+                            Some(AlignmentLayer::Matches),
+                        )
+                    }
+                }
+                (Some(a), None) => (
+                    Some(a.saturating_add(extd_pen)),
+                    Some(AlignmentLayer::Inserts),
+                ),
+                (None, Some(a)) => (
+                    Some(a.saturating_add(open_extd_pen)),
+                    Some(AlignmentLayer::Matches),
+                ),
+                (None, None) => (None, None),
+            };
+
+            result.deletes[i][j] = match (
+                result.deletes[i][j - 1].0,
+                gap_source(&result, &diagonals, i, j - 1),
+            ) {
+                (Some(a), Some(b)) => {
+                    if min(a.saturating_add(extd_pen), b.saturating_add(open_extd_pen))
+                        == a.saturating_add(extd_pen)
+                    {
+                        (
+                            Some(a.saturating_add(extd_pen)),
+                            Some(AlignmentLayer::Deletes),
+                        )
+                    } else {
+                        (
+                            Some(b.saturating_add(open_extd_pen)),
+                            Some(AlignmentLayer::Matches),
+                        )
+                    }
+                }
+                (Some(a), None) => (
+                    Some(a.saturating_add(extd_pen)),
+                    Some(AlignmentLayer::Deletes),
+                ),
+                (None, Some(a)) => (
+                    Some(a.saturating_add(open_extd_pen)),
+                    Some(AlignmentLayer::Matches),
+                ),
+                (None, None) => (None, None),
+            };
+
+            diagonals[i][j] = result.matches[i - 1][j - 1]
+                .0
+                .and_then(|a| sub_cost(i - 1, j - 1).map(|cost| a.saturating_add(cost)));
+            result.matches[i][j] = match (
+                diagonals[i][j],
+                result.deletes[i][j].0,
+                result.inserts[i][j].0,
+            ) {
+                (Some(a), Some(b), Some(c)) => {
+                    if a < b {
+                        if a < c {
+                            (Some(a), Some(AlignmentLayer::Matches))
+                        } else {
+                            (Some(c), Some(AlignmentLayer::Inserts))
+                        }
+                    } else if b <= c {
+                        (Some(b), Some(AlignmentLayer::Deletes))
+                    } else {
+                        (Some(c), Some(AlignmentLayer::Inserts))
+                    }
+                }
+                (Some(a), Some(b), None) => {
+                    if a < b {
+                        (Some(a), Some(AlignmentLayer::Matches))
+                    } else {
+                        (Some(b), Some(AlignmentLayer::Deletes))
+                    }
+                }
+                (Some(a), None, Some(c)) => {
+                    if a < c {
+                        (Some(a), Some(AlignmentLayer::Matches))
+                    } else {
+                        (Some(c), Some(AlignmentLayer::Inserts))
+                    }
+                }
+                (None, Some(b), Some(c)) => {
+                    if b < c {
+                        (Some(b), Some(AlignmentLayer::Deletes))
+                    } else {
+                        (Some(c), Some(AlignmentLayer::Inserts))
+                    }
+                }
+                (Some(a), None, None) => (Some(a), Some(AlignmentLayer::Matches)),
+                (None, Some(b), None) => (Some(b), Some(AlignmentLayer::Deletes)),
+                (None, None, Some(c)) => (Some(c), Some(AlignmentLayer::Inserts)),
+                (None, None, None) => (None, None),
+            };
+        }
+        if let Some(on_row) = on_row.as_mut() {
+            if let Some(best) = result.matches[i].iter().filter_map(|cell| cell.0).min() {
+                on_row(i, best);
+            }
+        }
+    }
+    result
+}
+
+fn new_mat(a: &str, b: &str, gap_pens: &dyn Fn(usize) -> (u32, u32), ends: &EndConfig) -> AlignMat {
+    let a_length = a.chars().count() + 1;
+    let b_length = b.chars().count() + 1;
+
+    let mut inserts = vec![vec![(None, None); b_length]; a_length];
+    let mut matches = vec![vec![(None, None); b_length]; a_length];
+    let mut deletes = vec![vec![(None, None); b_length]; a_length];
+
+    matches[0][0] = (Some(0), None);
+
+    // The insertions before the first char of b have the penalties of its column.
+    let (open_pen, extd_pen) = gap_pens(0);
+    inserts[1][0] = (
+        Some(extd_pen.saturating_add(open_pen)),
+        Some(AlignmentLayer::Matches),
+    );
+    matches[1][0] = inserts[1][0];
+    for i in 2..a_length {
+        inserts[i][0] = (
+            Some(inserts[i - 1][0].0.unwrap().saturating_add(extd_pen)),
+            Some(AlignmentLayer::Inserts),
+        );
+        matches[i][0] = inserts[i][0];
+    }
+
+    deletes[0][1] = (
+        Some(extd_pen.saturating_add(open_pen)),
+        Some(AlignmentLayer::Matches),
+    );
+    matches[0][1] = deletes[0][1];
+    for i in 2..b_length {
+        deletes[0][i] = (
+            Some(
+                deletes[0][i - 1]
+                    .0
+                    .unwrap()
+                    .saturating_add(gap_pens(i - 1).1),
+            ),
+            Some(AlignmentLayer::Deletes),
+        );
+        matches[0][i] = deletes[0][i];
+    }
+
+    // Free start gaps: the first column/row of the matches matrix are reachable at no cost.
+    if ends.query_start_free {
+        for row in matches.iter_mut().skip(1) {
+            row[0].0 = Some(0);
+        }
+    }
+    if ends.text_start_free {
+        for cell in matches[0].iter_mut().skip(1) {
+            cell.0 = Some(0);
+        }
+    }
+
+    AlignMat {
+        inserts,
+        matches,
+        deletes,
+    }
+}
+
+/// The cells of the SWG matrices are (layer, i, j, forced), forced being set when leaving a
+/// gap opened from a (mis)match that might not be the best way to reach its cell: with
+/// no_adjacent_indels, the (mis)match must then be taken even if its tag is a gap.
+struct SwgCells<'a> {
+    mat: &'a AlignMat,
+    no_adjacent_indels: bool,
+
+    /// The string the gaps are reported against. The inserts and deletes matrices hold the
+    /// gaps along a and b: with Reference::First, a is the text and its gaps are deletions.
+    reference: Reference,
+}
+
+impl SwgCells<'_> {
+    /// The cell of layer at (i, j). The cells of the first row and column can only be reached
+    /// through a gap, whatever their tags.
+    fn cell(
+        layer: AlignmentLayer,
+        i: usize,
+        j: usize,
+        forced: bool,
+    ) -> (AlignmentLayer, usize, usize, bool) {
+        match (i, j) {
+            (0, 0) => (AlignmentLayer::Matches, 0, 0, false),
+            (_, 0) => (AlignmentLayer::Inserts, i, 0, false),
+            (0, _) => (AlignmentLayer::Deletes, 0, j, false),
+            _ => (layer, i, j, forced),
+        }
+    }
+}
+
+impl TracebackCells for SwgCells<'_> {
+    type Cell = (AlignmentLayer, usize, usize, bool);
+
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError> {
+        let (layer, i, j, forced) = cell;
+        if (i, j) == (0, 0) {
+            return Ok(None);
+        }
+        let opened = |tag: Option<AlignmentLayer>| tag == Some(AlignmentLayer::Matches);
+        let previous = match layer {
+            // The gaps of the first row and column have no tags.
+            AlignmentLayer::Inserts if j == 0 => {
+                Self::cell(AlignmentLayer::Inserts, i - 1, 0, false)
+            }
+            AlignmentLayer::Deletes if i == 0 => {
+                Self::cell(AlignmentLayer::Deletes, 0, j - 1, false)
+            }
+            AlignmentLayer::Inserts => match opened(self.mat.inserts[i][j].1) {
+                true => Self::cell(AlignmentLayer::Matches, i - 1, j, self.no_adjacent_indels),
+                false => Self::cell(AlignmentLayer::Inserts, i - 1, j, false),
+            },
+            AlignmentLayer::Deletes => match opened(self.mat.deletes[i][j].1) {
+                true => Self::cell(AlignmentLayer::Matches, i, j - 1, self.no_adjacent_indels),
+                false => Self::cell(AlignmentLayer::Deletes, i, j - 1, false),
+            },
+            AlignmentLayer::Matches if forced => {
+                Self::cell(AlignmentLayer::Matches, i - 1, j - 1, false)
+            }
+            AlignmentLayer::Matches => match self.mat.matches[i][j].1 {
+                Some(AlignmentLayer::Matches) => {
+                    Self::cell(AlignmentLayer::Matches, i - 1, j - 1, false)
+                }
+                Some(gap) => Self::cell(gap, i, j, false),
+                None => {
+                    return Err(AlignmentError::BacktraceFailed(format!(
+                        "The cell at ({}, {}) of the matches matrix has no predecessor.",
+                        i, j
+                    )))
+                }
+            },
+        };
+        Ok(Some(previous))
+    }
+
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize) {
+        let (layer, i, j, _) = cell;
+        match self.reference {
+            Reference::Second => (layer, i, j),
+            Reference::First => match layer {
+                AlignmentLayer::Inserts => (AlignmentLayer::Deletes, j, i),
+                AlignmentLayer::Deletes => (AlignmentLayer::Inserts, j, i),
+                AlignmentLayer::Matches => (AlignmentLayer::Matches, j, i),
+            },
+        }
+    }
+
+    /// Each diagonal step of the matrices is a single column, whose cost was chosen by the
+    /// fill: mismatch_cost can make any pair of chars a (mis)match.
+    fn can_mismatch(&self, _previous: Self::Cell, _cell: Self::Cell) -> bool {
+        true
+    }
+}
+
+/// If no_adjacent_indels is set, the matrices must have been filled with it: every gap is then
+/// preceded by a (mis)match.
+fn trace_back(
+    mat: &AlignMat,
+    a: &str,
+    b: &str,
+    ends: &EndConfig,
+    no_adjacent_indels: bool,
+) -> AlignResult {
+    trace_back_against(mat, a, b, ends, no_adjacent_indels, Reference::Second)
+}
+
+/// Like trace_back, but the alignment returned has the reference string as its text. ends
+/// are those the matrices were filled with: the query ends are those of a.
+fn trace_back_against(
+    mat: &AlignMat,
+    a: &str,
+    b: &str,
+    ends: &EndConfig,
+    no_adjacent_indels: bool,
+    reference: Reference,
+) -> AlignResult {
+    let mut result = Alignment::new(0, String::new(), String::new());
+
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    // The (query, text) column of a char of a and a char of b.
+    let column = |a_char: Option<char>, b_char: Option<char>| match reference {
+        Reference::Second => (a_char, b_char),
+        Reference::First => (b_char, a_char),
+    };
+
+    let mut a_pos = a_chars.len();
+    let mut b_pos = b_chars.len();
+
+    result.score = mat.matches[a_pos][b_pos].0.unwrap();
+
+    // With free end gaps, the alignment can end on the last column or row.
+    // Ties are broken in favor of the longest alignment.
+    let mut end = (a_pos, b_pos);
+    if ends.query_end_free {
+        for i in (0..a_pos).rev() {
+            if mat.matches[i][b_pos].0.unwrap() < result.score {
+                result.score = mat.matches[i][b_pos].0.unwrap();
+                end = (i, b_pos);
+            }
+        }
+    }
+    if ends.text_end_free {
+        for j in (0..b_pos).rev() {
+            if mat.matches[a_pos][j].0.unwrap() < result.score {
+                result.score = mat.matches[a_pos][j].0.unwrap();
+                end = (a_pos, j);
+            }
+        }
+    }
+    let mut emit = |(q, t): (Option<char>, Option<char>)| {
+        result.query_aligned.push(q.unwrap_or(GAP_CHAR));
+        result.text_aligned.push(t.unwrap_or(GAP_CHAR));
+    };
+    while a_pos > end.0 {
+        a_pos -= 1;
+        emit(column(Some(a_chars[a_pos]), None));
+    }
+    while b_pos > end.1 {
+        b_pos -= 1;
+        emit(column(None, Some(b_chars[b_pos])));
+    }
+
+    let cells = SwgCells {
+        mat,
+        no_adjacent_indels,
+        reference,
+    };
+    let end = SwgCells::cell(AlignmentLayer::Matches, a_pos, b_pos, false);
+    let (query, text) = match reference {
+        Reference::Second => (&a_chars, &b_chars),
+        Reference::First => (&b_chars, &a_chars),
+    };
+    traceback(&cells, end, query, text, |q, t| emit((q, t)))?;
+    let ends = match reference {
+        Reference::Second => *ends,
+        Reference::First => EndConfig {
+            query_start_free: ends.text_start_free,
+            query_end_free: ends.text_end_free,
+            text_start_free: ends.query_start_free,
+            text_end_free: ends.query_end_free,
+        },
+    };
+    Ok(Alignment::new(
+        result.score,
+        result.query_aligned.chars().rev().collect(),
+        result.text_aligned.chars().rev().collect(),
+    )
+    .with_free_ends(&ends))
+}
+
+/// Aligns a and b with every (open_pen, extd_pen) of the grid open_range x extd_range, and
+/// returns the distinct alignments found, along with the first penalties that produced them.
+/// Alignments are compared on their aligned strings only, since the score depends on the
+/// penalties.
+pub fn pareto_alignments(
+    a: &str,
+    b: &str,
+    mismatch_pen: u32,
+    open_range: RangeInclusive<u32>,
+    extd_range: RangeInclusive<u32>,
+) -> Result<Vec<(Penalties, Alignment)>, AlignmentError> {
+    let mut result: Vec<(Penalties, Alignment)> = Vec::new();
+    for open_pen in open_range {
+        for extd_pen in extd_range.clone() {
+            let pens = Penalties {
+                mismatch_pen,
+                open_pen,
+                extd_pen,
+            };
+            let alignment = affine_gap_align(a, b, &pens)?;
+            let is_new = result.iter().all(|(_, seen)| {
+                seen.query_aligned != alignment.query_aligned
+                    || seen.text_aligned != alignment.text_aligned
+            });
+            if is_new {
+                result.push((pens, alignment));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Returns up to k alignments of a and b with the lowest scores, optimal or not, sorted by
+/// score: every alignment within max_delta of the optimal score is a candidate.
+/// Each alignment is a distinct path through Gotoh's 3 states (alignments ending with a
+/// (mis)match, an insertion or a deletion), so the alignments returned have distinct gapped
+/// strings. The paths are searched backwards from the end, best first: a partial path is
+/// ranked by its cost plus the best score of its start, known from the forward DP, so the
+/// complete paths are found in increasing score order and only the partial paths within
+/// the bound are explored.
+pub fn k_best_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    k: usize,
+    max_delta: u32,
+) -> Result<Vec<Alignment>, AlignmentError> {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to k_best_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let (len_a, len_b) = (chars_a.len(), chars_b.len());
+    let gap_open = pens.open_pen as u64 + pens.extd_pen as u64;
+    let gap_extd = pens.extd_pen as u64;
+    let sub = |i: usize, j: usize| match chars_a[i] == chars_b[j] {
+        true => 0,
+        false => pens.mismatch_pen as u64,
+    };
+    const LAYERS: [AlignmentLayer; 3] = [
+        AlignmentLayer::Matches,
+        AlignmentLayer::Inserts,
+        AlignmentLayer::Deletes,
+    ];
+    let index = |layer: AlignmentLayer| match layer {
+        AlignmentLayer::Matches => 0,
+        AlignmentLayer::Inserts => 1,
+        AlignmentLayer::Deletes => 2,
+    };
+    // The states (i, j, layer) that lead to a state in one column, with the cost of the column.
+    let predecessors = |i: usize, j: usize, layer: AlignmentLayer| {
+        let mut result: Vec<(usize, usize, AlignmentLayer, u64)> = Vec::with_capacity(3);
+        match layer {
+            AlignmentLayer::Matches if i > 0 && j > 0 => {
+                for previous in LAYERS {
+                    result.push((i - 1, j - 1, previous, sub(i - 1, j - 1)));
+                }
+            }
+            AlignmentLayer::Inserts if i > 0 => {
+                for previous in LAYERS {
+                    let cost = match previous {
+                        AlignmentLayer::Inserts => gap_extd,
+                        _ => gap_open,
+                    };
+                    result.push((i - 1, j, previous, cost));
+                }
+            }
+            AlignmentLayer::Deletes if j > 0 => {
+                for previous in LAYERS {
+                    let cost = match previous {
+                        AlignmentLayer::Deletes => gap_extd,
+                        _ => gap_open,
+                    };
+                    result.push((i, j - 1, previous, cost));
+                }
+            }
+            _ => {}
+        }
+        result
+    };
+
+    // Best score of the alignments of a[..i] and b[..j] ending in each state, u64::MAX if
+    // there is none.
+    let mut best = vec![vec![[u64::MAX; 3]; len_b + 1]; len_a + 1];
+    best[0][0][0] = 0;
+    for i in 0..=len_a {
+        for j in 0..=len_b {
+            for layer in LAYERS {
+                for (pi, pj, previous, cost) in predecessors(i, j, layer) {
+                    let score = best[pi][pj][index(previous)].saturating_add(cost);
+                    if score < best[i][j][index(layer)] {
+                        best[i][j][index(layer)] = score;
+                    }
+                }
+            }
+        }
+    }
+    let optimum = *best[len_a][len_b].iter().min().unwrap();
+    if optimum > u32::MAX as u64 {
+        return Err(AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            optimum
+        )));
+    }
+    // The scores of the alignments returned must fit in a u32 too.
+    let bound = (optimum + max_delta as u64).min(u32::MAX as u64);
+
+    // The partial paths, as (state, index of the next state towards the end), and the heap
+    // of (estimated score, chars left to reach the origin, index of the path).
+    let mut paths: Vec<((usize, usize, AlignmentLayer), Option<usize>)> = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for layer in LAYERS {
+        let score = best[len_a][len_b][index(layer)];
+        if score <= bound {
+            paths.push(((len_a, len_b, layer), None));
+            heap.push(Reverse((score, len_a + len_b, 0, paths.len() - 1)));
+        }
+    }
+    let mut result = Vec::new();
+    while let Some(Reverse((estimate, _, cost, path))) = heap.pop() {
+        if result.len() == k {
+            break;
+        }
+        let ((i, j, layer), _) = paths[path];
+        if (i, j) == (0, 0) {
+            // Complete: the columns are read from the start to the end.
+            let mut query_aligned = String::new();
+            let mut text_aligned = String::new();
+            let mut next = paths[path].1;
+            while let Some(idx) = next {
+                let ((i, j, layer), following) = paths[idx];
+                query_aligned.push(match layer {
+                    AlignmentLayer::Deletes => GAP_CHAR,
+                    _ => chars_a[i - 1],
+                });
+                text_aligned.push(match layer {
+                    AlignmentLayer::Inserts => GAP_CHAR,
+                    _ => chars_b[j - 1],
+                });
+                next = following;
+            }
+            result.push(Alignment::new(estimate as u32, query_aligned, text_aligned));
+            continue;
+        }
+        for (pi, pj, previous, step) in predecessors(i, j, layer) {
+            let start = best[pi][pj][index(previous)];
+            let estimate = start.saturating_add(cost + step);
+            if start != u64::MAX && estimate <= bound {
+                paths.push(((pi, pj, previous), Some(path)));
+                heap.push(Reverse((estimate, pi + pj, cost + step, paths.len() - 1)));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Performs the worst-case (maximum score) global alignment of two &str.
+/// Every path through the alignment matrix is a valid alignment: this returns the one with the
+/// highest score, which is useful for stress-testing and benchmarking.
+/// Unlike in affine_gap_align, the matches layer only holds paths that end with a (mis)match,
+/// otherwise a gap could be "reopened" from itself to increase the score.
+pub fn worst_align(a: &str, b: &str, pens: &Penalties) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to worst_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+    let a_length = chars_a.len() + 1;
+    let b_length = chars_b.len() + 1;
+
+    // The scores are kept in u64, as the worst score can be far above the optimal one.
+    let gap_open = pens.open_pen as u64 + pens.extd_pen as u64;
+    let gap_extd = pens.extd_pen as u64;
+    // Each cell holds the score and the layer of the previous cell.
+    let mut matches: Vec<Vec<Option<(u64, AlignmentLayer)>>> = vec![vec![None; b_length]; a_length];
+    let mut inserts: Vec<Vec<Option<(u64, AlignmentLayer)>>> = vec![vec![None; b_length]; a_length];
+    let mut deletes: Vec<Vec<Option<(u64, AlignmentLayer)>>> = vec![vec![None; b_length]; a_length];
+    matches[0][0] = Some((0, AlignmentLayer::Matches));
+
+    // Returns the highest-scoring candidate, the first one winning ties.
+    let highest = |candidates: [Option<(u64, AlignmentLayer)>; 3]| {
+        candidates
+            .into_iter()
+            .flatten()
+            .fold(None, |best: Option<(u64, AlignmentLayer)>, c| match best {
+                Some(b) if b.0 >= c.0 => Some(b),
+                _ => Some(c),
+            })
+    };
+    let opened = |cell: Option<(u64, AlignmentLayer)>, layer: AlignmentLayer| {
+        cell.map(|(score, _)| (score + gap_open, layer))
+    };
+    let extended = |cell: Option<(u64, AlignmentLayer)>, layer: AlignmentLayer| {
+        cell.map(|(score, _)| (score + gap_extd, layer))
+    };
+
+    for i in 0..a_length {
+        for j in 0..b_length {
+            if i > 0 {
+                inserts[i][j] = highest([
+                    extended(inserts[i - 1][j], AlignmentLayer::Inserts),
+                    opened(matches[i - 1][j], AlignmentLayer::Matches),
+                    opened(deletes[i - 1][j], AlignmentLayer::Deletes),
+                ]);
+            }
+            if j > 0 {
+                deletes[i][j] = highest([
+                    extended(deletes[i][j - 1], AlignmentLayer::Deletes),
+                    opened(matches[i][j - 1], AlignmentLayer::Matches),
+                    opened(inserts[i][j - 1], AlignmentLayer::Inserts),
+                ]);
+            }
+            if i > 0 && j > 0 {
+                let mismatch = if chars_a[i - 1] == chars_b[j - 1] {
+                    0
+                } else {
+                    pens.mismatch_pen as u64
+                };
+                matches[i][j] = highest([
+                    matches[i - 1][j - 1].map(|x| (x.0, AlignmentLayer::Matches)),
+                    inserts[i - 1][j - 1].map(|x| (x.0, AlignmentLayer::Inserts)),
+                    deletes[i - 1][j - 1].map(|x| (x.0, AlignmentLayer::Deletes)),
+                ])
+                .map(|(score, layer)| (score + mismatch, layer));
+            }
+        }
+    }
+
+    let mut a_pos = chars_a.len();
+    let mut b_pos = chars_b.len();
+    let (score, mut layer) = highest([
+        matches[a_pos][b_pos].map(|x| (x.0, AlignmentLayer::Matches)),
+        inserts[a_pos][b_pos].map(|x| (x.0, AlignmentLayer::Inserts)),
+        deletes[a_pos][b_pos].map(|x| (x.0, AlignmentLayer::Deletes)),
+    ])
+    .unwrap();
+    let score = u32::try_from(score).map_err(|_| {
+        AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        ))
+    })?;
+
+    let mut query_aligned = String::new();
+    let mut text_aligned = String::new();
+    while a_pos > 0 || b_pos > 0 {
+        let previous = match layer {
+            AlignmentLayer::Matches => {
+                a_pos -= 1;
+                b_pos -= 1;
+                query_aligned.push(chars_a[a_pos]);
+                text_aligned.push(chars_b[b_pos]);
+                matches[a_pos + 1][b_pos + 1]
+            }
+            AlignmentLayer::Inserts => {
+                a_pos -= 1;
+                query_aligned.push(chars_a[a_pos]);
+                text_aligned.push(GAP_CHAR);
+                inserts[a_pos + 1][b_pos]
+            }
+            AlignmentLayer::Deletes => {
+                b_pos -= 1;
+                query_aligned.push(GAP_CHAR);
+                text_aligned.push(chars_b[b_pos]);
+                deletes[a_pos][b_pos + 1]
+            }
+        };
+        layer = previous.unwrap().1;
+    }
+
+    Ok(Alignment::new(
+        score,
+        query_aligned.chars().rev().collect(),
+        text_aligned.chars().rev().collect(),
+    ))
+}
+
+/// Performs the SWG alignment of two &str, with the constraint that the chars at each
+/// (a position, b position) anchor are aligned together.
+/// The alignment is split at each anchor: the segments between anchors are aligned separately,
+/// and the results are concatenated.
+/// Anchors must be strictly increasing on both strings.
+pub fn anchored_align(
+    a: &str,
+    b: &str,
+    pens: &Penalties,
+    anchors: &[(usize, usize)],
+) -> AlignResult {
+    if a.is_empty() || b.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to anchored_align had a length of zero.
+                        Length of a: {}
+                        Length of b: {}",
+            a.len(),
+            b.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    let chars_a: Vec<char> = a.chars().collect();
+    let chars_b: Vec<char> = b.chars().collect();
+
+    let mut segments = Vec::new();
+    let mut a_start = 0;
+    let mut b_start = 0;
+    for &(a_pos, b_pos) in anchors {
+        if a_pos < a_start || b_pos < b_start || a_pos >= chars_a.len() || b_pos >= chars_b.len()
+        {
+            return Err(AlignmentError::InvalidAnchors(format!(
+                "The anchor ({}, {}) is out of bounds or not strictly increasing.",
+                a_pos, b_pos
+            )));
+        }
+        segments.push(align_segment(
+            &chars_a[a_start..a_pos],
+            &chars_b[b_start..b_pos],
+            pens,
+        )?);
+        segments.push(Alignment::new(
+            if chars_a[a_pos] == chars_b[b_pos] {
+                0
+            } else {
+                pens.mismatch_pen
+            },
+            chars_a[a_pos].to_string(),
+            chars_b[b_pos].to_string(),
+        ));
+        a_start = a_pos + 1;
+        b_start = b_pos + 1;
+    }
+    segments.push(align_segment(
+        &chars_a[a_start..],
+        &chars_b[b_start..],
+        pens,
+    )?);
+
+    // The anchors can force a score above the optimal one, so it is summed in u64.
+    let score: u64 = segments.iter().map(|segment| segment.score as u64).sum();
+    if score > u32::MAX as u64 {
+        return Err(AlignmentError::ScoreOverflow(format!(
+            "The alignment score is {}, which doesn't fit in a u32.",
+            score
+        )));
+    }
+    Ok(segments.iter().fold(
+        Alignment::new(0, String::new(), String::new()),
+        |result, segment| result.concat(segment),
+    ))
+}
+
+/// Returns the (query start, text start, length) of the longest substring shared by query and
+/// text, in chars, or None if they have no char in common. On ties, the substring starting
+/// first in the query, then in the text, is returned.
+/// Uses the O(n * m) DP of the lengths of the common suffixes, keeping a single row.
+pub fn longest_common_substring(query: &str, text: &str) -> Option<(usize, usize, usize)> {
+    let t_chars: Vec<char> = text.chars().collect();
+    // suffixes[j + 1]: length of the longest common suffix of the query up to the current char
+    // and of text[..=j].
+    let mut suffixes = vec![0; t_chars.len() + 1];
+    let mut best: Option<(usize, usize, usize)> = None;
+    for (i, q) in query.chars().enumerate() {
+        // Iterating backwards, so that suffixes[j] is still the value of the previous char.
+        for j in (0..t_chars.len()).rev() {
+            suffixes[j + 1] = if q == t_chars[j] { suffixes[j] + 1 } else { 0 };
+            let length = suffixes[j + 1];
+            let (query_start, text_start) = (i + 1 - length, j + 1 - length);
+            if length > 0
+                && best.map_or(true, |(best_query, best_text, best_length)| {
+                    length > best_length
+                        || (length == best_length
+                            && (query_start, text_start) < (best_query, best_text))
+                })
+            {
+                best = Some((query_start, text_start, length));
+            }
+        }
+    }
+    best
+}
+
+/// Aligns query and text around their longest common substring: its chars are aligned
+/// together, and the flanks on each side are aligned with SWG. If they have no char in common,
+/// this is the same as affine_gap_align.
+/// This is a heuristic for highly similar strings: the optimal alignment doesn't always go
+/// through the longest common substring, so the result isn't guaranteed to be optimal.
+pub fn align_around_lcs(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    if query.is_empty() || text.is_empty() {
+        return Err(AlignmentError::ZeroLength(format!(
+            "At least one of the string slices passed to align_around_lcs had a length of zero.
+                        Length of query: {}
+                        Length of text:  {}",
+            query.len(),
+            text.len()
+        )));
+    }
+    pens.check_degenerate()?;
+    match longest_common_substring(query, text) {
+        Some((query_start, text_start, length)) => {
+            let anchors: Vec<(usize, usize)> = (0..length)
+                .map(|offset| (query_start + offset, text_start + offset))
+                .collect();
+            anchored_align(query, text, pens, &anchors)
+        }
+        None => affine_gap_align(query, text, pens),
+    }
+}
+
+/// Aligns 2 possibly empty segments: if one of them is empty, the other is aligned to a gap.
+fn align_segment(a: &[char], b: &[char], pens: &Penalties) -> AlignResult {
+    let gap_pen = |len: usize| {
+        if len == 0 {
+            0
+        } else {
+            pens.open_pen as u64 + pens.extd_pen as u64 * len as u64
+        }
+    };
+    if a.is_empty() || b.is_empty() {
+        let score = gap_pen(a.len()) + gap_pen(b.len());
+        let score = u32::try_from(score).map_err(|_| {
+            AlignmentError::ScoreOverflow(format!(
+                "The alignment score is {}, which doesn't fit in a u32.",
+                score
+            ))
+        })?;
+        let gaps = |len: usize| iter::repeat(GAP_CHAR).take(len);
+        Ok(Alignment::new(
+            score,
+            a.iter().copied().chain(gaps(b.len())).collect(),
+            gaps(a.len()).chain(b.iter().copied()).collect(),
+        ))
+    } else {
+        affine_gap_align(
+            &a.iter().collect::<String>(),
+            &b.iter().collect::<String>(),
+            pens,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assert_align_score() {
+        assert_eq!(
+            affine_gap_align(
+                "CAT",
+                "CAT",
+                &Penalties {
+                    mismatch_pen: 1,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ),
+            Ok(Alignment::new(0, "CAT".to_string(), "CAT".to_string()))
+        );
+        assert_eq!(
+            affine_gap_align(
+                "CAT",
+                "CATS",
+                &Penalties {
+                    mismatch_pen: 1,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ),
+            Ok(Alignment::new(2, "CAT-".to_string(), "CATS".to_string()))
+        );
+        assert_eq!(
+            affine_gap_align(
+                "XX",
+                "YY",
+                &Penalties {
+                    mismatch_pen: 1,
+                    extd_pen: 100,
+                    open_pen: 100,
+                }
+            ),
+            Ok(Alignment::new(2, "XX".to_string(), "YY".to_string()))
+        );
+        assert_eq!(
+            affine_gap_align(
+                "XX",
+                "YY",
+                &Penalties {
+                    mismatch_pen: 100,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ),
+            Ok(Alignment::new(6, "XX--".to_string(), "--YY".to_string()))
+        );
+        assert_eq!(
+            affine_gap_align(
+                "XX",
+                "YYYYYYYY",
+                &Penalties {
+                    mismatch_pen: 100,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ),
+            Ok(Alignment::new(
+                12,
+                "XX--------".to_string(),
+                "--YYYYYYYY".to_string()
+            ))
+        );
+        assert_eq!(
+            affine_gap_align(
+                "XXZZ",
+                "XXYZ",
+                &Penalties {
+                    mismatch_pen: 100,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ),
+            Ok(Alignment::new(4, "XX-ZZ".to_string(), "XXYZ-".to_string()))
+        );
+        assert_eq!(
+            match affine_gap_align(
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
+                &Penalties {
+                    mismatch_pen: 1,
+                    extd_pen: 1,
+                    open_pen: 1,
+                }
+            ) {
+                Ok(s) => s.score,
+                _ => 1,
+            },
+            6
+        );
+
+        assert_eq!(
+            match affine_gap_align(
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
+                &Penalties {
+                    mismatch_pen: 135,
+                    extd_pen: 19,
+                    open_pen: 82,
+                }
+            ) {
+                Ok(s) => s.score,
+                _ => 1,
+            },
+            472
+        );
+    }
+
+    #[test]
+    fn test_worst_align() {
+        // Matching A with A costs 0, while the worst path opens a gap on each side:
+        // 2 * (open_pen + extd_pen).
+        let pens = Penalties {
+            mismatch_pen: 1,
+            extd_pen: 1,
+            open_pen: 1,
+        };
+        assert_eq!(
+            worst_align("A", "A", &pens),
+            Ok(Alignment::new(4, "-A".to_string(), "A-".to_string()))
+        );
+
+        // AB/CD: 2 mismatches = 2, while alternating 4 single-char gaps costs 4 * 2.
+        assert_eq!(worst_align("AB", "CD", &pens).unwrap().score, 8);
+
+        assert!(matches!(
+            worst_align(
+                "AB",
+                "CD",
+                &Penalties {
+                    extd_pen: 0,
+                    ..pens
+                }
+            ),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // 2 gaps of open_pen: the worst score doesn't fit in a u32.
+        assert!(matches!(
+            worst_align(
+                "A",
+                "A",
+                &Penalties {
+                    open_pen: u32::MAX / 2 + 1,
+                    ..pens
+                }
+            ),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_anchored_align() {
+        let pens = Penalties {
+            mismatch_pen: 1,
+            extd_pen: 1,
+            open_pen: 1,
+        };
+        assert_eq!(
+            affine_gap_align("AC", "ABC", &pens),
+            Ok(Alignment::new(2, "A-C".to_string(), "ABC".to_string()))
+        );
+        // Forcing the C to be aligned with the B.
+        assert_eq!(
+            anchored_align("AC", "ABC", &pens, &[(1, 1)]),
+            Ok(Alignment::new(3, "AC-".to_string(), "ABC".to_string()))
+        );
+        assert!(matches!(
+            anchored_align("AC", "ABC", &pens, &[(1, 1), (0, 2)]),
+            Err(AlignmentError::InvalidAnchors(_))
+        ));
+        assert!(matches!(
+            anchored_align("", "ABC", &pens, &[]),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+        assert!(matches!(
+            anchored_align(
+                "AC",
+                "ABC",
+                &Penalties {
+                    extd_pen: 0,
+                    ..pens
+                },
+                &[(1, 1)]
+            ),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // The 2 gaps forced by the anchor don't fit in a u32, while a single longer gap would.
+        let pens = Penalties {
+            open_pen: u32::MAX / 2,
+            ..pens
+        };
+        assert!(affine_gap_align("AXC", "ABXBC", &pens).is_ok());
+        assert!(matches!(
+            anchored_align("AXC", "ABXBC", &pens, &[(1, 2)]),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_align_ends() {
+        let pens = Penalties {
+            mismatch_pen: 2,
+            extd_pen: 1,
+            open_pen: 2,
+        };
+        for (a, b) in [("CAT", "GGCATGG"), ("GGGCAT", "CATTTT"), ("XXZZ", "XXYZ")] {
+            assert_eq!(
+                align_ends(a, b, &pens, &EndConfig::global()),
+                affine_gap_align(a, b, &pens)
+            );
+        }
+        // The coordinates leave out the chars skipped by the free end gaps.
+        let semi_global = align_ends("CAT", "GGCATGG", &pens, &EndConfig::semi_global()).unwrap();
+        assert_eq!(
+            semi_global,
+            Alignment {
+                text_start: 2,
+                text_end: 5,
+                ..Alignment::new(0, "--CAT--".to_string(), "GGCATGG".to_string())
+            }
+        );
+        assert_eq!(semi_global.aligned_len(), 3);
+        assert_eq!(
+            align_ends("CAT", "GGCTTGG", &pens, &EndConfig::semi_global()),
+            Ok(Alignment {
+                text_start: 2,
+                text_end: 5,
+                ..Alignment::new(2, "--CAT--".to_string(), "GGCTTGG".to_string())
+            })
+        );
+        assert_eq!(
+            align_ends("GGGCAT", "CATTTT", &pens, &EndConfig::overlap()),
+            Ok(Alignment {
+                query_start: 3,
+                text_end: 3,
+                ..Alignment::new(0, "GGGCAT---".to_string(), "---CATTTT".to_string())
+            })
+        );
+        assert_eq!(
+            align_ends("CATGGG", "GGGCAT", &pens, &EndConfig::overlap()),
+            Ok(Alignment {
+                query_start: 3,
+                text_end: 3,
+                ..Alignment::new(0, "CATGGG---".to_string(), "---GGGCAT".to_string())
+            })
+        );
+        // Only the end gaps are skipped: a gap inside the alignment is part of it.
+        let alignment =
+            align_ends("CATAAAGG", "GGCATGGTT", &pens, &EndConfig::semi_global()).unwrap();
+        assert_eq!(alignment.query_aligned, "--CATAAAGG--");
+        assert_eq!((alignment.query_start, alignment.query_end), (0, 8));
+        assert_eq!((alignment.text_start, alignment.text_end), (2, 7));
+        assert_eq!(alignment.aligned_len(), 8);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_affine_gap_align_banded() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use crate::wavefront_alignment::{wavefront_align, wavefront_align_banded};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(593);
+        for _ in 0..200 {
+            let query_length = rng.gen_range(1..20);
+            let text_length = query_length + rng.gen_range(0..5);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let band = 2;
+            let banded = affine_gap_align_banded(&query, &text, &pens, band).unwrap();
+            assert_eq!(
+                banded.score,
+                wavefront_align_banded(&query, &text, &pens, band)
+                    .unwrap()
+                    .score
+            );
+            assert!(banded.score >= wavefront_align(&query, &text, &pens).unwrap().score);
+
+            // A band covering every diagonal gives the optimal alignment.
+            assert_eq!(
+                affine_gap_align_banded(&query, &text, &pens, 30)
+                    .unwrap()
+                    .score,
+                affine_gap_align(&query, &text, &pens).unwrap().score
+            );
+        }
+    }
+
+    #[test]
+    fn test_wobble_align() {
+        let pens = Penalties {
+            mismatch_pen: 6,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        // Without wobble, gapping around the G-U pair is cheaper than the mismatch.
+        let plain = wobble_align("GG", "UG", &pens, false, 1).unwrap();
+        assert_eq!(plain.score, 4);
+        assert_eq!(plain, affine_gap_align("GG", "UG", &pens).unwrap());
+
+        let wobble = wobble_align("GG", "UG", &pens, true, 1).unwrap();
+        assert_eq!(
+            wobble,
+            Alignment::new(1, "GG".to_string(), "UG".to_string())
+        );
+    }
+
+    #[test]
+    fn test_strict_indels_align() {
+        let pens = Penalties {
+            mismatch_pen: 5,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        // A mismatch costs more than an insertion next to a deletion.
+        let default = strict_indels_align("GATTACA", "GCTTACA", &pens, false).unwrap();
+        assert_eq!(default.score, 4);
+        assert_eq!(
+            default,
+            affine_gap_align("GATTACA", "GCTTACA", &pens).unwrap()
+        );
+
+        let strict = strict_indels_align("GATTACA", "GCTTACA", &pens, true).unwrap();
+        assert_eq!(
+            strict,
+            Alignment::new(5, "GATTACA".to_string(), "GCTTACA".to_string())
+        );
+
+        // Gaps that aren't next to each other are still allowed.
+        let strict = strict_indels_align("GATTACA", "GTTACCA", &pens, true).unwrap();
+        assert_eq!(strict.score, 4);
+        assert_eq!(strict.recompute_score(&pens), 4);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_max_gap_align() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        // Deleting TT and inserting AA is the optimum, but without gaps the mismatches remain.
+        let unconstrained = affine_gap_align("CCCCGGGGAAAA", "CCCCTTGGGGAA", &pens).unwrap();
+        assert_eq!(unconstrained.score, 8);
+        assert_eq!(
+            max_gap_align("CCCCGGGGAAAA", "CCCCTTGGGGAA", &pens, 2)
+                .unwrap()
+                .score,
+            8
+        );
+        assert_eq!(
+            max_gap_align("CCCCGGGGAAAA", "CCCCTTGGGGAA", &pens, 0),
+            Ok(Alignment::new(
+                16,
+                "CCCCGGGGAAAA".to_string(),
+                "CCCCTTGGGGAA".to_string()
+            ))
+        );
+        assert!(matches!(
+            max_gap_align("GATTACA", "GATTTACA", &pens, 0),
+            Err(AlignmentError::NoAlignment(_))
+        ));
+
+        let longest_gap = |alignment: &Alignment| {
+            let gaps = |aligned: &str| aligned.split(|c| c != GAP_CHAR).map(str::len).max();
+            gaps(&alignment.query_aligned)
+                .max(gaps(&alignment.text_aligned))
+                .unwrap_or(0)
+        };
+        // The optimum inserts GGG and deletes TTT. With gaps of at most 2 chars, a G is
+        // mismatched instead.
+        let unconstrained = affine_gap_align("GGGACGTCAGT", "ACGTCAGTTTT", &pens).unwrap();
+        assert_eq!(unconstrained.score, 10);
+        assert_eq!(longest_gap(&unconstrained), 3);
+        let constrained = max_gap_align("GGGACGTCAGT", "ACGTCAGTTTT", &pens, 2).unwrap();
+        assert_eq!(constrained.score, 18);
+        assert_eq!(longest_gap(&constrained), 2);
+        assert!(constrained
+            .query_aligned
+            .chars()
+            .zip(constrained.text_aligned.chars())
+            .any(|(q, t)| q != t && q != GAP_CHAR && t != GAP_CHAR));
+        assert!(constrained.is_score_consistent(&pens));
+
+        let mut rng = StdRng::seed_from_u64(666);
+        for _ in 0..100 {
+            let a_length = rng.gen_range(1..30);
+            let a = random_sequence(&mut rng, a_length, &DNA_BASES);
+            let b_length = rng.gen_range(1..30);
+            let b = random_sequence(&mut rng, b_length, &DNA_BASES);
+            let optimum = affine_gap_align(&a, &b, &pens).unwrap();
+            // Without a binding limit, the score is the SWG one.
+            let unbounded = max_gap_align(&a, &b, &pens, 30).unwrap();
+            assert_eq!(unbounded.score, optimum.score);
+            let max_gap_len = rng.gen_range(1..4);
+            if let Ok(alignment) = max_gap_align(&a, &b, &pens, max_gap_len) {
+                assert!(alignment.score >= optimum.score);
+                assert!(longest_gap(&alignment) <= max_gap_len as usize);
+                assert!(alignment.is_score_consistent(&pens));
+                assert_eq!(alignment.query_aligned.replace('-', ""), a);
+                assert_eq!(alignment.text_aligned.replace('-', ""), b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_align_with_repeat_units() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // The extra copy of CAG costs a gap of 1 char instead of 3.
+        assert_eq!(
+            affine_gap_align("AATCAGCAGCAGGGA", "AATCAGCAGGGA", &pens)
+                .unwrap()
+                .score,
+            12
+        );
+        let alignment =
+            align_with_repeat_units("AATCAGCAGCAGGGA", "AATCAGCAGGGA", &pens, "CAG").unwrap();
+        assert_eq!(alignment.score, 8);
+        assert_eq!(alignment.query_aligned, "AATCAGCAGCAGGGA");
+        assert_eq!(alignment.text_aligned.replace('-', ""), "AATCAGCAGGGA");
+        assert_eq!(alignment.text_aligned.matches("---").count(), 1);
+        // Each copy is a gap of its own.
+        let alignment =
+            align_with_repeat_units("AATCAGGGA", "AATCAGCAGCAGGGA", &pens, "CAG").unwrap();
+        assert_eq!(alignment.score, 16);
+
+        // A copy that isn't part of a repeat is a regular gap.
+        assert_eq!(
+            align_with_repeat_units("AATCAGGGA", "AATGGA", &pens, "CAG"),
+            affine_gap_align("AATCAGGGA", "AATGGA", &pens)
+        );
+        // Without copies of the unit, this is affine_gap_align.
+        assert_eq!(
+            align_with_repeat_units("GATTACA", "GCATTAGA", &pens, "CAG")
+                .unwrap()
+                .score,
+            affine_gap_align("GATTACA", "GCATTAGA", &pens)
+                .unwrap()
+                .score
+        );
+        assert!(matches!(
+            align_with_repeat_units("GATTACA", "GATTACA", &pens, ""),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+
+        // A gap penalty close to u32::MAX: one unit gap fits in a u32, two don't.
+        let pens = Penalties {
+            open_pen: u32::MAX - 10,
+            ..pens
+        };
+        let alignment =
+            align_with_repeat_units("AATCAGCAGCAGGGA", "AATCAGCAGGGA", &pens, "CAG").unwrap();
+        assert_eq!(alignment.score, u32::MAX - 8);
+        assert!(matches!(
+            align_with_repeat_units("AATCAGCAGCAGGGA", "AATCAGGGA", &pens, "CAG"),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_score_with_gap_mask() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mask = |columns: &str| columns.chars().map(|c| c == 'G').collect::<Vec<bool>>();
+        // The optimal alignment has its gap at the 5th column: the mask moves it to the start,
+        // shifting the pairs that follow.
+        let alignment =
+            score_with_gap_mask("ACGTTACG", "ACGTACG", &mask("PPPPGPPP"), &pens).unwrap();
+        assert_eq!(alignment.score, 8);
+        let alignment =
+            score_with_gap_mask("ACGTTACG", "ACGTACG", &mask("GPPPPPPP"), &pens).unwrap();
+        assert_eq!(alignment.query_aligned, "ACGTTACG");
+        assert_eq!(alignment.text_aligned, "-ACGTACG");
+        assert_eq!(alignment.score, 3 * 4 + 8);
+        assert!(alignment.is_score_consistent(&pens));
+
+        // The side of each gap is chosen to minimize the mismatches.
+        let alignment = score_with_gap_mask("ACGT", "CGTA", &mask("GPPPG"), &pens).unwrap();
+        assert_eq!(alignment.query_aligned, "ACGT-");
+        assert_eq!(alignment.text_aligned, "-CGTA");
+        assert_eq!(alignment.score, 16);
+        let alignment = score_with_gap_mask("CGTA", "ACGT", &mask("GPPPG"), &pens).unwrap();
+        assert_eq!(alignment.query_aligned, "-CGTA");
+        assert_eq!(alignment.text_aligned, "ACGT-");
+
+        // Without gaps, the score only counts the mismatches.
+        assert_eq!(
+            score_with_gap_mask("GATTACA", "GACTATA", &mask("PPPPPPP"), &pens)
+                .unwrap()
+                .score,
+            8
+        );
+        assert!(matches!(
+            score_with_gap_mask("ACGTTACG", "ACGTACG", &mask("PPPPPPPP"), &pens),
+            Err(AlignmentError::LengthMismatch(_))
+        ));
+        assert!(matches!(
+            score_with_gap_mask("ACGT", "ACGT", &mask("PPPPG"), &pens),
+            Err(AlignmentError::LengthMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_local_align_all() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // Two similarities, in opposite orders: no single local alignment covers both.
+        let (first, second) = ("GATTACATCGGTCA", "CCGTAGGCTAG");
+        let a = format!("AAAAA{}CCCCCCCC{}AAAA", first, second);
+        let b = format!("TTT{}GGGGGGG{}TTTTTT", second, first);
+        let results = local_align_all(&a, &b, &pens, 2, 15, 10).unwrap();
+        assert_eq!(results.len(), 2);
+        let (similarity, alignment) = &results[0];
+        assert_eq!(*similarity, 28);
+        assert_eq!((alignment.query_start, alignment.query_end), (5, 19));
+        assert_eq!((alignment.text_start, alignment.text_end), (21, 35));
+        assert_eq!(alignment.score, 0);
+        assert_eq!(alignment.aligned_len(), first.len());
+        assert_eq!(alignment.query_aligned.replace('-', ""), a);
+        assert_eq!(alignment.text_aligned.replace('-', ""), b);
+        let (similarity, alignment) = &results[1];
+        assert_eq!(*similarity, 22);
+        assert_eq!((alignment.query_start, alignment.query_end), (27, 38));
+        assert_eq!((alignment.text_start, alignment.text_end), (3, 14));
+
+        // The results are bounded by max_results and min_score.
+        assert_eq!(local_align_all(&a, &b, &pens, 2, 15, 1).unwrap().len(), 1);
+        assert_eq!(local_align_all(&a, &b, &pens, 2, 25, 10).unwrap().len(), 1);
+        // A mismatch inside a match is kept if the chars around it make up for it.
+        let results =
+            local_align_all("CCGATTACATGATTACAGG", "TTGATTACAGGATTACATT", &pens, 2, 1, 1).unwrap();
+        assert_eq!(results[0].0, 2 * 14 - 4);
+        assert_eq!(results[0].1.score, 4);
+        assert!(matches!(
+            local_align_all("", "GATTACA", &pens, 2, 1, 1),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[test]
+    fn test_pareto_alignments() {
+        let alignments = pareto_alignments("GATTACA", "GCATTAGA", 4, 0..=6, 1..=3).unwrap();
+        assert!(alignments.len() >= 2);
+        for (i, (pens, alignment)) in alignments.iter().enumerate() {
+            assert_eq!(
+                alignment,
+                &affine_gap_align("GATTACA", "GCATTAGA", pens).unwrap()
+            );
+            for (_, other) in &alignments[i + 1..] {
+                assert_ne!(
+                    (&alignment.query_aligned, &alignment.text_aligned),
+                    (&other.query_aligned, &other.text_aligned)
+                );
+            }
+        }
+        assert!(matches!(
+            pareto_alignments("", "GCATTAGA", 4, 0..=6, 1..=3),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[test]
+    fn test_affine_gap_matrices() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mats = affine_gap_matrices("AC", "AG", &pens).unwrap();
+        assert_eq!(mats.matches.len(), 3);
+        assert_eq!(mats.matches[0].len(), 3);
+        assert_eq!(mats.matches[0][0], Some(0));
+        assert_eq!(mats.inserts[0][0], None);
+        assert_eq!(mats.inserts[1][0], Some(8));
+        assert_eq!(mats.deletes[0][2], Some(10));
+        assert_eq!(mats.matches[1][1], Some(0));
+        // Opening a deletion after the A-A match is cheaper than extending one from the start.
+        assert_eq!(mats.deletes[1][2], Some(8));
+        assert_eq!(
+            mats.matches[2][2],
+            Some(affine_gap_align("AC", "AG", &pens).unwrap().score as i32)
+        );
+        assert_eq!(mats.matches[2][2], Some(4));
+        assert!(matches!(
+            affine_gap_matrices("", "AG", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+        assert!(matches!(
+            affine_gap_matrices(
+                "AC",
+                "AG",
+                &Penalties {
+                    extd_pen: 0,
+                    ..pens
+                }
+            ),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // The optimal score fits in an i32, but not that of the gaps.
+        assert!(matches!(
+            affine_gap_matrices(
+                "AC",
+                "AG",
+                &Penalties {
+                    open_pen: i32::MAX as u32,
+                    ..pens
                 }
-                (Some(a), None, None) => (Some(a + mismatch), Some(AlignmentLayer::Matches)),
-                (None, Some(b), None) => (Some(b), Some(AlignmentLayer::Deletes)),
-                (None, None, Some(c)) => (Some(c), Some(AlignmentLayer::Inserts)),
-                (None, None, None) => panic!("(None, None, None), result.matches"),
+            ),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_affine_gap_align_linear_space() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            affine_gap_align_linear_space("GATTACA", "GATTACA", &pens),
+            affine_gap_align("GATTACA", "GATTACA", &pens)
+        );
+        assert!(matches!(
+            affine_gap_align_linear_space("", "GATTACA", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+
+        let mut rng = StdRng::seed_from_u64(619);
+        for _ in 0..2000 {
+            let query_length = rng.gen_range(1..30);
+            let text_length = rng.gen_range(1..30);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(0..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(0..10),
             };
+            if pens.check_degenerate().is_err() {
+                continue;
+            }
+            let alignment = affine_gap_align_linear_space(&query, &text, &pens).unwrap();
+            assert_eq!(
+                alignment.score,
+                affine_gap_align(&query, &text, &pens).unwrap().score,
+                "{} {} {:?}",
+                query,
+                text,
+                pens
+            );
+            assert_eq!(alignment.recompute_score(&pens), alignment.score);
+            assert_eq!(alignment.query_aligned.replace('-', ""), query);
+            assert_eq!(alignment.text_aligned.replace('-', ""), text);
         }
     }
-    result
-}
 
-fn new_mat(a: &str, b: &str, pens: &Penalties) -> AlignMat {
-    let a_length = a.len() + 1;
-    let b_length = b.len() + 1;
+    #[test]
+    fn test_degenerate_penalties() {
+        let zero = Penalties {
+            mismatch_pen: 0,
+            open_pen: 0,
+            extd_pen: 0,
+        };
+        assert!(matches!(
+            affine_gap_align("GATTACA", "GCATGCU", &zero),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        assert!(matches!(
+            affine_gap_align_linear_space("GATTACA", "GCATGCU", &zero),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // Gaps of any length have the same cost: every SWG entry point rejects it, even for
+        // strings aligned without gaps.
+        let zero_extension = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 0,
+        };
+        for result in [
+            affine_gap_align("GATTACA", "GATTACA", &zero_extension),
+            affine_gap_align_linear_space("GATTACA", "GCATGCU", &zero_extension),
+            affine_gap_align_banded("GATTACA", "GCATGCU", &zero_extension, 2),
+            align_ends("GATTACA", "GCATGCU", &zero_extension, &EndConfig::global()),
+            indel_only_align("GATTACA", "GCATGCU", &zero_extension),
+            align_around_lcs("GATTACA", "GCATGCU", &zero_extension),
+        ] {
+            assert!(matches!(
+                result,
+                Err(AlignmentError::DegeneratePenalties(_))
+            ));
+        }
+    }
 
-    let mut inserts = vec![vec![(None, None); b_length]; a_length];
-    let mut matches = vec![vec![(None, None); b_length]; a_length];
-    let mut deletes = vec![vec![(None, None); b_length]; a_length];
+    #[test]
+    fn test_align_around_lcs() {
+        assert_eq!(
+            longest_common_substring("GATTACA", "TTACG"),
+            Some((2, 0, 4))
+        );
+        assert_eq!(longest_common_substring("ACGT", "TGCA"), Some((0, 3, 1)));
+        assert_eq!(longest_common_substring("AAAA", "CCCC"), None);
+        assert_eq!(longest_common_substring("ééA", "Aéé"), Some((0, 1, 2)));
 
-    matches[0][0] = (Some(0), None);
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let core = "CTGACCTAGGATCGATTGCAAGT";
+        let query = format!("AC{}TTG", core);
+        let text = format!("GGACT{}TAGC", core);
+        let alignment = align_around_lcs(&query, &text, &pens).unwrap();
+        assert_eq!(
+            alignment.score,
+            affine_gap_align(&query, &text, &pens).unwrap().score
+        );
+        assert_eq!(alignment.recompute_score(&pens), alignment.score);
+        assert!(alignment.query_aligned.contains(core) && alignment.text_aligned.contains(core));
 
-    inserts[1][0] = (
-        Some(pens.extd_pen + pens.open_pen),
-        Some(AlignmentLayer::Matches),
-    );
-    matches[1][0] = inserts[1][0];
-    for i in 2..a_length {
-        inserts[i][0] = (
-            Some(inserts[i - 1][0].0.unwrap() + pens.extd_pen),
-            Some(AlignmentLayer::Inserts),
+        assert_eq!(
+            align_around_lcs("AAAA", "CCCC", &pens),
+            affine_gap_align("AAAA", "CCCC", &pens)
         );
-        matches[i][0] = inserts[i][0];
+        assert!(matches!(
+            align_around_lcs("", "CCCC", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
     }
 
-    deletes[0][1] = (
-        Some(pens.extd_pen + pens.open_pen),
-        Some(AlignmentLayer::Matches),
-    );
-    matches[0][1] = deletes[0][1];
-    for i in 2..b_length {
-        deletes[0][i] = (
-            Some(deletes[0][i - 1].0.unwrap() + pens.extd_pen),
-            Some(AlignmentLayer::Deletes),
+    #[test]
+    fn test_huge_open_pen() {
+        // Opening a gap overflows a u32, but the optimal alignment is a single mismatch.
+        for open_pen in [u32::MAX - 1, u32::MAX / 2, 3_000_000_000] {
+            let pens = Penalties {
+                mismatch_pen: 4,
+                open_pen,
+                extd_pen: 2,
+            };
+            let expected = Alignment::new(4, "GATTACA".to_string(), "GATCACA".to_string());
+            assert_eq!(
+                affine_gap_align("GATTACA", "GATCACA", &pens),
+                Ok(expected.clone())
+            );
+            assert_eq!(
+                affine_gap_align_banded("GATTACA", "GATCACA", &pens, 2),
+                Ok(expected.clone())
+            );
+            assert_eq!(
+                align_ends("GATTACA", "GATCACA", &pens, &EndConfig::global()),
+                Ok(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_affine_gap_align_with_reference() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let second =
+            affine_gap_align_with_reference("GATTACA", "GATCA", &pens, Reference::Second).unwrap();
+        let first =
+            affine_gap_align_with_reference("GATTACA", "GATCA", &pens, Reference::First).unwrap();
+        assert_eq!(first.score, second.score);
+        assert_eq!(first.recompute_score(&pens), first.score);
+        assert_eq!(first, second.swap());
+        assert_eq!(second.query_aligned.replace('-', ""), "GATTACA");
+        assert_eq!(first.query_aligned.replace('-', ""), "GATCA");
+        // The longer first string has insertions (gaps in the text) when the second one is the
+        // reference, and deletions (gaps in the query) when it is the reference itself.
+        assert!(second.text_aligned.contains('-') && !second.query_aligned.contains('-'));
+        assert!(first.query_aligned.contains('-') && !first.text_aligned.contains('-'));
+    }
+
+    #[test]
+    fn test_all_vs_all() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let seqs = ["GATTACAGGCAT", "GGCATTTCAGA", "CCCCTCCCC"];
+        let overlaps = all_vs_all(&seqs, &pens, 4, 4);
+        assert_eq!(
+            overlaps,
+            vec![(
+                0,
+                1,
+                Alignment {
+                    query_start: 7,
+                    text_end: 5,
+                    ..Alignment::new(
+                        0,
+                        "GATTACAGGCAT------".to_string(),
+                        "-------GGCATTTCAGA".to_string()
+                    )
+                }
+            )]
         );
-        matches[0][i] = deletes[0][i];
+        // Without the min_overlap filter, every pair has an overlap of score 0, which can be
+        // empty. A sequence is never aligned with itself.
+        let all = all_vs_all(&seqs, &pens, 0, 0);
+        assert_eq!(all.len(), 6);
+        assert!(all.iter().all(|(i, j, _)| i != j));
+        assert_eq!(all_vs_all(&["GATTACA", "GATTACA"], &pens, 0, 7).len(), 2);
+        assert!(all_vs_all(&["GATTACA", ""], &pens, 100, 0).is_empty());
     }
 
-    AlignMat {
-        inserts,
-        matches,
-        deletes,
+    #[test]
+    fn test_ungapped_best_offset() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            ungapped_best_offset("GATTACA", "CCGATCACATT", &pens),
+            Ok((
+                2,
+                Alignment {
+                    text_start: 2,
+                    text_end: 9,
+                    ..Alignment::new(4, "--GATTACA--".to_string(), "CCGATCACATT".to_string())
+                }
+            ))
+        );
+        // Strings of the same length can only be aligned with the shift 0.
+        let (offset, alignment) = ungapped_best_offset("GATTACA", "GATACAT", &pens).unwrap();
+        assert_eq!((offset, alignment.score), (0, 16));
+        assert_eq!(
+            ungapped_best_offset("TTGATTACAGG", "GATTACA", &pens),
+            Ok((
+                -2,
+                Alignment {
+                    query_start: 2,
+                    query_end: 9,
+                    ..Alignment::new(0, "TTGATTACAGG".to_string(), "--GATTACA--".to_string())
+                }
+            ))
+        );
+        // Ties are broken in favor of the lowest shift.
+        assert_eq!(ungapped_best_offset("AAAA", "AAAAAA", &pens).unwrap().0, 0);
+        assert!(matches!(
+            ungapped_best_offset("", "GATTACA", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
     }
-}
 
-fn trace_back(mat: &AlignMat, a: &str, b: &str) -> Result<Alignment, AlignmentError> {
-    let mut result = Alignment {
-        query_aligned: String::new(),
-        text_aligned: String::new(),
-        score: 0,
-    };
+    #[test]
+    fn test_align_windows() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let text = "TTTGATTACAGGGCATCCC";
+        let windows = align_windows("GATTACAGTCAT", text, &pens, 5, 3);
+        let starts: Vec<usize> = windows.iter().map(|(start, _)| *start).collect();
+        assert_eq!(starts, vec![0, 3, 6]);
+        for (start, alignment) in &windows {
+            assert_eq!(
+                alignment,
+                &align_ends(
+                    &"GATTACAGTCAT"[*start..start + 5],
+                    text,
+                    &pens,
+                    &EndConfig::semi_global()
+                )
+            );
+        }
+        let (_, first) = &windows[0];
+        assert_eq!(first.as_ref().unwrap().score, 0);
+        assert_eq!(first.as_ref().unwrap().query_aligned, "---GATTA-----------");
 
-    let mut a_pos = a.len();
-    let mut b_pos = b.len();
+        // The last window ends exactly at the end of the query.
+        assert_eq!(align_windows("GATTACA", text, &pens, 4, 3).len(), 2);
+        assert!(align_windows("GAT", text, &pens, 4, 1).is_empty());
+    }
 
-    let a_chars: Vec<char> = a.chars().collect();
-    let b_chars: Vec<char> = b.chars().collect();
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_gap_model_align() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
 
-    let mut layer = AlignmentLayer::Matches;
-    result.score = mat.matches[a_pos][b_pos].0.unwrap();
+        let mut rng = StdRng::seed_from_u64(643);
+        for _ in 0..200 {
+            let query_length = rng.gen_range(1..20);
+            let text_length = query_length + rng.gen_range(0..5);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            assert_eq!(
+                gap_model_align(&query, &text, pens.mismatch_pen, &AffineGap::from(&pens)),
+                affine_gap_align(&query, &text, &pens)
+            );
+        }
 
-    while (a_pos > 0) || (b_pos > 0) {
-        if a_pos == 0 {
-            b_pos -= 1;
-            result.query_aligned.push('-');
-            result.text_aligned.push(b_chars[b_pos]);
-        } else if b_pos == 0 {
-            a_pos -= 1;
-            result.query_aligned.push(a_chars[a_pos]);
-            result.text_aligned.push('-');
-        } else {
-            match &mut layer {
-                AlignmentLayer::Inserts => {
-                    result.query_aligned.push(a_chars[a_pos - 1]);
-                    result.text_aligned.push('-');
-                    if let Some(AlignmentLayer::Matches) = mat.inserts[a_pos][b_pos].1 {
-                        layer = AlignmentLayer::Matches;
-                    };
-                    a_pos -= 1;
-                }
-                AlignmentLayer::Matches => match mat.matches[a_pos][b_pos].1 {
-                    Some(AlignmentLayer::Matches) => {
-                        a_pos -= 1;
-                        b_pos -= 1;
-                        result.query_aligned.push(a_chars[a_pos]);
-                        result.text_aligned.push(b_chars[b_pos]);
-                    }
-                    Some(AlignmentLayer::Inserts) => {
-                        layer = AlignmentLayer::Inserts;
-                    }
-                    Some(AlignmentLayer::Deletes) => {
-                        layer = AlignmentLayer::Deletes;
-                    }
-                    _ => panic!(),
-                },
-                AlignmentLayer::Deletes => {
-                    result.query_aligned.push('-');
-                    result.text_aligned.push(b_chars[b_pos - 1]);
-                    if let Some(AlignmentLayer::Matches) = mat.deletes[a_pos][b_pos].1 {
-                        layer = AlignmentLayer::Matches;
-                    };
-                    b_pos -= 1;
+        // With a gap cost that grows slower than the length, a single long gap beats the
+        // mismatches it avoids.
+        struct LogGap;
+        impl GapModel for LogGap {
+            fn open_cost(&self) -> u32 {
+                4
+            }
+
+            fn extend_cost(&self) -> u32 {
+                1
+            }
+
+            fn gap_cost(&self, run_length: u32) -> u32 {
+                match run_length {
+                    0 => 0,
+                    len => self.open_cost() + len.ilog2(),
                 }
             }
         }
+        let alignment = gap_model_align("AAAAGGGG", "AAAACCCCCCCCGGGG", 1, &LogGap).unwrap();
+        assert_eq!(alignment.query_aligned, "AAAA--------GGGG");
+        assert_eq!(alignment.score, 7);
+        assert!(matches!(
+            gap_model_align(
+                "GATTACA",
+                "GATCACA",
+                0,
+                &AffineGap::from(&Penalties {
+                    mismatch_pen: 0,
+                    open_pen: 0,
+                    extd_pen: 0,
+                })
+            ),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
     }
-    result.query_aligned = result.query_aligned.chars().rev().collect();
-    result.text_aligned = result.text_aligned.chars().rev().collect();
-    Ok(result)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    fn assert_align_score() {
+    fn test_indel_only_align() {
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        // The substitution becomes a deletion and an insertion.
+        let alignment = indel_only_align("GATTACA", "GATCACA", &pens).unwrap();
+        assert_eq!(alignment.score, 6);
+        assert_eq!(alignment.query_aligned.replace('-', ""), "GATTACA");
+        assert_eq!(alignment.text_aligned.replace('-', ""), "GATCACA");
+        assert!(alignment
+            .query_aligned
+            .chars()
+            .zip(alignment.text_aligned.chars())
+            .all(|(q, t)| q == t || q == '-' || t == '-'));
         assert_eq!(
-            affine_gap_align(
-                "CAT",
-                "CAT",
-                &Penalties {
-                    mismatch_pen: 1,
-                    extd_pen: 1,
-                    open_pen: 1,
-                }
-            ),
-            Ok(Alignment {
-                query_aligned: "CAT".to_string(),
-                text_aligned: "CAT".to_string(),
-                score: 0,
-            })
+            affine_gap_align("GATTACA", "GATCACA", &pens).unwrap().score,
+            1
         );
         assert_eq!(
-            affine_gap_align(
-                "CAT",
-                "CATS",
+            indel_only_align("GATTACA", "GATTACA", &pens),
+            affine_gap_align("GATTACA", "GATTACA", &pens)
+        );
+        assert!(matches!(
+            indel_only_align(
+                "GATTACA",
+                "GATCACA",
                 &Penalties {
                     mismatch_pen: 1,
-                    extd_pen: 1,
-                    open_pen: 1,
+                    open_pen: 0,
+                    extd_pen: 0,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "CAT-".to_string(),
-                text_aligned: "CATS".to_string(),
-                score: 2,
-            })
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+    }
+
+    #[test]
+    fn test_align_masked() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let text = "GATTACACCCCGATCACA";
+        assert_eq!(
+            align_masked("GATTACA", text, &pens, &[]),
+            affine_gap_align("GATTACA", text, &pens)
         );
+        assert_eq!(affine_gap_align("GATTACA", text, &pens).unwrap().score, 28);
+        // Masking the exact match leaves the copy with a mismatch.
         assert_eq!(
-            affine_gap_align(
-                "XX",
-                "YY",
-                &Penalties {
-                    mismatch_pen: 1,
-                    extd_pen: 100,
-                    open_pen: 100,
-                }
-            ),
-            Ok(Alignment {
-                query_aligned: "XX".to_string(),
-                text_aligned: "YY".to_string(),
-                score: 2,
-            })
+            align_masked("GATTACA", text, &pens, &[(0, 7)]),
+            Ok(Alignment::new(
+                32,
+                "-----------GATTACA".to_string(),
+                text.to_string()
+            ))
         );
+        // Masking everything only leaves gaps.
+        let alignment = align_masked("GATTACA", text, &pens, &[(0, 5), (3, 100)]).unwrap();
+        assert!(alignment
+            .query_aligned
+            .chars()
+            .zip(alignment.text_aligned.chars())
+            .all(|(q, t)| q == '-' || t == '-'));
+        assert_eq!(alignment.recompute_score(&pens), alignment.score);
+    }
+
+    #[test]
+    fn test_affine_gap_align_with_progress() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mut rows: Vec<(usize, u32)> = Vec::new();
+        let alignment = affine_gap_align_with_progress("GATTACA", "GCATGCTA", &pens, |i, score| {
+            rows.push((i, score))
+        });
+        assert_eq!(alignment, affine_gap_align("GATTACA", "GCATGCTA", &pens));
+        // Once per row, in order.
         assert_eq!(
-            affine_gap_align(
-                "XX",
-                "YY",
-                &Penalties {
-                    mismatch_pen: 100,
-                    extd_pen: 1,
-                    open_pen: 1,
-                }
-            ),
-            Ok(Alignment {
-                query_aligned: "XX--".to_string(),
-                text_aligned: "--YY".to_string(),
-                score: 6,
-            })
+            rows.iter().map(|(i, _)| *i).collect::<Vec<usize>>(),
+            (1..=7).collect::<Vec<usize>>()
         );
+        // The last row holds the end of the alignment.
+        assert!(rows[6].1 <= alignment.unwrap().score);
+        // Matching the first chars costs nothing.
+        assert_eq!(rows[0], (1, 0));
+
+        let mut calls = 0;
+        assert!(matches!(
+            affine_gap_align_with_progress("", "GATTACA", &pens, |_, _| calls += 1),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn test_align_with_qualities() {
+        let pens = Penalties {
+            mismatch_pen: 8,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let quals = [MAX_QUALITY; 3];
+        // A trusted mismatch costs more than an insertion and a deletion.
         assert_eq!(
-            affine_gap_align(
-                "XX",
-                "YYYYYYYY",
-                &Penalties {
-                    mismatch_pen: 100,
-                    extd_pen: 1,
-                    open_pen: 1,
-                }
-            ),
-            Ok(Alignment {
-                query_aligned: "XX--------".to_string(),
-                text_aligned: "--YYYYYYYY".to_string(),
-                score: 12,
-            })
+            align_with_qualities("AAC", "ACC", &quals, &pens),
+            Ok(Alignment::new(6, "AAC-".to_string(), "A-CC".to_string()))
         );
         assert_eq!(
-            affine_gap_align(
-                "XXZZ",
-                "XXYZ",
-                &Penalties {
-                    mismatch_pen: 100,
-                    extd_pen: 1,
-                    open_pen: 1,
-                }
-            ),
-            Ok(Alignment {
-                query_aligned: "XX-ZZ".to_string(),
-                text_aligned: "XXYZ-".to_string(),
-                score: 4,
-            })
+            align_with_qualities("AAC", "ACC", &quals, &pens),
+            affine_gap_align("AAC", "ACC", &pens)
         );
+        // Not a doubtful one.
         assert_eq!(
-            match affine_gap_align(
-                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
-                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
-                &Penalties {
-                    mismatch_pen: 1,
-                    extd_pen: 1,
-                    open_pen: 1,
-                }
-            ) {
-                Ok(s) => s.score,
-                _ => 1,
-            },
+            align_with_qualities("AAC", "ACC", &[MAX_QUALITY, 10, MAX_QUALITY], &pens),
+            Ok(Alignment::new(2, "AAC".to_string(), "ACC".to_string()))
+        );
+        // Qualities above MAX_QUALITY cost the full penalty.
+        assert_eq!(
+            align_with_qualities("AAC", "ACC", &[0, 93, 0], &pens)
+                .unwrap()
+                .score,
             6
         );
+        assert!(matches!(
+            align_with_qualities("AAC", "ACC", &quals[1..], &pens),
+            Err(AlignmentError::LengthMismatch(_))
+        ));
+    }
 
+    #[test]
+    fn test_align_with_gap_profile() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // Uniform profiles give the penalties of pens.
+        for (query, text) in [("AAAA", "AAGAA"), ("GATTACA", "GCATGCTA"), ("CAT", "C")] {
+            let length = text.len();
+            assert_eq!(
+                align_with_gap_profile(query, text, &vec![6; length], &vec![2; length], 4),
+                affine_gap_align(query, text, &pens)
+            );
+        }
         assert_eq!(
-            match affine_gap_align(
-                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
-                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
-                &Penalties {
-                    mismatch_pen: 135,
-                    extd_pen: 19,
-                    open_pen: 82,
-                }
-            ) {
-                Ok(s) => s.score,
-                _ => 1,
-            },
-            472
+            align_with_gap_profile("AAAA", "AAGAA", &[6; 5], &[2; 5], 4),
+            Ok(Alignment::new(8, "AA-AA".to_string(), "AAGAA".to_string()))
+        );
+        // The G can't be deleted anymore: an A is deleted instead, and the G is mismatched.
+        let alignment =
+            align_with_gap_profile("AAAA", "AAGAA", &[6, 6, 50, 6, 6], &[2, 2, 50, 2, 2], 4)
+                .unwrap();
+        assert_eq!(alignment.score, 12);
+        assert_ne!(alignment.query_aligned, "AA-AA");
+        assert_eq!(alignment.query_aligned.replace('-', ""), "AAAA");
+
+        assert!(matches!(
+            align_with_gap_profile("AAAA", "AAGAA", &[6; 4], &[2; 5], 4),
+            Err(AlignmentError::LengthMismatch(_))
+        ));
+        assert!(matches!(
+            align_with_gap_profile("AAAA", "AAGAA", &[0; 5], &[0; 5], 0),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // A single extension penalty of 0 is rejected too.
+        assert!(matches!(
+            align_with_gap_profile("AAAA", "AAGAA", &[6; 5], &[2, 2, 0, 2, 2], 4),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        // The profiles have one penalty per char, not per byte.
+        assert_eq!(
+            align_with_gap_profile("A", "é", &[6], &[2], 4),
+            Ok(Alignment::new(4, "A".to_string(), "é".to_string()))
+        );
+        assert!(matches!(
+            align_with_gap_profile("A", "é", &[6; 2], &[2; 2], 4),
+            Err(AlignmentError::LengthMismatch(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_align_to_consensus() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let consensus: Vec<HashSet<char>> = ["A", "CT", "G", "AG"]
+            .iter()
+            .map(|column| column.chars().collect())
+            .collect();
+        for query in ["ACGA", "ATGG", "ACGG", "ATGA"] {
+            assert_eq!(
+                align_to_consensus(query, &consensus, &pens),
+                Ok(Alignment::new(0, query.to_string(), query.to_string()))
+            );
+        }
+        // Unmatched columns show their smallest char.
+        assert_eq!(
+            align_to_consensus("AAGA", &consensus, &pens),
+            Ok(Alignment::new(4, "AAGA".to_string(), "ACGA".to_string()))
+        );
+        assert_eq!(
+            align_to_consensus("AGG", &consensus, &pens),
+            Ok(Alignment::new(8, "A-GG".to_string(), "ACGG".to_string()))
+        );
+        // Singleton columns behave like a plain text.
+        let text: Vec<HashSet<char>> = "GATTACA".chars().map(|c| HashSet::from([c])).collect();
+        assert_eq!(
+            align_to_consensus("GATACA", &text, &pens),
+            affine_gap_align("GATACA", "GATTACA", &pens)
+        );
+        assert!(matches!(
+            align_to_consensus("ACGA", &[], &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[test]
+    fn test_damerau_align() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // One transposition rather than 2 substitutions.
+        assert_eq!(
+            damerau_align("ab", "ba", &pens, 3),
+            Ok((
+                Alignment::new(3, "ab".to_string(), "ba".to_string()),
+                vec![DamerauOp::Transposition]
+            ))
+        );
+        assert_eq!(affine_gap_align("ab", "ba", &pens).unwrap().score, 8);
+        // A transposition more expensive than 2 substitutions isn't used.
+        let (alignment, ops) = damerau_align("ab", "ba", &pens, 9).unwrap();
+        assert_eq!(alignment.score, 8);
+        assert_eq!(ops, vec![DamerauOp::Column(AlignmentOp::Mismatch); 2]);
+
+        let (alignment, ops) = damerau_align("recieve", "receive", &pens, 3).unwrap();
+        assert_eq!(alignment.score, 3);
+        assert_eq!(
+            ops,
+            [
+                vec![DamerauOp::Column(AlignmentOp::Match); 3],
+                vec![DamerauOp::Transposition],
+                vec![DamerauOp::Column(AlignmentOp::Match); 2],
+            ]
+            .concat()
+        );
+        // Without transpositions, the alignment is the SWG one.
+        for (a, b) in [("GATTACA", "GCATGCTA"), ("CAT", "CATS"), ("AAAA", "A")] {
+            let (alignment, ops) = damerau_align(a, b, &pens, 100).unwrap();
+            assert_eq!(
+                alignment.score,
+                affine_gap_align(a, b, &pens).unwrap().score
+            );
+            assert_eq!(alignment.recompute_score(&pens), alignment.score);
+            assert!(!ops.contains(&DamerauOp::Transposition));
+        }
+        // A huge open_pen doesn't overflow the gap penalty: gaps are only an error when the
+        // alignment needs one.
+        let pens = Penalties {
+            open_pen: u32::MAX,
+            ..pens
+        };
+        assert_eq!(damerau_align("ab", "ba", &pens, 3).unwrap().0.score, 3);
+        assert!(matches!(
+            damerau_align("ab", "abc", &pens, 3),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_k_best_align() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // The substitution, then the alignments with an insertion and a deletion.
+        let alignments = k_best_align("GATTACA", "GATCACA", &pens, 2, 20).unwrap();
+        assert_eq!(
+            alignments.iter().map(|a| a.score).collect::<Vec<u32>>(),
+            vec![4, 16]
+        );
+        assert_eq!(
+            alignments[0],
+            affine_gap_align("GATTACA", "GATCACA", &pens).unwrap()
+        );
+        assert_eq!(alignments[1].recompute_score(&pens), 16);
+        assert_eq!(
+            k_best_align("GATTACA", "GATCACA", &pens, 2, 10)
+                .unwrap()
+                .len(),
+            1
         );
+        assert_eq!(
+            k_best_align("GATTACA", "GATCACA", &pens, 0, 10).unwrap(),
+            vec![]
+        );
+        // With a huge open_pen, the alignments with gaps score above u32::MAX.
+        let huge = Penalties {
+            open_pen: u32::MAX,
+            ..pens
+        };
+        assert_eq!(
+            k_best_align("GATTACA", "GATCACA", &huge, 2, u32::MAX).unwrap(),
+            vec![affine_gap_align("GATTACA", "GATCACA", &huge).unwrap()]
+        );
+        assert!(matches!(
+            k_best_align("GATTACA", "GATACA", &huge, 2, 0),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+
+        // Every alignment of a and b, by brute force.
+        fn all_alignments(a: &[char], b: &[char]) -> Vec<(String, String)> {
+            if a.is_empty() && b.is_empty() {
+                return vec![(String::new(), String::new())];
+            }
+            let mut result = Vec::new();
+            let mut extend = |q: char, t: char, rest: Vec<(String, String)>| {
+                for (query, text) in rest {
+                    result.push((q.to_string() + &query, t.to_string() + &text));
+                }
+            };
+            if !a.is_empty() && !b.is_empty() {
+                extend(a[0], b[0], all_alignments(&a[1..], &b[1..]));
+            }
+            if !a.is_empty() {
+                extend(a[0], '-', all_alignments(&a[1..], b));
+            }
+            if !b.is_empty() {
+                extend('-', b[0], all_alignments(a, &b[1..]));
+            }
+            result
+        }
+        let mut rng = StdRng::seed_from_u64(656);
+        for _ in 0..30 {
+            let a: String = (0..rng.gen_range(1..5))
+                .map(|_| ['A', 'C'][rng.gen_range(0..2)])
+                .collect();
+            let b: String = (0..rng.gen_range(1..5))
+                .map(|_| ['A', 'C'][rng.gen_range(0..2)])
+                .collect();
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..6),
+                open_pen: rng.gen_range(0..6),
+                extd_pen: rng.gen_range(1..6),
+            };
+            let a_chars: Vec<char> = a.chars().collect();
+            let b_chars: Vec<char> = b.chars().collect();
+            let mut scores: Vec<u32> = all_alignments(&a_chars, &b_chars)
+                .into_iter()
+                .map(|(query, text)| Alignment::new(0, query, text).recompute_score(&pens))
+                .collect();
+            scores.sort();
+            let alignments = k_best_align(&a, &b, &pens, 10, u32::MAX / 2).unwrap();
+            assert_eq!(alignments.len(), scores.len().min(10));
+            for (alignment, score) in alignments.iter().zip(&scores) {
+                assert_eq!(alignment.score, *score);
+                assert_eq!(alignment.recompute_score(&pens), alignment.score);
+            }
+            for (idx, alignment) in alignments.iter().enumerate() {
+                assert!(alignments[..idx].iter().all(|other| other != alignment));
+            }
+        }
     }
 }
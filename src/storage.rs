@@ -0,0 +1,227 @@
+//! Compact binary storage of alignments.
+//!
+//! Each alignment is stored as its score, followed by its number of CIGAR runs and the runs
+//! themselves. Every number is a LEB128 varint, and a run is stored as length << 2 | op, with
+//! op 0 for a (mis)match, 1 for an insertion and 2 for a deletion. The aligned chars aren't
+//! stored: the sequences are needed to rebuild the alignments. Neither are the coordinates:
+//! the alignments are read back as global alignments.
+
+use crate::alignment_lib::*;
+use std::io::{self, Read, Write};
+
+const RUN_MATCH: u64 = 0;
+const RUN_INSERTION: u64 = 1;
+const RUN_DELETION: u64 = 2;
+
+/// Writes the alignments to w, in the order they are given.
+pub fn write_alignments<W: Write>(mut w: W, aligns: &[Alignment]) -> io::Result<()> {
+    for alignment in aligns {
+        let runs = cigar_runs(alignment);
+        write_varint(&mut w, alignment.score as u64)?;
+        write_varint(&mut w, runs.len() as u64)?;
+        for (op, length) in runs {
+            write_varint(&mut w, (length as u64) << 2 | op)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the alignments written by write_alignments, rebuilding each of them from the
+/// (query, text) pair of sequences at the same index.
+/// Returns an io::ErrorKind::InvalidData error if the number of alignments isn't the number of
+/// pairs, or if an alignment doesn't span its sequences.
+pub fn read_alignments<R: Read>(
+    mut r: R,
+    sequences: &[(&str, &str)],
+) -> io::Result<Vec<Alignment>> {
+    let mut alignments = Vec::with_capacity(sequences.len());
+    while let Some(score) = read_varint(&mut r)? {
+        let (query, text) = sequences.get(alignments.len()).ok_or_else(|| {
+            invalid_data(format!(
+                "More alignments were stored than the {} sequence pairs given.",
+                sequences.len()
+            ))
+        })?;
+        let score = u32::try_from(score)
+            .map_err(|_| invalid_data(format!("The score {} doesn't fit in a u32.", score)))?;
+        let num_runs = read_varint(&mut r)?.ok_or_else(truncated)?;
+        let mut q_chars = query.chars();
+        let mut t_chars = text.chars();
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        for _ in 0..num_runs {
+            let run = read_varint(&mut r)?.ok_or_else(truncated)?;
+            let (consumes_query, consumes_text) = match run & 3 {
+                RUN_MATCH => (true, true),
+                RUN_INSERTION => (true, false),
+                RUN_DELETION => (false, true),
+                op => return Err(invalid_data(format!("Unknown CIGAR operation {}.", op))),
+            };
+            for _ in 0..run >> 2 {
+                let too_long = || {
+                    invalid_data(format!(
+                        "The alignment {} is longer than its sequences.",
+                        alignments.len()
+                    ))
+                };
+                query_aligned.push(match consumes_query {
+                    true => q_chars.next().ok_or_else(too_long)?,
+                    false => GAP_CHAR,
+                });
+                text_aligned.push(match consumes_text {
+                    true => t_chars.next().ok_or_else(too_long)?,
+                    false => GAP_CHAR,
+                });
+            }
+        }
+        if q_chars.next().is_some() || t_chars.next().is_some() {
+            return Err(invalid_data(format!(
+                "The alignment {} is shorter than its sequences.",
+                alignments.len()
+            )));
+        }
+        alignments.push(Alignment::new(score, query_aligned, text_aligned));
+    }
+    if alignments.len() != sequences.len() {
+        return Err(invalid_data(format!(
+            "{} alignments were stored for {} sequence pairs.",
+            alignments.len(),
+            sequences.len()
+        )));
+    }
+    Ok(alignments)
+}
+
+/// Returns the (op, length) runs of the CIGAR string of an alignment.
+fn cigar_runs(alignment: &Alignment) -> Vec<(u64, usize)> {
+    let mut runs: Vec<(u64, usize)> = Vec::new();
+    for (q, t) in alignment.columns() {
+        let op = match AlignmentOp::from_column(q, t) {
+            Some(AlignmentOp::Match | AlignmentOp::Mismatch) => RUN_MATCH,
+            Some(AlignmentOp::Insertion) => RUN_INSERTION,
+            Some(AlignmentOp::Deletion) => RUN_DELETION,
+            // A column of 2 gaps aligns nothing.
+            None => continue,
+        };
+        match runs.last_mut() {
+            Some((last_op, length)) if *last_op == op => *length += 1,
+            _ => runs.push((op, 1)),
+        }
+    }
+    runs
+}
+
+fn write_varint<W: Write>(w: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads a varint, or returns None if r is at its end.
+fn read_varint<R: Read>(r: &mut R) -> io::Result<Option<u64>> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if r.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(truncated()),
+            };
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(invalid_data("A varint is longer than 64 bits.".to_string()))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "The stored alignments end in the middle of an alignment.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wavefront_alignment::wavefront_align;
+
+    #[test]
+    fn test_cigar_runs_double_gap() {
+        let alignment = Alignment::new(0, "A-CG".to_string(), "A-C-".to_string());
+        assert_eq!(
+            cigar_runs(&alignment),
+            vec![(RUN_MATCH, 2), (RUN_INSERTION, 1)]
+        );
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_alignments_round_trip() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mut rng = StdRng::seed_from_u64(626);
+        let mut pairs: Vec<(String, String)> = vec![("CAT".to_string(), "CAT".to_string())];
+        for _ in 0..100 {
+            let query_length = rng.gen_range(1..200);
+            let text_length = query_length + rng.gen_range(0..30);
+            pairs.push((
+                random_sequence(&mut rng, query_length, &DNA_BASES),
+                random_sequence(&mut rng, text_length, &DNA_BASES),
+            ));
+        }
+        let sequences: Vec<(&str, &str)> = pairs
+            .iter()
+            .map(|(query, text)| (query.as_str(), text.as_str()))
+            .collect();
+        let alignments: Vec<Alignment> = sequences
+            .iter()
+            .map(|(query, text)| wavefront_align(query, text, &pens).unwrap())
+            .collect();
+
+        let mut stored = Vec::new();
+        write_alignments(&mut stored, &alignments).unwrap();
+        assert_eq!(
+            read_alignments(&stored[..], &sequences).unwrap(),
+            alignments
+        );
+        // Smaller than the aligned strings alone.
+        let aligned_length: usize = alignments
+            .iter()
+            .map(|alignment| alignment.query_aligned.len() + alignment.text_aligned.len())
+            .sum();
+        assert!(stored.len() * 10 < aligned_length);
+
+        let error_kind = |result: io::Result<Vec<Alignment>>| result.unwrap_err().kind();
+        assert_eq!(
+            error_kind(read_alignments(&stored[..], &sequences[1..])),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            error_kind(read_alignments(&stored[..stored.len() - 1], &sequences)),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            error_kind(read_alignments(&stored[..3], &[("CAT", "CATS")])),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(read_alignments(&[][..], &[]).unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,144 @@
+//! Translated alignment of a DNA query against a protein text.
+
+use crate::alignment_lib::*;
+use crate::wavefront_alignment::wavefront_align;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Amino acids of the standard genetic code, for the codons sorted with the bases in TCAG
+/// order. Stop codons are '*'.
+const GENETIC_CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+/// Returns the index of a base in the TCAG order, or None if it isn't one of ACGT.
+fn base_index(base: char) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        'T' | 'U' => Some(0),
+        'C' => Some(1),
+        'A' => Some(2),
+        'G' => Some(3),
+        _ => None,
+    }
+}
+
+fn complement(base: char) -> char {
+    match base.to_ascii_uppercase() {
+        'A' => 'T',
+        'T' | 'U' => 'A',
+        'C' => 'G',
+        'G' => 'C',
+        other => other,
+    }
+}
+
+/// Translates dna using the standard genetic code.
+/// Frames 0 to 2 start at the base of the same index, frames 3 to 5 are frames 0 to 2 of the
+/// reverse complement of dna. A trailing incomplete codon is dropped, and codons with a
+/// non-ACGT base are translated to 'X'.
+/// Panics if frame > 5.
+pub fn translate(dna: &str, frame: u8) -> String {
+    assert!(
+        frame <= 5,
+        "The frame must be between 0 and 5, got {}.",
+        frame
+    );
+    let bases: Vec<char> = if frame < 3 {
+        dna.chars().collect()
+    } else {
+        dna.chars().rev().map(complement).collect()
+    };
+    bases
+        .get((frame % 3) as usize..)
+        .unwrap_or(&[])
+        .chunks_exact(3)
+        .map(|codon| {
+            codon
+                .iter()
+                .try_fold(0, |index, base| Some(index * 4 + base_index(*base)?))
+                .map_or('X', |index| GENETIC_CODE[index] as char)
+        })
+        .collect()
+}
+
+/// Translates dna_query in each of the 6 frames, and aligns the translations to protein_text
+/// using WFA. Returns the best frame along with its alignment, the lowest frame on ties.
+/// The frames that can't be aligned (e.g. a translation longer than the text) are skipped: if
+/// no frame can be aligned, the error of frame 0 is returned.
+pub fn translated_align(
+    dna_query: &str,
+    protein_text: &str,
+    pens: &Penalties,
+) -> Result<(u8, Alignment), AlignmentError> {
+    let mut best: Option<(u8, Alignment)> = None;
+    let mut first_error: Option<AlignmentError> = None;
+    for frame in 0..6 {
+        match wavefront_align(&translate(dna_query, frame), protein_text, pens) {
+            Ok(alignment) => {
+                if best
+                    .as_ref()
+                    .map_or(true, |(_, best)| alignment.score < best.score)
+                {
+                    best = Some((frame, alignment));
+                }
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    best.ok_or_else(|| first_error.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    const DNA: &str = "ATGGCCATTGTAATGGGCCGCTGA";
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate(DNA, 0), "MAIVMGR*");
+        assert_eq!(translate("atggcc", 0), "MA");
+        // The trailing incomplete codons are dropped.
+        assert_eq!(translate("ATGGC", 0), "M");
+        assert_eq!(translate("ATGGC", 1), "W");
+        assert_eq!(translate("AT", 0), "");
+        assert_eq!(translate("ATGNCC", 0), "MX");
+
+        let reverse_complement: String = DNA.chars().rev().map(complement).collect();
+        for frame in 0..3 {
+            assert_eq!(
+                translate(&reverse_complement, frame + 3),
+                translate(DNA, frame)
+            );
+            assert_eq!(
+                translate(DNA, frame + 3),
+                translate(&reverse_complement, frame)
+            );
+        }
+    }
+
+    #[test]
+    fn test_translated_align() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            translated_align(DNA, "MAIVMGR*", &pens),
+            Ok((
+                0,
+                Alignment::new(0, "MAIVMGR*".to_string(), "MAIVMGR*".to_string())
+            ))
+        );
+        let reverse_complement: String = DNA.chars().rev().map(complement).collect();
+        let (frame, alignment) =
+            translated_align(&(reverse_complement + "C"), "MAIVMGR*", &pens).unwrap();
+        assert_eq!((frame, alignment.score), (4, 0));
+        assert!(matches!(
+            translated_align("AT", "MAIVMGR*", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+}
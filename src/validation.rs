@@ -1,15 +1,19 @@
 use lib::alignment_lib::*;
+use lib::generation;
 
 use std::sync::mpsc::{self, Receiver, Sender}; // Parallel validation.
 use std::{fmt, thread}; // Parallel validation and error messages.
 
-use rand::{thread_rng, Rng}; // Validation case generation.
+use rand::rngs::StdRng; // Reproducible validation cases.
+use rand::{thread_rng, Rng, SeedableRng}; // Validation case generation.
 
 use clap::Parser;
 
 fn main() {
     let args = ValidateArgs::parse();
-    if args.parallel {
+    if let Some(seed) = args.seed {
+        validate_seeded(seed, args);
+    } else if args.parallel {
         validate_concurrent(args);
     } else {
         validate(args);
@@ -38,19 +42,42 @@ struct ValidateArgs {
     #[clap(short, long, default_value_t = u64::MAX)]
     /// Number of random pairings to validate.
     number: u64,
+
+    #[clap(long)]
+    /// Replay the single case generated from this seed, as printed by a failed validation.
+    seed: Option<u64>,
+
+    #[clap(long)]
+    /// Compare the alignments of the WFA variants (A* and the operations backtrace) with
+    /// wavefront_align, instead of comparing the scores of WFA and SWG.
+    compare_variants: bool,
+
+    #[clap(long)]
+    /// When the optimal alignment is unique, check that WFA and SWG return the exact same
+    /// alignment (once their gaps are normalized), and not only the same score.
+    unique_alignments: bool,
 }
 
 fn validate(args: ValidateArgs) -> bool {
+    let mut seeds = thread_rng();
     for cycle in 0..args.number {
-        match run_validation(
+        let seed = seeds.gen();
+        match run_validation_seeded(
+            seed,
             args.min_length,
             args.max_length,
             args.min_error,
             args.max_error,
+            args.compare_variants,
+            args.unique_alignments,
         ) {
             Ok(_) => println!("Validation successful at cycle {}", cycle),
             Err(a) => {
-                println!("Validation failed at cycle {}. \n {:?}", cycle, a);
+                println!(
+                    "Validation failed at cycle {} (seed {}). \n {:?}",
+                    cycle, seed, a
+                );
+                print_minimized(&a);
                 return false;
             }
         }
@@ -58,34 +85,67 @@ fn validate(args: ValidateArgs) -> bool {
     true
 }
 
+/// Runs the validation case generated from seed, so that a failure can be replayed.
+fn validate_seeded(seed: u64, args: ValidateArgs) -> bool {
+    match run_validation_seeded(
+        seed,
+        args.min_length,
+        args.max_length,
+        args.min_error,
+        args.max_error,
+        args.compare_variants,
+        args.unique_alignments,
+    ) {
+        Ok(_) => {
+            println!("Validation successful with seed {}", seed);
+            true
+        }
+        Err(a) => {
+            println!("Validation failed with seed {}. \n {:?}", seed, a);
+            print_minimized(&a);
+            false
+        }
+    }
+}
+
 fn validate_concurrent(args: ValidateArgs) -> bool {
     let num_threads = num_cpus::get();
-    let (tx, rx): (
-        Sender<Result<(), ValidationError>>,
-        Receiver<Result<(), ValidationError>>,
-    ) = mpsc::channel();
+    let (tx, rx): (Sender<SeededResult>, Receiver<SeededResult>) = mpsc::channel();
     let mut threads = Vec::new();
 
     for _ in 0..num_threads {
         let new_tx = tx.clone();
         threads.push(thread::spawn(move || {
-            while new_tx
-                .send(run_validation(
+            let mut seeds = thread_rng();
+            loop {
+                let seed = seeds.gen();
+                let result = run_validation_seeded(
+                    seed,
                     args.min_length,
                     args.max_length,
                     args.min_error,
                     args.max_error,
-                ))
-                .is_ok()
-            {}
+                    args.compare_variants,
+                    args.unique_alignments,
+                );
+                if new_tx.send((seed, result)).is_err() {
+                    break;
+                }
+            }
         }));
     }
+    // Only the worker threads hold senders: if they all die, recv() errors out instead of hanging.
+    drop(tx);
 
     for cycle in 1..=args.number {
         match rx.recv() {
-            Ok(Ok(_)) => println!("Validation successful at cycle {}", cycle),
-            Ok(Err(a)) => {
-                println!("Validation failed at cycle {}. \n {:?}", cycle, a);
+            Ok((_, Ok(_))) => println!("Validation successful at cycle {}", cycle),
+            Ok((seed, Err(a))) => {
+                println!(
+                    "Validation failed at cycle {} (seed {}). \n {:?}",
+                    cycle, seed, a
+                );
+                print_minimized(&a);
                 return false;
             }
             Err(a) => {
@@ -97,81 +157,8 @@ fn validate_concurrent(args: ValidateArgs) -> bool {
     true
 }
 
-mod validation_generation {
-    use rand::distributions::{Alphanumeric, Distribution, Standard};
-    use rand::{thread_rng, Rng};
-
-    enum MutationType {
-        Insertion,
-        Deletion,
-        Substitution,
-    }
-
-    // Allows to randomly generate a MutationType.
-    impl Distribution<MutationType> for Standard {
-        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> MutationType {
-            match rng.gen_range(0..=2) {
-                0 => MutationType::Insertion,
-                1 => MutationType::Deletion,
-                _ => MutationType::Substitution,
-            }
-        }
-    }
-
-    pub fn random_string(min_length: usize, max_length: usize) -> String {
-        let mut rng = thread_rng();
-        let length = rng.gen_range(min_length..max_length);
-
-        (&mut rng)
-            .sample_iter(Alphanumeric)
-            .take(length)
-            .map(char::from)
-            .collect()
-    }
-
-    fn gen_new_char() -> char {
-        let mut rng = thread_rng();
-        (&mut rng)
-            .sample_iter(Alphanumeric)
-            .take(1)
-            .map(char::from)
-            .collect::<Vec<char>>()[0]
-    }
-
-    fn gen_new_char_different(a: char) -> char {
-        loop {
-            let c = gen_new_char();
-            if c != a {
-                return c;
-            }
-        }
-    }
-
-    pub fn mutate(text: &str, min_error: i32, max_error: i32) -> String {
-        let mut rng = thread_rng();
-        let mut mutated: Vec<char> = text.chars().collect();
-        let error_rate: i32 = rng.gen_range(min_error..max_error);
-        let final_err_count: i32 = (error_rate * (mutated.len() as i32)) / 100;
-
-        for _ in 0..final_err_count {
-            let position: usize = rng.gen_range(0..mutated.len());
-            let mutation: MutationType = rand::random();
-            if let MutationType::Insertion = mutation {
-                mutated.insert(position, gen_new_char());
-            }
-            if let MutationType::Deletion = mutation {
-                mutated.remove(position);
-            }
-            if let MutationType::Substitution = mutation {
-                mutated[position] = gen_new_char_different(mutated[position]);
-            }
-        }
-        mutated.into_iter().collect()
-    }
-}
-
 fn check_score_error(alignment: Alignment, pens: &Penalties) -> Option<IncorrectScore> {
-    let computed_score = compute_score_from_alignment(&alignment, pens);
+    let computed_score = alignment.recompute_score(pens);
     if alignment.score == computed_score {
         None
     } else {
@@ -182,38 +169,6 @@ fn check_score_error(alignment: Alignment, pens: &Penalties) -> Option<Incorrect
     }
 }
 
-fn compute_score_from_alignment(alignment: &Alignment, pens: &Penalties) -> u32 {
-    let mut computed_score: u32 = 0;
-    let mut current_layer: lib::alignment_lib::AlignmentLayer = AlignmentLayer::Matches;
-    for (c1, c2) in alignment
-        .query_aligned
-        .chars()
-        .zip(alignment.text_aligned.chars())
-    {
-        if c1 == '-' {
-            computed_score += pens.extd_pen
-                + match current_layer {
-                    AlignmentLayer::Deletes => 0,
-                    _ => pens.open_pen,
-                };
-            current_layer = AlignmentLayer::Deletes;
-        } else if c2 == '-' {
-            computed_score += pens.extd_pen
-                + match current_layer {
-                    AlignmentLayer::Inserts => 0,
-                    _ => pens.open_pen,
-                };
-            current_layer = AlignmentLayer::Inserts;
-        } else {
-            current_layer = AlignmentLayer::Matches;
-            if c1 != c2 {
-                computed_score += pens.mismatch_pen;
-            }
-        }
-    }
-    computed_score
-}
-
 struct IncorrectScore {
     alignment: Alignment,
     computed_score: u32,
@@ -226,6 +181,8 @@ impl fmt::Debug for IncorrectScore {
 }
 
 /// This type returns every type of error we can get in a validation case.
+/// The fields are only read through the Debug impl, when the error is printed.
+#[allow(dead_code)]
 #[derive(Debug)]
 enum ValidationError {
     /// This variant is for the case where the score is incorrect: it doesn't match the
@@ -234,10 +191,29 @@ enum ValidationError {
 
     /// This variant is for the case where both alignments have different scores. There can be only
     /// one optimal alignment score, so at least one of them is wrong.
-    ScoresDiffer(ScoresDiffer),
+    ScoresDiffer(Box<ScoresDiffer>),
 
     /// For the case when one alignment failed (returned an AlignmentError) but not the other.
     AlignmentFailure((AlignmentError, AlignmentAlgorithm)),
+
+    /// For the case when a WFA implementation doesn't return the exact same alignment as
+    /// wavefront_align.
+    AlignmentsDiffer(Box<AlignmentsDiffer>),
+}
+
+struct AlignmentsDiffer {
+    query: String,
+    text: String,
+    pens: Penalties,
+    aligner: &'static str,
+    expected: AlignResult,
+    found: AlignResult,
+}
+
+impl fmt::Debug for AlignmentsDiffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error comparing the alignments of {} and {}. wavefront_align returns {:?} while {} returns {:?}.\nPenalties:{:?}", self.query, self.text, self.expected, self.aligner, self.found, self.pens)
+    }
 }
 
 struct ScoresDiffer {
@@ -245,6 +221,10 @@ struct ScoresDiffer {
     text: String,
     a_score: u32,
     b_score: u32,
+    /// Scores recomputed from the alignments: if they don't match the reported scores, the
+    /// bug is in the way that method computes its score.
+    a_recomputed: u32,
+    b_recomputed: u32,
     query_aligned_a: String,
     text_aligned_a: String,
     query_aligned_b: String,
@@ -254,56 +234,108 @@ struct ScoresDiffer {
 
 impl fmt::Debug for ScoresDiffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Error comparing the alignment of {} with {}. The first method finds a score of {} while the second gives {}. \n First alignment: {}\n{}\nSecond alignment:{}\n{}\nPenalties:{:?}", self.query, self.text, self.a_score, self.b_score, self.query_aligned_a, self.text_aligned_a, self.query_aligned_b, self.text_aligned_b, self.pens)
+        write!(f, "Error comparing the alignment of {} with {}. The first method finds a score of {} while the second gives {}. \n First alignment: {}\n{}\nSecond alignment:{}\n{}\nPenalties:{:?}", self.query, self.text, self.a_score, self.b_score, self.query_aligned_a, self.text_aligned_a, self.query_aligned_b, self.text_aligned_b, self.pens)?;
+        write!(
+            f,
+            "\nRecomputed scores: {} for the first alignment ({}), {} for the second ({}).",
+            self.a_recomputed,
+            consistency(self.a_score, self.a_recomputed),
+            self.b_recomputed,
+            consistency(self.b_score, self.b_recomputed)
+        )
+    }
+}
+
+/// Describes whether the score reported by a method matches its alignment.
+fn consistency(reported: u32, recomputed: u32) -> &'static str {
+    if reported == recomputed {
+        "matches its score"
+    } else {
+        "DOESN'T match its score"
     }
 }
 
-/// This function generates a case, run the alignment, and then checks that it is valid.
-fn run_validation(
+type ValidationResult = Result<(), ValidationError>;
+
+/// A validation result, along with the seed of the case that produced it.
+type SeededResult = (u64, ValidationResult);
+
+/// This function generates a case from seed, run the alignment, and then checks that it is valid.
+/// The same seed and arguments always generate the same case.
+fn run_validation_seeded(
+    seed: u64,
     min_length: usize,
     max_length: usize,
     min_error: i32,
     max_error: i32,
-) -> Result<(), ValidationError> {
+    compare_variants: bool,
+    unique_alignments: bool,
+) -> ValidationResult {
+    let mut rng = StdRng::seed_from_u64(seed);
+
     // generate 2 strings
-    let mut text = validation_generation::random_string(min_length, max_length);
-    let mut query = validation_generation::mutate(&text, min_error, max_error);
+    let mut text = generation::random_string(&mut rng, min_length, max_length);
+    let mut query = generation::mutate(&mut rng, &text, min_error, max_error);
     if query.len() > text.len() {
         std::mem::swap(&mut query, &mut text);
     }
 
     // generate pens
-    let mut rng = thread_rng();
     let pens = Penalties {
         mismatch_pen: rng.gen_range(1..100),
         open_pen: rng.gen_range(1..100),
         extd_pen: rng.gen_range(1..100),
     };
 
+    if compare_variants {
+        return compare_wfa_variants(query, text, pens);
+    }
+
     // align them using the method
     let a_result = lib::wavefront_alignment::wavefront_align(&query, &text, &pens);
     let b_result = lib::reference::affine_gap_align(&query, &text, &pens);
 
     match (a_result, b_result) {
+        (Ok(a), Ok(b))
+            if a.normalize_gaps() != b.normalize_gaps()
+                && unique_alignments
+                && optimal_alignment_count(&query, &text, &pens) == 1 =>
+        {
+            Err(ValidationError::AlignmentsDiffer(Box::new(
+                AlignmentsDiffer {
+                    query,
+                    text,
+                    pens,
+                    aligner: "affine_gap_align",
+                    expected: Ok(a),
+                    found: Ok(b),
+                },
+            )))
+        }
         (Ok(a), Ok(b)) if a.score == b.score => {
             // Both functions aligned succesfully with the same score.
-            match (check_score_error(a, &pens), check_score_error(b, &pens)) {
-                (None, None) => Ok(()),
+            match (
+                check_score_error(a.clone(), &pens),
+                check_score_error(b, &pens),
+            ) {
+                (None, None) => check_symmetry(query, text, pens, a),
                 (Some(a), _) => Err(ValidationError::IncorrectScore(a)),
                 (_, Some(a)) => Err(ValidationError::IncorrectScore(a)),
             }
         }
-        (Ok(a), Ok(b)) => Err(ValidationError::ScoresDiffer(ScoresDiffer {
+        (Ok(a), Ok(b)) => Err(ValidationError::ScoresDiffer(Box::new(ScoresDiffer {
             query,
             text,
             a_score: a.score,
             b_score: b.score,
+            a_recomputed: a.recompute_score(&pens),
+            b_recomputed: b.recompute_score(&pens),
             query_aligned_a: a.query_aligned,
             text_aligned_a: a.text_aligned,
             query_aligned_b: b.query_aligned,
             text_aligned_b: b.text_aligned,
             pens,
-        })),
+        }))),
 
         (Err(_), Err(_)) => Ok(()), // both alignment functions didn't work, let's assume it's normal.
         (Err(a), Ok(_)) => Err(ValidationError::AlignmentFailure((
@@ -317,6 +349,200 @@ fn run_validation(
     }
 }
 
+/// Checks that aligning the reverses of query and text gives the same score as alignment, the
+/// alignment of query and text by wavefront_align. Backtrace bugs often break this symmetry.
+fn check_symmetry(
+    query: String,
+    text: String,
+    pens: Penalties,
+    alignment: Alignment,
+) -> ValidationResult {
+    match lib::wavefront_alignment::align_reversed(&query, &text, &pens) {
+        Ok(reversed) if reversed.score == alignment.score => {
+            match check_score_error(reversed, &pens) {
+                None => Ok(()),
+                Some(a) => Err(ValidationError::IncorrectScore(a)),
+            }
+        }
+        Ok(reversed) => Err(ValidationError::ScoresDiffer(Box::new(ScoresDiffer {
+            query,
+            text,
+            a_score: alignment.score,
+            b_score: reversed.score,
+            a_recomputed: alignment.recompute_score(&pens),
+            b_recomputed: reversed.recompute_score(&pens),
+            query_aligned_a: alignment.query_aligned,
+            text_aligned_a: alignment.text_aligned,
+            query_aligned_b: reversed.query_aligned,
+            text_aligned_b: reversed.text_aligned,
+            pens,
+        }))),
+        Err(a) => Err(ValidationError::AlignmentFailure((
+            a,
+            AlignmentAlgorithm::Wavefront,
+        ))),
+    }
+}
+
+/// Returns whether WFA and SWG both align query and text, with different scores.
+fn wfa_swg_scores_differ(query: &str, text: &str, pens: &Penalties) -> bool {
+    match (
+        lib::wavefront_alignment::wavefront_align(query, text, pens),
+        lib::reference::affine_gap_align(query, text, pens),
+    ) {
+        (Ok(a), Ok(b)) => a.score != b.score,
+        _ => false,
+    }
+}
+
+/// Shrinks a case on which WFA and SWG disagree to a smaller one on which they still disagree,
+/// as a reproducer. The case is returned unchanged if they agree on it.
+fn minimize_failing_case(query: &str, text: &str, pens: &Penalties) -> (String, String) {
+    minimize_case(query, text, |query, text| {
+        wfa_swg_scores_differ(query, text, pens)
+    })
+}
+
+/// Greedily removes chars from query and text, one at a time, as long as fails still returns
+/// true for the smaller case. The result is a local minimum: removing any single char of it
+/// makes the failure disappear.
+fn minimize_case<F: Fn(&str, &str) -> bool>(query: &str, text: &str, fails: F) -> (String, String) {
+    let mut case: [Vec<char>; 2] = [query.chars().collect(), text.chars().collect()];
+    let to_string = |chars: &[char]| chars.iter().collect::<String>();
+    if !fails(query, text) {
+        return (query.to_string(), text.to_string());
+    }
+    let mut shrunk = true;
+    while shrunk {
+        shrunk = false;
+        for seq in 0..2 {
+            let mut pos = 0;
+            while pos < case[seq].len() {
+                let mut candidate = case.clone();
+                candidate[seq].remove(pos);
+                if fails(&to_string(&candidate[0]), &to_string(&candidate[1])) {
+                    case = candidate;
+                    shrunk = true;
+                } else {
+                    pos += 1;
+                }
+            }
+        }
+    }
+    (to_string(&case[0]), to_string(&case[1]))
+}
+
+/// Prints a minimized version of a failing case on which WFA and SWG disagree.
+fn print_minimized(error: &ValidationError) {
+    if let ValidationError::ScoresDiffer(differ) = error {
+        if wfa_swg_scores_differ(&differ.query, &differ.text, &differ.pens) {
+            let (query, text) = minimize_failing_case(&differ.query, &differ.text, &differ.pens);
+            println!(
+                "Minimized case: query {}, text {}, penalties {:?}",
+                query, text, differ.pens
+            );
+        }
+    }
+}
+
+/// Returns the number of optimal alignments of query and text, saturating at 2: 1 means that
+/// the optimal alignment is unique, so that every correct aligner must return it.
+/// This isn't a heuristic: Gotoh's DP is run with 3 states per cell, for the alignments ending
+/// with a (mis)match, an insertion or a deletion, keeping the best score of each state with
+/// the number of paths reaching it. Each alignment is a single path through these states, so
+/// the number of optimal paths to the last cell is the number of optimal alignments.
+fn optimal_alignment_count(query: &str, text: &str, pens: &Penalties) -> u8 {
+    // (best score, number of paths with that score) of a state, None if it can't be reached.
+    type Paths = Option<(u64, u8)>;
+    let merge = |a: Paths, b: Paths| match (a, b) {
+        (Some(a), Some(b)) if a.0 == b.0 => Some((a.0, (a.1 + b.1).min(2))),
+        (Some(a), Some(b)) => Some(if a.0 < b.0 { a } else { b }),
+        (a, None) => a,
+        (None, b) => b,
+    };
+    let add = |paths: Paths, cost: u32| paths.map(|(score, count)| (score + cost as u64, count));
+    let gap_open = pens.open_pen + pens.extd_pen;
+
+    let q_chars: Vec<char> = query.chars().collect();
+    let t_chars: Vec<char> = text.chars().collect();
+    // [(mis)match, insertion, deletion] states of each cell.
+    let mut states: Vec<Vec<[Paths; 3]>> =
+        vec![vec![[None; 3]; t_chars.len() + 1]; q_chars.len() + 1];
+    states[0][0][0] = Some((0, 1));
+    for i in 0..=q_chars.len() {
+        for j in 0..=t_chars.len() {
+            if i > 0 && j > 0 {
+                let cost = if q_chars[i - 1] == t_chars[j - 1] {
+                    0
+                } else {
+                    pens.mismatch_pen
+                };
+                let [matches, inserts, deletes] = states[i - 1][j - 1];
+                states[i][j][0] = add(merge(merge(matches, inserts), deletes), cost);
+            }
+            if i > 0 {
+                let [matches, inserts, deletes] = states[i - 1][j];
+                states[i][j][1] = merge(
+                    add(inserts, pens.extd_pen),
+                    add(merge(matches, deletes), gap_open),
+                );
+            }
+            if j > 0 {
+                let [matches, inserts, deletes] = states[i][j - 1];
+                states[i][j][2] = merge(
+                    add(deletes, pens.extd_pen),
+                    add(merge(matches, inserts), gap_open),
+                );
+            }
+        }
+    }
+    let [matches, inserts, deletes] = states[q_chars.len()][t_chars.len()];
+    merge(merge(matches, inserts), deletes).map_or(0, |(_, count)| count)
+}
+
+/// The WFA variants that must return the exact same alignment as wavefront_align.
+/// There is a single wavefront grid: they share it, but not the way it is filled or read.
+const WFA_VARIANTS: [(&str, AlignmentFunction); 2] = [
+    ("A*", lib::wavefront_alignment::wavefront_align_astar),
+    ("ops backtrace", wavefront_align_from_ops),
+];
+
+/// Aligns using wavefront_align_ops, and builds the alignment from its operations.
+fn wavefront_align_from_ops(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    let (score, ops) = lib::wavefront_alignment::wavefront_align_ops(query, text, pens)?;
+    // Operations that don't match the strings give an empty alignment, which is then reported
+    // as different from the expected one.
+    Ok(
+        Alignment::from_ops(score, &ops, query, text).unwrap_or(Alignment::new(
+            score,
+            String::new(),
+            String::new(),
+        )),
+    )
+}
+
+/// Checks that every WFA variant returns the same alignment as wavefront_align. Comparing the
+/// alignments, and not only the scores, catches the variants that break ties differently.
+fn compare_wfa_variants(query: String, text: String, pens: Penalties) -> ValidationResult {
+    let expected = lib::wavefront_alignment::wavefront_align(&query, &text, &pens);
+    for (aligner, align) in WFA_VARIANTS {
+        let found = align(&query, &text, &pens);
+        if found != expected {
+            return Err(ValidationError::AlignmentsDiffer(Box::new(
+                AlignmentsDiffer {
+                    query,
+                    text,
+                    pens,
+                    aligner,
+                    expected,
+                    found,
+                },
+            )));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -330,6 +556,156 @@ mod test {
             max_error: 100,
             number: 250,
             parallel: true,
+            seed: None,
+            compare_variants: false,
+            unique_alignments: false,
         }));
     }
+
+    #[test]
+    fn validate_compare_variants() {
+        for seed in 0..100 {
+            if let Err(e) = run_validation_seeded(611 + seed, 1, 100, 0, 30, true, false) {
+                panic!("Validation failed with seed {}. \n {:?}", 611 + seed, e);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_unique_alignments() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(optimal_alignment_count("GATTACA", "GATTACA", &pens), 1);
+        assert_eq!(optimal_alignment_count("GATTACA", "GATCACA", &pens), 1);
+        // The extra A can be aligned with any of the 3 As.
+        assert_eq!(optimal_alignment_count("AAA", "AAAA", &pens), 2);
+        assert_eq!(optimal_alignment_count("AA", "AAAAAAAAAAAAAA", &pens), 2);
+
+        assert!(validate_seeded(
+            631,
+            ValidateArgs {
+                min_length: 1,
+                max_length: 100,
+                min_error: 0,
+                max_error: 30,
+                number: 1,
+                parallel: false,
+                seed: Some(631),
+                compare_variants: false,
+                unique_alignments: true,
+            }
+        ));
+        for seed in 0..100 {
+            if let Err(e) = run_validation_seeded(631 + seed, 1, 100, 0, 30, false, true) {
+                panic!("Validation failed with seed {}. \n {:?}", 631 + seed, e);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_traceback_unique_alignments() {
+        let mut rng = StdRng::seed_from_u64(673);
+        let mut unique = 0;
+        for _ in 0..300 {
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let text = generation::random_string(&mut rng, 1, 60);
+            let query = generation::mutate(&mut rng, &text, 0, 30);
+            if query.is_empty()
+                || query.len() > text.len()
+                || optimal_alignment_count(&query, &text, &pens) != 1
+            {
+                continue;
+            }
+            unique += 1;
+            // Both aligners walk back their cells with the same traceback: a unique optimum
+            // is returned byte for byte by both.
+            let wfa = lib::wavefront_alignment::wavefront_align(&query, &text, &pens).unwrap();
+            let swg = lib::reference::affine_gap_align(&query, &text, &pens).unwrap();
+            assert_eq!(wfa, swg, "query {}, text {}, {:?}", query, text, pens);
+        }
+        assert!(unique >= 50);
+    }
+
+    #[test]
+    fn seeded_generation_is_reproducible() {
+        let gen_case = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let text = generation::random_string(&mut rng, 1, 100);
+            let query = generation::mutate(&mut rng, &text, 0, 100);
+            (text, query)
+        };
+        assert_eq!(gen_case(42), gen_case(42));
+        assert!(validate_seeded(
+            42,
+            ValidateArgs {
+                min_length: 1,
+                max_length: 100,
+                min_error: 0,
+                max_error: 100,
+                number: 1,
+                parallel: false,
+                seed: Some(42),
+                compare_variants: false,
+                unique_alignments: false,
+            }
+        ));
+    }
+
+    #[test]
+    fn scores_differ_reports_consistency() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let error = ScoresDiffer {
+            query: "GAT".to_string(),
+            text: "GCAT".to_string(),
+            a_score: 3,
+            b_score: 2,
+            a_recomputed: 3,
+            b_recomputed: 5,
+            query_aligned_a: "G-AT".to_string(),
+            text_aligned_a: "GCAT".to_string(),
+            query_aligned_b: "GAT-".to_string(),
+            text_aligned_b: "GCAT".to_string(),
+            pens,
+        };
+        let message = format!("{:?}", error);
+        assert!(message.contains("3 for the first alignment (matches its score)"));
+        assert!(message.contains("5 for the second (DOESN'T match its score)"));
+    }
+
+    #[test]
+    fn minimize_failing_cases() {
+        // Fails as long as the query has an X and the text a Y.
+        let fails = |query: &str, text: &str| query.contains('X') && text.contains('Y');
+        assert_eq!(
+            minimize_case("GATXTACA", "CAYTYGAT", fails),
+            ("X".to_string(), "Y".to_string())
+        );
+        assert_eq!(
+            minimize_case("GATTACA", "CAYT", fails),
+            ("GATTACA".to_string(), "CAYT".to_string())
+        );
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // WFA and SWG agree: there is nothing to minimize.
+        assert!(!wfa_swg_scores_differ("GATTACA", "GCATGCTA", &pens));
+        assert_eq!(
+            minimize_failing_case("GATTACA", "GCATGCTA", &pens),
+            ("GATTACA".to_string(), "GCATGCTA".to_string())
+        );
+    }
 }
@@ -0,0 +1,28 @@
+//! wasm-bindgen entry point, to run alignments in the browser.
+
+use crate::alignment_lib::Penalties;
+use crate::wavefront_alignment::wavefront_align;
+use wasm_bindgen::prelude::*;
+
+/// Aligns 2 strings using WFA.
+/// On success, returns an object with the fields of Alignment: the score, query_aligned,
+/// text_aligned and the coordinates of the aligned region.
+/// Alignment errors are thrown as strings.
+#[wasm_bindgen]
+pub fn align_wasm(
+    query: &str,
+    text: &str,
+    mismatch: u32,
+    open: u32,
+    extd: u32,
+) -> Result<JsValue, JsValue> {
+    let pens = Penalties {
+        mismatch_pen: mismatch,
+        open_pen: open,
+        extd_pen: extd,
+    };
+    match wavefront_align(query, text, &pens) {
+        Ok(alignment) => serde_wasm_bindgen::to_value(&alignment).map_err(JsValue::from),
+        Err(e) => Err(JsValue::from_str(&format!("{:?}", e))),
+    }
+}
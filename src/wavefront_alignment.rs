@@ -1,13 +1,317 @@
 //! This module exports the wavefront alignment functions.
 use super::alignment_lib::*;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::cmp::Reverse;
+#[cfg(feature = "std")]
+use std::io::BufRead;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
 /// This function is exported and can be called to perform an alignment.
-/// The query cannot be longer than the text.
-pub fn wavefront_align(
+/// The query cannot be longer than the text. Equal strings are returned as the all-match
+/// alignment without computing any wavefront.
+pub fn wavefront_align(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    check_inputs(query, text, pens)?;
+    if query == text {
+        return Ok(Alignment::new(0, query.to_string(), text.to_string()));
+    }
+    let mut current_front = new_wavefront_state(query, text, pens);
+    run_wavefront(&mut current_front)?;
+    current_front.backtrace()
+}
+
+/// Same as wavefront_align, but two chars match when eq returns true for them, instead of
+/// when they are equal. Wildcards, case-insensitivity or IUPAC codes can be handled by the
+/// caller this way, e.g. with |q, t| q.eq_ignore_ascii_case(&t).
+/// eq should behave like an equivalence relation (reflexive, symmetric and transitive) for the
+/// alignment to make sense. The score of the alignment counts the pairs accepted by eq as
+/// matches.
+pub fn wavefront_align_by<F: Fn(char, char) -> bool>(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    eq: F,
+) -> AlignResult {
+    check_inputs(query, text, pens)?;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    current_front.char_eq = Some(CharEq(&eq));
+    run_wavefront(&mut current_front)?;
+    current_front.backtrace()
+}
+
+/// Aligns query and text maximizing a similarity score, where each match is rewarded with
+/// match_reward, and mismatches and gaps are penalized as usual (a gap of length l costs
+/// open + l * extd). Returns the similarity of the alignment with the alignment.
+/// WFA needs matches to cost 0, so the similarity scheme is transformed into penalties:
+/// mismatch' = 2 * (match_reward + mismatch), open' = 2 * open and
+/// extd' = 2 * extd + match_reward. Every column consumes 2 chars (or 1 for a gap column),
+/// so for any alignment, similarity = (match_reward * (|query| + |text|) - penalty') / 2:
+/// both problems have the same optimal alignments. The score of the returned alignment is
+/// its penalty' under the transformed penalties.
+/// The transformation is only valid for global alignments, where the lengths of the strings
+/// are fixed. It also needs match_reward + mismatch > 0, otherwise mismatch' is 0 and
+/// mismatches are as cheap as matches: AlignmentError::DegeneratePenalties is returned.
+pub fn wavefront_align_with_match_reward(
+    query: &str,
+    text: &str,
+    match_reward: u32,
+    mismatch: u32,
+    open: u32,
+    extd: u32,
+) -> Result<(i64, Alignment), AlignmentError> {
+    if match_reward == 0 && mismatch == 0 {
+        return Err(AlignmentError::DegeneratePenalties(
+            "match_reward and mismatch are 0: mismatches are as cheap as matches.".to_string(),
+        ));
+    }
+    let overflow = || {
+        AlignmentError::ScoreOverflow("The transformed penalties don't fit in a u32.".to_string())
+    };
+    let pens = Penalties {
+        mismatch_pen: match_reward
+            .checked_add(mismatch)
+            .and_then(|pen| pen.checked_mul(2))
+            .ok_or_else(overflow)?,
+        open_pen: open.checked_mul(2).ok_or_else(overflow)?,
+        extd_pen: extd
+            .checked_mul(2)
+            .and_then(|pen| pen.checked_add(match_reward))
+            .ok_or_else(overflow)?,
+    };
+    let alignment = wavefront_align(query, text, &pens)?;
+    let length = (query.chars().count() + text.chars().count()) as i64;
+    let similarity = (match_reward as i64 * length - alignment.score as i64) / 2;
+    Ok((similarity, alignment))
+}
+
+/// Performs a banded alignment: only the diagonals at a distance <= band of the diagonals
+/// between the start (diagonal 0) and the end (final diagonal) of the alignment are computed.
+/// The result is the best alignment within the band, which isn't always the optimal alignment.
+pub fn wavefront_align_banded(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    band: usize,
+) -> AlignResult {
+    check_inputs(query, text, pens)?;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    current_front.restrict_band(band);
+    run_wavefront(&mut current_front)?;
+    current_front.backtrace()
+}
+
+/// Performs an exact alignment, and reports the max distance between the diagonals of its
+/// path and the final diagonal. Since wavefront_align_banded computes the diagonals around
+/// both the final diagonal and diagonal 0, it finds this alignment (or one just as good) with
+/// this band: it can be reused as the band of similar pairs.
+pub fn wavefront_align_band_report(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+) -> Result<BandReport, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    run_wavefront(&mut current_front)?;
+    let (alignment, min_sufficient_band) = current_front.backtrace_with_band()?;
+    Ok(BandReport {
+        alignment,
+        min_sufficient_band,
+    })
+}
+
+/// Same as wavefront_align, but stops once timeout has elapsed, returning the alignment that
+/// reaches furthest into the strings instead of an error: the backtrace starts from the
+/// furthest-reaching point of the wavefronts computed, which may be on any diagonal.
+/// The deadline is checked after each score, so the wavefronts of score 0 are always
+/// computed: a partial alignment covers at least the common prefix of the strings.
+#[cfg(feature = "std")]
+pub fn wavefront_align_timeout_partial(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    timeout: Duration,
+) -> Result<PartialAlignment, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    let deadline = Instant::now() + timeout;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    while !step_wavefront(&mut current_front)? {
+        if Instant::now() >= deadline {
+            let ((score, diag), offset) = current_front.furthest_point();
+            let end = ((AlignmentLayer::Matches, score, diag), offset);
+            let (_, query_end, text_end) = current_front.position(end);
+            return Ok(PartialAlignment {
+                alignment: current_front.backtrace_from(end)?,
+                partial: true,
+                query_end,
+                text_end,
+            });
+        }
+    }
+    Ok(PartialAlignment {
+        alignment: current_front.backtrace()?,
+        partial: false,
+        query_end: query.chars().count(),
+        text_end: text.chars().count(),
+    })
+}
+
+/// Performs banded alignments, doubling the band until the result is guaranteed to be optimal.
+/// A banded alignment is optimal when every alignment that leaves the band is costlier:
+/// any such alignment goes through one of the 2 diagonals right outside of the band, and
+/// the gaps needed to get there and back to the final diagonal have a known minimum cost.
+/// Returns AlignmentError::BandExceeded if the optimality can't be proven with band <= max_band.
+pub fn wavefront_align_doubling(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    initial_band: usize,
+    max_band: usize,
+) -> AlignResult {
+    check_inputs(query, text, pens)?;
+    let mut band = initial_band;
+    while band <= max_band {
+        let mut current_front = new_wavefront_state(query, text, pens);
+        current_front.restrict_band(band);
+        run_wavefront(&mut current_front)?;
+        let alignment = current_front.backtrace()?;
+        if alignment.score <= current_front.out_of_band_lower_bound() {
+            return Ok(alignment);
+        }
+        band = band.saturating_mul(2).max(1);
+    }
+    Err(AlignmentError::BandExceeded(format!(
+        "The optimal alignment couldn't be found with a band <= {}.",
+        max_band
+    )))
+}
+
+/// The optimal score of an alignment, returned by wavefront_score. The wavefronts are dropped
+/// once the score is known: the alignment is recomputed on demand by backtrace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavefrontScore<'a> {
+    query: &'a str,
+    text: &'a str,
+    pens: &'a Penalties,
+    score: u32,
+}
+
+impl WavefrontScore<'_> {
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// Recomputes the alignment, which is the same as wavefront_align's.
+    /// Knowing the score bounds the diagonals the alignment can reach: a diagonal at a distance
+    /// d of the diagonal 0 needs gaps of length >= d, so only the diagonals at a distance
+    /// <= (score - open_pen) / extd_pen are computed. Every path of score <= score stays in
+    /// that band, so the wavefronts are the same as the unbanded ones within it.
+    pub fn backtrace(&self) -> AlignResult {
+        let mut current_front = new_wavefront_state(self.query, self.text, self.pens);
+        if let Some(band) = self
+            .score
+            .saturating_sub(self.pens.open_pen)
+            .checked_div(self.pens.extd_pen)
+        {
+            current_front.restrict_band(band as usize);
+        }
+        run_wavefront(&mut current_front)?;
+        current_front.backtrace()
+    }
+}
+
+/// Computes the optimal alignment score of query and text. The alignment itself can then be
+/// computed with WavefrontScore::backtrace, without keeping the wavefronts in the meantime.
+pub fn wavefront_score<'a>(
+    query: &'a str,
+    text: &'a str,
+    pens: &'a Penalties,
+) -> Result<WavefrontScore<'a>, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    run_wavefront(&mut current_front)?;
+    Ok(WavefrontScore {
+        query,
+        text,
+        pens,
+        score: current_front.current_score,
+    })
+}
+
+/// Band of the alignment computed by wavefront_align_astar to get its initial upper bound.
+const ASTAR_SEED_BAND: usize = 4;
+
+/// Performs an A*-guided alignment: a cheap banded alignment gives an upper bound of the
+/// optimal score, and the diagonals that can't lead to an alignment within that bound are
+/// pruned. The heuristic of a diagonal is the cost of extending a gap up to the final diagonal,
+/// which never overestimates the remaining cost: the result is optimal.
+pub fn wavefront_align_astar(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    check_inputs(query, text, pens)?;
+    let mut seed = new_wavefront_state(query, text, pens);
+    seed.restrict_band(ASTAR_SEED_BAND);
+    run_wavefront(&mut seed)?;
+
+    let mut current_front = new_wavefront_state(query, text, pens);
+    current_front.astar_bound = Some(seed.current_score);
+    run_wavefront(&mut current_front)?;
+    current_front.backtrace()
+}
+
+/// Wavefronts narrower than this aren't reduced by the adaptive WFA.
+const ADAPTIVE_MIN_WAVEFRONT_LENGTH: usize = 10;
+
+/// Default max_distance of the adaptive WFA.
+const ADAPTIVE_MAX_DISTANCE: usize = 50;
+
+/// Performs an adaptive WFA alignment, using the default cutoff. See
+/// wavefront_align_adaptive_report.
+pub fn wavefront_align_adaptive(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    wavefront_align_adaptive_report(query, text, pens, ADAPTIVE_MAX_DISTANCE)
+        .map(|report| report.alignment)
+}
+
+/// Performs an adaptive WFA alignment: after each extension, the diagonals at the edges of the
+/// wavefront that are more than max_distance chars further from the end of the alignment than
+/// the closest diagonal are pruned. The result isn't always optimal, unless max_distance is
+/// large enough (usize::MAX never prunes). Returns the alignment with statistics of the run.
+pub fn wavefront_align_adaptive_report(
     query: &str,
     text: &str,
     pens: &Penalties,
-) -> Result<Alignment, AlignmentError> {
+    max_distance: usize,
+) -> Result<AdaptiveReport, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    if query == text {
+        return Ok(AdaptiveReport {
+            alignment: Alignment::new(0, query.to_string(), text.to_string()),
+            path_score: 0,
+            final_band_width: 0,
+            diagonals_pruned: 0,
+            cells_computed: 0,
+        });
+    }
+    let mut current_front = new_wavefront_state(query, text, pens);
+    current_front.adaptive_max_distance = Some(max_distance);
+    run_wavefront(&mut current_front)?;
+    let alignment = current_front.backtrace()?;
+    Ok(AdaptiveReport {
+        path_score: alignment.recompute_score(pens),
+        alignment,
+        final_band_width: current_front.grid.max_width(),
+        diagonals_pruned: current_front.diagonals_pruned,
+        cells_computed: current_front.grid.num_cells() + current_front.diagonals_removed,
+    })
+}
+
+/// Checks that the strings can be aligned using WFA, and that the score won't overflow.
+/// On top of the checks of Penalties::is_valid_for, WFA needs mismatch_pen > 0.
+fn check_inputs(query: &str, text: &str, pens: &Penalties) -> Result<(), AlignmentError> {
     if query.is_empty() || text.is_empty() {
         return Err(AlignmentError::ZeroLength(format!(
             "At least one of the string slices passed to wavefront_align had a length of zero.
@@ -25,27 +329,517 @@ pub fn wavefront_align(
                       )
                   );
     }
+    if pens.mismatch_pen == 0 {
+        // A mismatch would extend the wavefront it's computed from, instead of the next one.
+        return Err(AlignmentError::DegeneratePenalties(
+            "mismatch_pen is 0: mismatches are as cheap as matches, which WFA can't handle."
+                .to_string(),
+        ));
+    }
+    pens.check_valid_for(
+        &query.chars().collect::<Vec<char>>(),
+        &text.chars().collect::<Vec<char>>(),
+    )
+}
+
+/// Main loop of the algorithm: runs until the end of both strings is reached.
+fn run_wavefront(current_front: &mut WavefrontState) -> Result<(), AlignmentError> {
+    while !step_wavefront(current_front)? {}
+    Ok(())
+}
+
+/// Performs one iteration of the main loop: extends the wavefronts of the current score, then
+/// computes those of the next score unless the alignment is finished.
+/// Returns whether the alignment is finished.
+fn step_wavefront(current_front: &mut WavefrontState) -> Result<bool, AlignmentError> {
+    current_front.extend();
+    if current_front.is_finished() {
+        return Ok(true);
+    }
+    if let Some(max_distance) = current_front.adaptive_max_distance {
+        current_front.adaptive_reduce(max_distance);
+    }
+    current_front.increment_score();
+    current_front.next()?;
+    Ok(false)
+}
+
+/// State of an alignment computed by wavefront_align_chunked.
+#[derive(Debug)]
+pub enum ChunkedAlignment<'a> {
+    /// The alignment isn't finished: poll must be called to continue it.
+    Pending(PendingAlignment<'a>),
+    Done(Alignment),
+}
+
+/// An unfinished alignment of wavefront_align_chunked.
+#[derive(Debug)]
+pub struct PendingAlignment<'a> {
+    state: Box<WavefrontState<'a>>,
+    steps_per_poll: usize,
+}
+
+impl<'a> PendingAlignment<'a> {
+    /// Continues the alignment for at most steps_per_poll score increments.
+    pub fn poll(mut self) -> Result<ChunkedAlignment<'a>, AlignmentError> {
+        for _ in 0..self.steps_per_poll {
+            if step_wavefront(&mut self.state)? {
+                return self.state.backtrace().map(ChunkedAlignment::Done);
+            }
+        }
+        Ok(ChunkedAlignment::Pending(self))
+    }
+
+    /// Returns the wavefront of a score as (diagonal, offset) pairs, by increasing diagonal.
+    /// Only the diagonals reached with that score are returned. The offset is the number of
+    /// text chars aligned on the furthest-reaching point of the diagonal, and the diagonal is
+    /// its query position minus its text position. The wavefront of the score being computed
+    /// isn't extended yet, and the iterator is empty for the scores not reached.
+    pub fn current_wavefront(&self, score: u32) -> impl Iterator<Item = (i64, u32)> + '_ {
+        self.state.grid.live_diagonals(score)
+    }
+
+    /// Saves the alignment, so that it can be finished later with wavefront_resume.
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.state.checkpoint()
+    }
+}
+
+/// An unfinished alignment saved by PendingAlignment::checkpoint, which can be serialized
+/// with the serde feature. Only the wavefronts needed to compute the next ones are kept: those
+/// of the last max(mismatch_pen, open_pen + extd_pen) scores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    query_len: usize,
+    text_len: usize,
+    pens: Penalties,
+    score: u32,
+    grid: GridWindow,
+}
+
+impl Checkpoint {
+    /// The score the alignment had reached. The optimal score is at least as high.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+}
+
+/// Finishes the alignment of query and text saved in checkpoint. The wavefronts dropped by
+/// the checkpoint are needed to backtrace, so only the score is computed: the alignment can
+/// then be recomputed with WavefrontScore::backtrace.
+/// query, text and pens must be those of the saved alignment. Returns an
+/// AlignmentError::InvalidCheckpoint if the lengths of the strings or the penalties differ, or
+/// if the checkpoint is corrupted.
+pub fn wavefront_resume<'a>(
+    checkpoint: &Checkpoint,
+    query: &'a str,
+    text: &'a str,
+    pens: &'a Penalties,
+) -> Result<WavefrontScore<'a>, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    let mut current_front = WavefrontState::resume(checkpoint, query, text, pens)?;
+    run_wavefront(&mut current_front)?;
+    Ok(WavefrontScore {
+        query,
+        text,
+        pens,
+        score: current_front.current_score,
+    })
+}
+
+/// Same as wavefront_align, but the alignment is computed by calling PendingAlignment::poll
+/// until it is done. Each poll performs at most steps_per_poll score increments (at least 1),
+/// so that callers such as async executors can bound the work done at once and interleave
+/// alignments without threads. No work is done before the first poll.
+pub fn wavefront_align_chunked<'a>(
+    query: &'a str,
+    text: &'a str,
+    pens: &'a Penalties,
+    steps_per_poll: usize,
+) -> Result<ChunkedAlignment<'a>, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    Ok(ChunkedAlignment::Pending(PendingAlignment {
+        state: Box::new(new_wavefront_state(query, text, pens)),
+        steps_per_poll: steps_per_poll.max(1),
+    }))
+}
+
+/// Same as wavefront_align, but returns the score and the operations of the alignment,
+/// which are read directly from the wavefronts.
+pub fn wavefront_align_ops(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+) -> Result<(u32, Vec<AlignmentOp>), AlignmentError> {
+    check_inputs(query, text, pens)?;
+    let mut current_front = new_wavefront_state(query, text, pens);
+    run_wavefront(&mut current_front)?;
+    Ok((current_front.current_score, current_front.backtrace_ops()?))
+}
+
+/// Returns the runs of consecutive matches of the optimal alignment of query and text, without
+/// building the aligned strings.
+pub fn match_blocks(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+) -> Result<Vec<MatchBlock>, AlignmentError> {
+    let (_, ops) = wavefront_align_ops(query, text, pens)?;
+    let mut blocks: Vec<MatchBlock> = Vec::new();
+    let mut query_pos = 0;
+    let mut text_pos = 0;
+    let mut previous = None;
+    for op in ops {
+        if op == AlignmentOp::Match {
+            match blocks.last_mut() {
+                Some(block) if previous == Some(AlignmentOp::Match) => block.length += 1,
+                _ => blocks.push(MatchBlock {
+                    query_start: query_pos,
+                    text_start: text_pos,
+                    length: 1,
+                }),
+            }
+        }
+        query_pos += (op != AlignmentOp::Deletion) as usize;
+        text_pos += (op != AlignmentOp::Insertion) as usize;
+        previous = Some(op);
+    }
+    Ok(blocks)
+}
+
+/// Same as wavefront_align, for DNA sequences packed using 2 bits per base.
+/// The extension step compares 32 bases at a time instead of 1.
+pub fn wavefront_align_packed(
+    query: &Packed2Bit,
+    text: &Packed2Bit,
+    pens: &Penalties,
+) -> AlignResult {
+    // The unpacked strings are only used to build the aligned strings when backtracking.
+    let query_str = query.to_string();
+    let text_str = text.to_string();
+    check_inputs(&query_str, &text_str, pens)?;
+    let mut current_front = new_wavefront_state(&query_str, &text_str, pens);
+    current_front.packed = Some((query, text));
+    run_wavefront(&mut current_front)?;
+    current_front.backtrace()
+}
+
+/// Same as wavefront_align, for byte sequences: each byte is a char of the alignment, and two
+/// bytes match when they are equal. These are raw-byte semantics, and the bytes aren't decoded:
+/// the bytes of a multi-byte UTF-8 char are aligned separately, and can partially match.
+/// In the aligned strings, byte b is the char of code point b, so ASCII bytes are unchanged.
+/// Use wavefront_align_bytes_checked to reject non-ASCII bytes, or wavefront_align to align
+/// chars.
+pub fn wavefront_align_bytes(query: &[u8], text: &[u8], pens: &Penalties) -> AlignResult {
+    let query: String = query.iter().map(|b| *b as char).collect();
+    let text: String = text.iter().map(|b| *b as char).collect();
+    wavefront_align(&query, &text, pens)
+}
+
+/// Same as wavefront_align_bytes, but returns an AlignmentError::NonAscii if query or text has
+/// a non-ASCII byte, for which bytes and chars differ.
+pub fn wavefront_align_bytes_checked(query: &[u8], text: &[u8], pens: &Penalties) -> AlignResult {
+    for (name, seq) in [("query", query), ("text", text)] {
+        if let Some(pos) = seq.iter().position(|b| !b.is_ascii()) {
+            return Err(AlignmentError::NonAscii(format!(
+                "The {} has the non-ASCII byte {:#04x} at position {}.",
+                name, seq[pos], pos
+            )));
+        }
+    }
+    wavefront_align_bytes(query, text, pens)
+}
+
+/// Aligns sequences of tokens, e.g. words, each token being an atomic symbol: 2 tokens match
+/// if they are equal strings, so a changed word is a single mismatch.
+/// Each distinct token is mapped to a char, other than GAP_CHAR, and the strings of chars are
+/// aligned with wavefront_align. Returns AlignmentError::Unsupported if there are more
+/// distinct tokens than chars.
+pub fn wavefront_align_tokens<'a>(
+    query: &[&'a str],
+    text: &[&'a str],
+    pens: &Penalties,
+) -> Result<TokenAlignment<'a>, AlignmentError> {
+    let mut symbols: BTreeMap<&str, char> = BTreeMap::new();
+    let mut tokens: BTreeMap<char, &str> = BTreeMap::new();
+    let mut unused = (0..=char::MAX as u32)
+        .filter_map(char::from_u32)
+        .filter(|c| *c != GAP_CHAR);
+    let mut encode = |seq: &[&'a str]| -> Result<String, AlignmentError> {
+        seq.iter()
+            .map(|token| match symbols.get(token) {
+                Some(c) => Ok(*c),
+                None => {
+                    let c = unused.next().ok_or_else(|| {
+                        AlignmentError::Unsupported(
+                            "There are more distinct tokens than chars.".to_string(),
+                        )
+                    })?;
+                    symbols.insert(token, c);
+                    tokens.insert(c, token);
+                    Ok(c)
+                }
+            })
+            .collect()
+    };
+    let (query_chars, text_chars) = (encode(query)?, encode(text)?);
+    let alignment = wavefront_align(&query_chars, &text_chars, pens)?;
+    let decode = |aligned: &str| -> Vec<Option<&'a str>> {
+        aligned.chars().map(|c| tokens.get(&c).copied()).collect()
+    };
+    Ok(TokenAlignment {
+        score: alignment.score,
+        query_aligned: decode(&alignment.query_aligned),
+        text_aligned: decode(&alignment.text_aligned),
+    })
+}
+
+/// Aligns the reverses of query and text with wavefront_align, and reverses the alignment
+/// found: the result is an optimal alignment of query and text, whose score is the same as
+/// wavefront_align's. When there are several optimal alignments, ties are broken in mirror
+/// image, e.g. a gap that can slide is placed at the other end of its range.
+/// A score that differs from wavefront_align's reveals a bug of the backtrace.
+pub fn align_reversed(query: &str, text: &str, pens: &Penalties) -> AlignResult {
+    let reversed_query: String = query.chars().rev().collect();
+    let reversed_text: String = text.chars().rev().collect();
+    let reversed = wavefront_align(&reversed_query, &reversed_text, pens)?;
+    Ok(Alignment::new(
+        reversed.score,
+        reversed.query_aligned.chars().rev().collect(),
+        reversed.text_aligned.chars().rev().collect(),
+    ))
+}
+
+/// Re-aligns strings that already contain gaps, e.g. the rows of a multiple alignment.
+/// If strip_existing is set, the GAP_CHARs are removed before aligning. Otherwise, gapped
+/// strings return an AlignmentError::InvalidChar, since WFA can't consume gaps.
+pub fn realign(
+    query_gapped: &str,
+    text_gapped: &str,
+    pens: &Penalties,
+    strip_existing: bool,
+) -> AlignResult {
+    if !strip_existing && (query_gapped.contains(GAP_CHAR) || text_gapped.contains(GAP_CHAR)) {
+        return Err(AlignmentError::InvalidChar(format!(
+            "The strings passed to realign contain gaps ('{}'), which can't be aligned.
+                        Set strip_existing to remove them first.",
+            GAP_CHAR
+        )));
+    }
+    let query: String = query_gapped.chars().filter(|c| *c != GAP_CHAR).collect();
+    let text: String = text_gapped.chars().filter(|c| *c != GAP_CHAR).collect();
+    wavefront_align(&query, &text, pens)
+}
+
+/// Aligns each query to the text, and returns the index of the best-matching query with its
+/// alignment, the lowest index on ties. This assigns a read to the nearest of a set of
+/// barcodes. Once a query has been aligned, the following ones are only aligned until their
+/// score reaches its score, and skipped if their score_lower_bound already does.
+/// The queries that can't be aligned (e.g. longer than the text) are skipped: if no query can
+/// be aligned, the error of the first one is returned.
+pub fn classify(
+    queries: &[&str],
+    text: &str,
+    pens: &Penalties,
+) -> Result<(usize, Alignment), AlignmentError> {
+    let mut best: Option<(usize, Alignment)> = None;
+    let mut first_error: Option<AlignmentError> = None;
+    for (index, query) in queries.iter().enumerate() {
+        if let Err(e) = check_inputs(query, text, pens) {
+            first_error.get_or_insert(e);
+            continue;
+        }
+        let cap = best.as_ref().map_or(u32::MAX, |(_, best)| best.score);
+        if score_lower_bound(query, text, pens) >= cap {
+            continue;
+        }
+        let mut current_front = new_wavefront_state(query, text, pens);
+        if run_wavefront_capped(&mut current_front, cap)? {
+            best = Some((index, current_front.backtrace()?));
+        }
+    }
+    best.ok_or_else(|| {
+        first_error.unwrap_or_else(|| {
+            AlignmentError::ZeroLength("No query was passed to classify.".to_string())
+        })
+    })
+}
+
+/// Aligns the query to each text and returns the top_n (text index, score) pairs, by increasing
+/// score and then index, e.g. to rank the references of a read. Only the scores are computed:
+/// there is no backtrace. As in classify, once top_n texts have been aligned, the following
+/// ones are only aligned until their score reaches the worst score kept, and skipped if their
+/// score_lower_bound already does. The texts that can't be aligned (e.g. shorter than the
+/// query) are left out of the ranking.
+pub fn rank_matches(
+    query: &str,
+    texts: &[&str],
+    pens: &Penalties,
+    top_n: usize,
+) -> Vec<(usize, u32)> {
+    let mut ranking: Vec<(usize, u32)> = Vec::with_capacity(top_n);
+    if top_n == 0 {
+        return ranking;
+    }
+    for (index, text) in texts.iter().enumerate() {
+        if check_inputs(query, text, pens).is_err() {
+            continue;
+        }
+        let cap = match ranking.len() == top_n {
+            true => ranking[top_n - 1].1,
+            false => u32::MAX,
+        };
+        if score_lower_bound(query, text, pens) >= cap {
+            continue;
+        }
+        let mut current_front = new_wavefront_state(query, text, pens);
+        if let Ok(true) = run_wavefront_capped(&mut current_front, cap) {
+            let score = current_front.current_score;
+            // Ties keep the lowest indexes, which come first.
+            let pos = ranking.partition_point(|&(_, ranked)| ranked <= score);
+            ranking.insert(pos, (index, score));
+            ranking.truncate(top_n);
+        }
+    }
+    ranking
+}
+
+/// Runs the main loop until the end of both strings is reached, or the score reaches cap.
+/// Returns whether the alignment is finished, with a score < cap.
+fn run_wavefront_capped(
+    current_front: &mut WavefrontState,
+    cap: u32,
+) -> Result<bool, AlignmentError> {
+    loop {
+        if current_front.current_score >= cap {
+            return Ok(false);
+        }
+        if step_wavefront(current_front)? {
+            return Ok(true);
+        }
+    }
+}
+
+/// Returns the matrix of the identities of the alignments of every pair of seqs, as computed by
+/// Alignment::identity, for clustering. Each pair is aligned once with wavefront_align, the
+/// shortest sequence as the query, and the matrix is symmetric. The diagonal is 1, and the
+/// pairs that can't be aligned (e.g. an empty sequence) have an identity of 0.
+pub fn identity_matrix(seqs: &[&str], pens: &Penalties) -> Vec<Vec<f64>> {
+    let mut matrix = vec![vec![1.0; seqs.len()]; seqs.len()];
+    for i in 0..seqs.len() {
+        for j in i + 1..seqs.len() {
+            let (query, text) = match seqs[i].chars().count() <= seqs[j].chars().count() {
+                true => (seqs[i], seqs[j]),
+                false => (seqs[j], seqs[i]),
+            };
+            let identity = wavefront_align(query, text, pens).map_or(0.0, |a| a.identity());
+            matrix[i][j] = identity;
+            matrix[j][i] = identity;
+        }
+    }
+    matrix
+}
+
+/// Same as wavefront_align, but returns Ok(None) as soon as the identity of the alignment, as
+/// computed by Alignment::identity, can't reach min_identity, e.g. to only keep the pairs of
+/// a cluster. After each score, the optimal score is at least the current one, which bounds
+/// the identity with max_identity. If the alignment is completed, Ok(None) is still returned
+/// when its identity is below min_identity.
+pub fn align_min_identity(
+    query: &str,
+    text: &str,
+    pens: &Penalties,
+    min_identity: f64,
+) -> Result<Option<Alignment>, AlignmentError> {
+    check_inputs(query, text, pens)?;
+    if query == text {
+        return Ok(Some(Alignment::new(0, query.to_string(), text.to_string())));
+    }
+    let (query_len, text_len) = (query.chars().count(), text.chars().count());
     let mut current_front = new_wavefront_state(query, text, pens);
     loop {
-        current_front.extend();
-        if current_front.is_finished() {
+        if max_identity(query_len, text_len, current_front.current_score, pens) < min_identity {
+            return Ok(None);
+        }
+        if step_wavefront(&mut current_front)? {
             break;
         }
-        current_front.increment_score();
-        current_front.next();
     }
-    current_front.backtrace()
+    let alignment = current_front.backtrace()?;
+    Ok(Some(alignment).filter(|alignment| alignment.identity() >= min_identity))
 }
 
-/// Main struct, implementing the algorithm.
-#[derive(Debug, PartialEq, Eq)]
-struct WavefrontState<'a> {
-    query: &'a str,
-    text: &'a str,
+/// Upper bound of the identity of the alignments of score >= score of a query of query_len
+/// chars to a text of text_len chars, with query_len <= text_len.
+/// A column costs at most max(mismatch_pen, open_pen + extd_pen), so the alignment has at
+/// least k = score / that cost columns that aren't matches, and at least
+/// text_len - query_len deletions. With at most query_len matches, its identity is at most
+/// query_len / (query_len + k).
+fn max_identity(query_len: usize, text_len: usize, score: u32, pens: &Penalties) -> f64 {
+    let column_cost = pens.mismatch_pen.max(pens.open_extd_pen()) as u64;
+    let non_matches = (score as u64)
+        .div_ceil(column_cost)
+        .max((text_len - query_len) as u64);
+    match query_len as u64 + non_matches {
+        0 => 1.0,
+        columns => query_len as f64 / columns as f64,
+    }
+}
+
+/// Aligns the sequences read from a reader, which must yield alternating query/text lines.
+/// Pairs are read lazily: one alignment result is produced per pair of lines.
+/// A trailing query line without a text line yields an AlignmentError::ReadFailure.
+#[cfg(feature = "std")]
+pub fn align_stream<'a, R: BufRead + 'a>(
+    reader: R,
     pens: &'a Penalties,
+) -> impl Iterator<Item = AlignResult> + 'a {
+    align_stream_with(reader, pens, wavefront_align)
+}
+
+/// Match predicate used by wavefront_align_by. Closures can't be printed or compared: two
+/// predicates are equal when they are the same object.
+#[derive(Clone, Copy)]
+struct CharEq<'a>(&'a dyn Fn(char, char) -> bool);
+
+impl fmt::Debug for CharEq<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CharEq")
+    }
+}
+
+impl PartialEq for CharEq<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        core::ptr::addr_eq(self.0, other.0)
+    }
+}
+
+impl Eq for CharEq<'_> {}
+
+/// Main struct, implementing the algorithm.
+/// The strings and penalties are owned, so that tests can build states without borrowing.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct WavefrontState<'a> {
+    pens: Penalties,
     q_chars: Vec<char>,
     t_chars: Vec<char>,
 
+    /// Packed versions of query and text, used to extend faster when they are set.
+    packed: Option<(&'a Packed2Bit, &'a Packed2Bit)>,
+
+    /// Match predicate replacing char equality when it is set.
+    char_eq: Option<CharEq<'a>>,
+
+    /// Upper bound of the optimal score, used to prune diagonals in A* mode.
+    astar_bound: Option<u32>,
+
+    /// Cutoff of the adaptive WFA, number of diagonals it pruned, and number of diagonals it
+    /// removed, including the empty ones.
+    adaptive_max_distance: Option<usize>,
+    diagonals_pruned: usize,
+    diagonals_removed: usize,
+
     /// Counter for looping and later backtracking.
     current_score: u32,
 
@@ -53,15 +847,38 @@ struct WavefrontState<'a> {
 
     /// Number of diagonals in the query-text alignment
     /// == to q_chars + t_chars - 1.
-    num_diags: i32,
+    num_diags: i64,
 
     /// The only diagonal on which we can align every char of query and
     /// text.
-    final_diagonal: i32,
+    final_diagonal: i64,
 
     /// Highest and lowest possible diags.
-    highest_diag: i32,
-    lowest_diag: i32,
+    highest_diag: i64,
+    lowest_diag: i64,
+}
+
+/// Prints the strings and the progress of the alignment, with a summary of the grid instead
+/// of its cells.
+impl fmt::Debug for WavefrontState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WavefrontState")
+            .field("query", &self.q_chars.iter().collect::<String>())
+            .field("text", &self.t_chars.iter().collect::<String>())
+            .field("pens", &self.pens)
+            .field("current_score", &self.current_score)
+            .field("final_diagonal", &self.final_diagonal)
+            .field(
+                "grid",
+                &format_args!(
+                    "{} layers, {} cells, {} diagonals in the widest layer",
+                    self.grid.num_layers(),
+                    self.grid.num_cells(),
+                    self.grid.max_width()
+                ),
+            )
+            .finish_non_exhaustive()
+    }
 }
 
 /// Initializes a WavefrontState with the correct fields, for 2 string
@@ -71,13 +888,21 @@ fn new_wavefront_state<'a>(
     text: &'a str,
     pens: &'a Penalties,
 ) -> WavefrontState<'a> {
-    let q_chars: Vec<char> = query.chars().collect();
-    let t_chars: Vec<char> = text.chars().collect();
+    wavefront_state_from_chars(
+        query.chars().collect(),
+        text.chars().collect(),
+        pens.clone(),
+    )
+}
 
-    let final_diagonal = (q_chars.len() as i32) - (t_chars.len() as i32); // A_k in the article
-    let num_diags = (q_chars.len() + t_chars.len() + 1) as i32;
-    let highest_diag = q_chars.len() as i32;
-    let lowest_diag = 0 - t_chars.len() as i32;
+/// Same as new_wavefront_state, taking ownership of the chars and penalties.
+pub(crate) fn wavefront_state_from_chars(
+    q_chars: Vec<char>,
+    t_chars: Vec<char>,
+    pens: Penalties,
+) -> WavefrontState<'static> {
+    let (final_diagonal, lowest_diag, highest_diag) = diagonal_bounds(q_chars.len(), t_chars.len());
+    let num_diags = highest_diag - lowest_diag + 1;
 
     let mut matches = vec![vec![None; num_diags as usize]; 1];
     matches[0][(0 - lowest_diag) as usize] = Some((0, AlignmentLayer::Matches)); // Initialize the starting cell.
@@ -85,11 +910,15 @@ fn new_wavefront_state<'a>(
     let grid = new_wavefront_grid();
 
     WavefrontState {
-        query,
-        text,
         pens,
         q_chars,
         t_chars,
+        packed: None,
+        char_eq: None,
+        astar_bound: None,
+        adaptive_max_distance: None,
+        diagonals_pruned: 0,
+        diagonals_removed: 0,
         current_score: 0,
         num_diags,
         final_diagonal,
@@ -99,6 +928,14 @@ fn new_wavefront_state<'a>(
     }
 }
 
+/// Returns the (final, lowest, highest) diagonals of the alignment of a query and a text of
+/// the given lengths. Diagonals are i64s, so that they can't overflow for sequences longer
+/// than i32::MAX.
+fn diagonal_bounds(q_len: usize, t_len: usize) -> (i64, i64, i64) {
+    let final_diagonal = q_len as i64 - t_len as i64; // A_k in the article
+    (final_diagonal, -(t_len as i64), q_len as i64)
+}
+
 impl Wavefront for WavefrontState<'_> {
     fn extend(&mut self) {
         //! Extends the matches wavefronts to the furthest reaching point
@@ -109,14 +946,14 @@ impl Wavefront for WavefrontState<'_> {
             .expect("get_diag_range returned None at wavefront_extend");
 
         for diag in (diag_range.0)..=(diag_range.1) {
-            let text_pos = match self
+            let (text_pos, layer) = match self
                 .grid
                 .get(AlignmentLayer::Matches, self.current_score, diag)
             {
-                Some((val, _)) => val,
+                Some(cell) => cell,
                 _ => continue,
             };
-            let mut query_pos = (text_pos as i32 + diag) as usize;
+            let mut query_pos = (text_pos as i64 + diag) as usize;
             let mut text_pos = text_pos as usize;
             // The furthest reaching point value stored is the number
             // of matched chars in the Text string.
@@ -124,13 +961,24 @@ impl Wavefront for WavefrontState<'_> {
             // matrix, the number of chars matched for the Query is the
             // number of Text chars matched + diagonal.
 
+            if let Some((q_packed, t_packed)) = self.packed {
+                let matched = q_packed.common_prefix(query_pos, t_packed, text_pos) as u32;
+                self.grid.set(
+                    AlignmentLayer::Matches,
+                    self.current_score,
+                    diag,
+                    Some((text_pos as u32 + matched, layer)),
+                );
+                continue;
+            }
+
             while query_pos < self.q_chars.len() && text_pos < self.t_chars.len() {
                 match (
-                    self.q_chars.get(query_pos as usize),
-                    self.t_chars.get(text_pos as usize),
+                    self.q_chars.get(query_pos),
+                    self.t_chars.get(text_pos),
                 ) {
                     (Some(q), Some(t)) => {
-                        if q == t {
+                        if self.char_eq.map_or(q == t, |eq| (eq.0)(*q, *t)) {
                             self.grid.increment(self.current_score, diag);
                             query_pos += 1;
                             text_pos += 1;
@@ -163,18 +1011,17 @@ impl Wavefront for WavefrontState<'_> {
         }
     }
 
-    fn next(&mut self) {
+    fn next(&mut self) -> Result<(), AlignmentError> {
         //! Equivalent of WAVEFRONT_NEXT
 
         // Calculating the next highest diagonal of the wavefront
         let mut hi = 1 + vec![
             self.current_score.checked_sub(self.pens.mismatch_pen),
-            self.current_score.checked_sub(self.pens.open_pen + self.pens.extd_pen),
+            self.current_score.checked_sub(self.pens.open_extd_pen()),
             self.current_score.checked_sub(self.pens.extd_pen),
         ]
         .into_iter()
-        .filter(|x| x.is_some())
-        .map(|x| x.unwrap())
+        .flatten()
         .map(|x| self.grid.get_diag_range(x).unwrap().1)
         .max()
         .unwrap_or(-1);
@@ -185,12 +1032,11 @@ impl Wavefront for WavefrontState<'_> {
 
         let mut lo = vec![
             self.current_score.checked_sub(self.pens.mismatch_pen),
-            self.current_score.checked_sub(self.pens.open_pen + self.pens.extd_pen),
+            self.current_score.checked_sub(self.pens.open_extd_pen()),
             self.current_score.checked_sub(self.pens.extd_pen),
         ]
         .into_iter()
-        .filter(|x| x.is_some())
-        .map(|x| x.unwrap())
+        .flatten()
         .map(|x| self.grid.get_diag_range(x).unwrap().0)
         .min()
         .unwrap_or(1)
@@ -200,190 +1046,400 @@ impl Wavefront for WavefrontState<'_> {
             lo = self.lowest_diag;
         }
 
-        self.grid.add_layer(lo, hi);
+        if let Some(bound) = self.astar_bound {
+            (lo, hi) = self.astar_prune(bound, lo, hi);
+        }
+
+        self.grid.add_layer(lo, hi)?;
 
         for diag in lo..=hi {
             self.update_ins(diag);
             self.update_del(diag);
             self.update_mat(diag);
         }
+        Ok(())
     }
 
-    fn backtrace(&self) -> Result<Alignment, AlignmentError> {
-        let mut curr_score = self.current_score;
-        let mut curr_diag = self.final_diagonal;
-        let mut curr_layer = AlignmentLayer::Matches;
-
-        let mut query_aligned = String::new();
-        let mut text_aligned = String::new();
+    fn backtrace(&self) -> AlignResult {
+        self.backtrace_with_band().map(|(alignment, _)| alignment)
+    }
+}
 
-        while curr_score > 0 {
-            match &mut curr_layer {
-                // If we're on a match
-                AlignmentLayer::Matches => {
-                    match self
-                        .grid
-                        .get(AlignmentLayer::Matches, curr_score, curr_diag)
-                    {
-                        Some((score, AlignmentLayer::Inserts)) => {
-                            curr_layer = AlignmentLayer::Inserts;
-                            let mut current_char = score;
-                            while current_char
-                                > self
-                                    .grid
-                                    .get(AlignmentLayer::Inserts, curr_score, curr_diag)
-                                    .unwrap()
-                                    .0
-                            {
-                                query_aligned
-                                    .push(self.q_chars[(current_char as i32 + curr_diag - 1) as usize]);
-                                text_aligned.push(self.t_chars[(current_char - 1) as usize]);
-                                current_char -= 1;
-                            }
-                        }
-                        Some((score, AlignmentLayer::Deletes)) => {
-                            curr_layer = AlignmentLayer::Deletes;
-                            let mut current_char = score;
-                            while current_char
-                                > self
-                                    .grid
-                                    .get(AlignmentLayer::Deletes, curr_score, curr_diag)
-                                    .unwrap()
-                                    .0
-                            {
-                                query_aligned
-                                    .push(self.q_chars[(current_char as i32 + curr_diag - 1) as usize]);
-                                text_aligned.push(self.t_chars[(current_char - 1) as usize]);
-                                current_char -= 1;
-                            }
-                        }
-                        Some((score, AlignmentLayer::Matches)) => {
-                            let mut current_char = score;
-                            curr_score -= self.pens.mismatch_pen;
-                            while current_char
-                                > self
-                                    .grid
-                                    .get(AlignmentLayer::Matches, curr_score, curr_diag)
-                                    .unwrap()
-                                    .0
-                            {
-                                query_aligned
-                                    .push(self.q_chars[(current_char as i32 + curr_diag - 1) as usize]);
-                                text_aligned.push(self.t_chars[(current_char - 1) as usize]);
-                                current_char -= 1;
-                            }
-                        }
-                        _ => panic!(),
-                    };
-                }
-                // If we're on the Inserts layer.
-                AlignmentLayer::Inserts => {
-                    match self
-                        .grid
-                        .get(AlignmentLayer::Inserts, curr_score, curr_diag)
-                    {
-                        Some((_, AlignmentLayer::Matches)) => {
-                            let previous = self
-                                .grid
-                                .get(
-                                    AlignmentLayer::Matches,
-                                    curr_score - self.pens.extd_pen - self.pens.open_pen,
-                                    curr_diag - 1,
-                                )
-                                .unwrap();
-                            query_aligned.push(self.q_chars[(previous.0 as i32 + curr_diag - 1) as usize]);
-                            text_aligned.push('-');
-                            curr_diag -= 1;
-                            curr_score -= self.pens.extd_pen + self.pens.open_pen;
-                            curr_layer = AlignmentLayer::Matches;
-                        }
-                        Some((_, AlignmentLayer::Inserts)) => {
-                            let previous = self
-                                .grid
-                                .get(
-                                    AlignmentLayer::Inserts,
-                                    curr_score - self.pens.extd_pen,
-                                    curr_diag - 1,
-                                )
-                                .unwrap();
-                            query_aligned.push(self.q_chars[(previous.0 as i32 + curr_diag - 1) as usize]);
-                            text_aligned.push('-');
-                            curr_diag -= 1;
-                            curr_score -= self.pens.extd_pen;
-                        }
-                        _ => panic!(),
-                    };
-                }
-                AlignmentLayer::Deletes => {
-                    match self
-                        .grid
-                        .get(AlignmentLayer::Deletes, curr_score, curr_diag)
-                    {
-                        Some((_, AlignmentLayer::Matches)) => {
-                            let previous = self
-                                .grid
-                                .get(
-                                    AlignmentLayer::Matches,
-                                    curr_score - self.pens.extd_pen - self.pens.open_pen,
-                                    curr_diag + 1,
-                                )
-                                .unwrap();
-                            query_aligned.push('-');
-                            text_aligned.push(self.t_chars[(previous.0) as usize]);
-                            curr_diag += 1;
-                            curr_score -= self.pens.extd_pen + self.pens.open_pen;
-                            curr_layer = AlignmentLayer::Matches;
-                        }
+/// The cells of the wavefronts are ((layer, score, diagonal), offset), the offset being the
+/// number of text chars aligned.
+impl TracebackCells for WavefrontState<'_> {
+    type Cell = ((AlignmentLayer, u32, i64), u32);
 
-                        Some((_, AlignmentLayer::Deletes)) => {
-                            let previous = self
-                                .grid
-                                .get(
-                                    AlignmentLayer::Deletes,
-                                    curr_score - self.pens.extd_pen,
-                                    curr_diag + 1,
-                                )
-                                .unwrap();
-                            query_aligned.push('-');
-                            text_aligned.push(self.t_chars[(previous.0) as usize]);
-                            curr_diag += 1;
-                            curr_score -= self.pens.extd_pen;
-                        }
-                        _ => panic!(),
-                    };
-                }
-            };
-        }
-        if let AlignmentLayer::Matches = curr_layer {
-            if curr_score == 0 {
-                let remaining = self.grid.get(AlignmentLayer::Matches, 0, 0).unwrap().0 as usize;
-                if remaining > 0 {
-                    query_aligned =
-                        query_aligned + &self.q_chars[..remaining].iter().rev().collect::<String>();
-                    text_aligned =
-                        text_aligned + &self.t_chars[..remaining].iter().rev().collect::<String>();
-                }
-            }
+    fn predecessor(&self, cell: Self::Cell) -> Result<Option<Self::Cell>, AlignmentError> {
+        let ((layer, score, diag), offset) = cell;
+        match score {
+            // The extension of the start cell, from the origin.
+            0 if offset > 0 => Ok(Some(((AlignmentLayer::Matches, 0, 0), 0))),
+            0 => Ok(None),
+            _ => self
+                .find_predecessor(layer, score, diag, offset)
+                .map(Some)
+                .ok_or_else(|| self.backtrace_failed(score, diag)),
         }
+    }
 
-        let q = query_aligned.chars().rev().collect();
-        let t = text_aligned.chars().rev().collect();
+    fn position(&self, cell: Self::Cell) -> (AlignmentLayer, usize, usize) {
+        let ((layer, _, diag), offset) = cell;
+        (layer, (offset as i64 + diag) as usize, offset as usize)
+    }
 
-        Ok(Alignment {
-            score: self.current_score,
-            query_aligned: q,
-            text_aligned: t,
-        })
+    /// A match cell is reached with a mismatch from a match cell of a lower score. From a gap
+    /// cell of the same score, or at score 0, it only extends matches.
+    fn can_mismatch(&self, previous: Self::Cell, cell: Self::Cell) -> bool {
+        let ((previous_layer, previous_score, _), _) = previous;
+        previous_layer == AlignmentLayer::Matches && previous_score < (cell.0).1
+    }
+
+    fn chars_match(&self, q: char, t: char) -> bool {
+        self.char_eq.map_or(q == t, |eq| (eq.0)(q, t))
     }
 }
 
 impl<'a> WavefrontState<'a> {
-    fn update_ins(&mut self, diag: i32) {
-        let from_open = if self.current_score >= (self.pens.open_pen + self.pens.extd_pen)
+    /// Saves the wavefronts needed by the next calls to next, which read the layers down to
+    /// the score current_score + 1 - max(mismatch_pen, open_pen + extd_pen).
+    fn checkpoint(&self) -> Checkpoint {
+        let window = self.pens.mismatch_pen.max(self.pens.open_extd_pen());
+        Checkpoint {
+            query_len: self.q_chars.len(),
+            text_len: self.t_chars.len(),
+            pens: self.pens.clone(),
+            score: self.current_score,
+            grid: self
+                .grid
+                .window((self.current_score + 1).saturating_sub(window)),
+        }
+    }
+
+    /// Rebuilds the state saved in checkpoint, with empty layers for the dropped scores.
+    fn resume(
+        checkpoint: &Checkpoint,
+        query: &'a str,
+        text: &'a str,
+        pens: &'a Penalties,
+    ) -> Result<WavefrontState<'a>, AlignmentError> {
+        let mut state = new_wavefront_state(query, text, pens);
+        if (checkpoint.query_len, checkpoint.text_len) != (state.q_chars.len(), state.t_chars.len())
+            || checkpoint.pens != *pens
+        {
+            return Err(AlignmentError::InvalidCheckpoint(format!(
+                "The checkpoint is for strings of lengths {} and {} with {:?}, not {} and {} with {:?}.",
+                checkpoint.query_len,
+                checkpoint.text_len,
+                checkpoint.pens,
+                state.q_chars.len(),
+                state.t_chars.len(),
+                pens
+            )));
+        }
+        state.grid = WavefrontGrid::from_window(&checkpoint.grid)
+            .filter(|grid| grid.num_layers() == checkpoint.score as usize + 1)
+            .ok_or_else(|| {
+                AlignmentError::InvalidCheckpoint(
+                    "The wavefronts of the checkpoint are corrupted.".to_string(),
+                )
+            })?;
+        state.current_score = checkpoint.score;
+        Ok(state)
+    }
+
+    /// Walks back from the end of the alignment to its start with traceback, calling emit on
+    /// every column of the alignment (in reverse order) with its query and text chars.
+    /// A None char is a gap.
+    /// Returns AlignmentError::BacktraceFailed if a cell has no predecessor, which would mean
+    /// that the wavefronts are inconsistent.
+    fn walk_back<F: FnMut(Option<char>, Option<char>)>(
+        &self,
+        emit: F,
+    ) -> Result<(), AlignmentError> {
+        let (offset, _) = self
+            .grid
+            .get(
+                AlignmentLayer::Matches,
+                self.current_score,
+                self.final_diagonal,
+            )
+            .ok_or_else(|| self.backtrace_failed(self.current_score, self.final_diagonal))?;
+        let end = (
+            (
+                AlignmentLayer::Matches,
+                self.current_score,
+                self.final_diagonal,
+            ),
+            offset,
+        );
+        traceback(self, end, &self.q_chars, &self.t_chars, emit)
+    }
+
+    /// Returns the cell that the (layer, score, diag) cell of the given offset was computed
+    /// from, with its offset.
+    /// The source layer stored in the cell is tried first. If its cell is missing or its
+    /// offset doesn't lead to this one, the other possible predecessors are searched for one
+    /// that does, instead of failing:
+    /// - an insertion comes from the cell of the same offset on diag - 1, a deletion from the
+    ///   cell of offset - 1 on diag + 1, opening (from Matches) or extending the gap.
+    /// - a match cell comes from a gap cell of the same score and diagonal, or from a mismatch
+    ///   on the match cell of the same diagonal, followed by the extension. The chars of the
+    ///   extension must match.
+    fn find_predecessor(
+        &self,
+        layer: AlignmentLayer,
+        score: u32,
+        diag: i64,
+        offset: u32,
+    ) -> Option<((AlignmentLayer, u32, i64), u32)> {
+        let stored = self.grid.get(layer, score, diag)?.1;
+        let open = score.checked_sub(self.pens.open_extd_pen());
+        let extd = score.checked_sub(self.pens.extd_pen);
+        let candidates = match layer {
+            AlignmentLayer::Matches => vec![
+                (AlignmentLayer::Inserts, Some(score), diag),
+                (AlignmentLayer::Deletes, Some(score), diag),
+                (
+                    AlignmentLayer::Matches,
+                    score.checked_sub(self.pens.mismatch_pen),
+                    diag,
+                ),
+            ],
+            AlignmentLayer::Inserts => vec![
+                (AlignmentLayer::Matches, open, diag - 1),
+                (AlignmentLayer::Inserts, extd, diag - 1),
+            ],
+            AlignmentLayer::Deletes => vec![
+                (AlignmentLayer::Matches, open, diag + 1),
+                (AlignmentLayer::Deletes, extd, diag + 1),
+            ],
+        };
+        let consistent = |candidate: AlignmentLayer, previous_offset: u32| match layer {
+            AlignmentLayer::Matches if candidate == AlignmentLayer::Matches => {
+                previous_offset < offset && self.extends(diag, previous_offset + 1, offset)
+            }
+            AlignmentLayer::Matches => {
+                previous_offset <= offset && self.extends(diag, previous_offset, offset)
+            }
+            AlignmentLayer::Inserts => previous_offset == offset,
+            AlignmentLayer::Deletes => previous_offset + 1 == offset,
+        };
+        candidates
+            .iter()
+            .filter(|(candidate, _, _)| *candidate == stored)
+            .chain(
+                candidates
+                    .iter()
+                    .filter(|(candidate, _, _)| *candidate != stored),
+            )
+            .find_map(|&(candidate, previous_score, previous_diag)| {
+                let previous_score = previous_score?;
+                let (previous_offset, _) =
+                    self.grid.get(candidate, previous_score, previous_diag)?;
+                consistent(candidate, previous_offset)
+                    .then_some(((candidate, previous_score, previous_diag), previous_offset))
+            })
+    }
+
+    /// Returns whether the chars of diag match from the offset start to the offset end, i.e.
+    /// whether a match cell can be extended from start to end.
+    fn extends(&self, diag: i64, start: u32, end: u32) -> bool {
+        (start..end).all(|text_pos| {
+            let query_pos = (text_pos as i64 + diag) as usize;
+            match (
+                self.q_chars.get(query_pos),
+                self.t_chars.get(text_pos as usize),
+            ) {
+                (Some(q), Some(t)) => self.chars_match(*q, *t),
+                _ => false,
+            }
+        })
+    }
+
+    fn backtrace_failed(&self, score: u32, diag: i64) -> AlignmentError {
+        AlignmentError::BacktraceFailed(format!(
+            "No predecessor found for the cell of score {} on diagonal {}.",
+            score, diag
+        ))
+    }
+
+    /// Returns the ((score, diagonal), offset) of the match cell that aligns the most chars
+    /// of both strings, among the wavefronts already extended, i.e. of the scores below the
+    /// current one. Ties are broken in favor of the lowest score.
+    #[cfg(feature = "std")]
+    fn furthest_point(&self) -> ((u32, i64), u32) {
+        (0..self.current_score)
+            .flat_map(|score| {
+                self.grid
+                    .live_diagonals(score)
+                    .map(move |(diag, offset)| ((score, diag), offset))
+            })
+            .min_by_key(|&((score, diag), offset)| (Reverse(2 * offset as i64 + diag), score))
+            .unwrap_or(((0, 0), 0))
+    }
+
+    /// Same as backtrace, but from any cell instead of the end of the alignment: returns the
+    /// alignment of the prefixes of the strings up to the cell, with the score of the cell.
+    #[cfg(feature = "std")]
+    fn backtrace_from(&self, end: <Self as TracebackCells>::Cell) -> AlignResult {
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        traceback(self, end, &self.q_chars, &self.t_chars, |q, t| {
+            query_aligned.push(q.unwrap_or(GAP_CHAR));
+            text_aligned.push(t.unwrap_or(GAP_CHAR));
+        })?;
+        Ok(Alignment::new(
+            (end.0).1,
+            query_aligned.chars().rev().collect(),
+            text_aligned.chars().rev().collect(),
+        ))
+    }
+
+    /// Same as backtrace, but also returns the max distance between the diagonals of the
+    /// alignment's path and the final diagonal.
+    fn backtrace_with_band(&self) -> Result<(Alignment, usize), AlignmentError> {
+        let mut query_aligned = String::new();
+        let mut text_aligned = String::new();
+        let mut diag = self.final_diagonal;
+        let mut band = 0;
+        self.walk_back(|q, t| {
+            query_aligned.push(q.unwrap_or(GAP_CHAR));
+            text_aligned.push(t.unwrap_or(GAP_CHAR));
+            // Walking back over an insertion (deletion) leaves the previous (next) diagonal.
+            match (q, t) {
+                (Some(_), None) => diag -= 1,
+                (None, Some(_)) => diag += 1,
+                _ => (),
+            }
+            band = band.max(diag.abs_diff(self.final_diagonal) as usize);
+        })?;
+
+        let q = query_aligned.chars().rev().collect();
+        let t = text_aligned.chars().rev().collect();
+
+        Ok((Alignment::new(self.current_score, q, t), band))
+    }
+
+    /// Same as backtrace, but returns the operations of the alignment.
+    fn backtrace_ops(&self) -> Result<Vec<AlignmentOp>, AlignmentError> {
+        let mut ops = Vec::new();
+        self.walk_back(|q, t| ops.extend(AlignmentOp::from_column(q, t)))?;
+        ops.reverse();
+        Ok(ops)
+    }
+
+    /// Restricts the diagonals that will be computed to those at a distance <= band of
+    /// the diagonals between 0 and the final diagonal.
+    fn restrict_band(&mut self, band: usize) {
+        let band = i64::try_from(band).unwrap_or(i64::MAX);
+        self.lowest_diag = self
+            .lowest_diag
+            .max(self.final_diagonal.min(0).saturating_sub(band));
+        self.highest_diag = self
+            .highest_diag
+            .min(self.final_diagonal.max(0).saturating_add(band));
+    }
+
+    /// Restricts lo..=hi to the diagonals d such that current_score + heuristic(d) <= bound,
+    /// where heuristic(d) is the cost of extending a gap from d to the final diagonal.
+    /// The gap opening isn't counted, since the cells of the gap layers are already in a gap.
+    /// Returns an empty range (hi == lo - 1) if no diagonal can lead to such an alignment.
+    fn astar_prune(&self, bound: u32, lo: i64, hi: i64) -> (i64, i64) {
+        let remaining = match bound.checked_sub(self.current_score) {
+            Some(remaining) => remaining as i64,
+            None => return (lo, lo - 1),
+        };
+        let max_distance = match self.pens.extd_pen {
+            0 => i64::MAX,
+            extd_pen => remaining / extd_pen as i64,
+        };
+        let pruned_lo = lo.max(self.final_diagonal.saturating_sub(max_distance));
+        let pruned_hi = hi.min(self.final_diagonal.saturating_add(max_distance));
+        if pruned_lo > pruned_hi {
+            (lo, lo - 1)
+        } else {
+            (pruned_lo, pruned_hi)
+        }
+    }
+
+    /// Removes the diagonals at the edges of the current wavefront whose distance to the end of
+    /// the alignment is more than max_distance above the distance of the closest diagonal.
+    /// The distance of a diagonal is the max of the numbers of query and text chars left.
+    /// The empty diagonals at the edges are removed too, but aren't counted as pruned.
+    fn adaptive_reduce(&mut self, max_distance: usize) {
+        let (lo, hi) = *self.grid.get_diag_range(self.current_score).unwrap();
+        if ((hi - lo + 1) as usize) < ADAPTIVE_MIN_WAVEFRONT_LENGTH {
+            return;
+        }
+        // Some(None) for the cells past the end of a string, which have no distance, and None
+        // for the empty cells.
+        let distance = |diag: i64| {
+            let (text_pos, _) = self
+                .grid
+                .get(AlignmentLayer::Matches, self.current_score, diag)?;
+            let query_left = self.q_chars.len() as i64 - (text_pos as i64 + diag);
+            let text_left = self.t_chars.len() as i64 - text_pos as i64;
+            Some((query_left >= 0 && text_left >= 0).then(|| query_left.max(text_left) as usize))
+        };
+        let min_distance = match (lo..=hi).filter_map(distance).flatten().min() {
+            Some(min_distance) => min_distance,
+            None => return,
+        };
+        // The closest diagonal is never removed, so both loops stop before it.
+        let removable = |diag: i64| match distance(diag) {
+            None => true,
+            Some(None) => false,
+            Some(Some(d)) => d - min_distance > max_distance,
+        };
+        let mut new_lo = lo;
+        while removable(new_lo) {
+            new_lo += 1;
+        }
+        let mut new_hi = hi;
+        while removable(new_hi) {
+            new_hi -= 1;
+        }
+        if (new_lo, new_hi) != (lo, hi) {
+            let pruned = (lo..new_lo)
+                .chain(new_hi + 1..=hi)
+                .filter(|diag| distance(*diag).is_some())
+                .count();
+            self.grid.restrict_last_layer(new_lo, new_hi);
+            self.diagonals_pruned += pruned;
+            self.diagonals_removed += (new_lo - lo + hi - new_hi) as usize;
+        }
+    }
+
+    /// Minimum score of an alignment that goes outside of the computed diagonals.
+    /// Returns u32::MAX if every diagonal is computed.
+    fn out_of_band_lower_bound(&self) -> u32 {
+        let gap_pen = |len: i64| match len.unsigned_abs() {
+            0 => 0,
+            len => self.pens.open_pen.saturating_add(
+                self.pens
+                    .extd_pen
+                    .saturating_mul(u32::try_from(len).unwrap_or(u32::MAX)),
+            ),
+        };
+        let through = |diag: i64| gap_pen(diag).saturating_add(gap_pen(self.final_diagonal - diag));
+
+        let mut bound = u32::MAX;
+        if self.lowest_diag > -(self.t_chars.len() as i64) {
+            bound = bound.min(through(self.lowest_diag - 1));
+        }
+        if self.highest_diag < self.q_chars.len() as i64 {
+            bound = bound.min(through(self.highest_diag + 1));
+        }
+        bound
+    }
+
+    fn update_ins(&mut self, diag: i64) {
+        let from_open = if self.current_score >= self.pens.open_extd_pen()
         {
             self.grid.get(
                 AlignmentLayer::Matches,
-                self.current_score - (self.pens.open_pen + self.pens.extd_pen),
+                self.current_score - self.pens.open_extd_pen(),
                 diag - 1,
             )
         } else {
@@ -436,12 +1492,12 @@ impl<'a> WavefrontState<'a> {
         }
     }
 
-    fn update_del(&mut self, diag: i32) {
-        let from_open = if self.current_score >= self.pens.open_pen + self.pens.extd_pen
+    fn update_del(&mut self, diag: i64) {
+        let from_open = if self.current_score >= self.pens.open_extd_pen()
         {
             self.grid.get(
                 AlignmentLayer::Matches,
-                self.current_score - (self.pens.open_pen + self.pens.extd_pen),
+                self.current_score - self.pens.open_extd_pen(),
                 diag + 1,
             )
         } else {
@@ -495,7 +1551,7 @@ impl<'a> WavefrontState<'a> {
         }
     }
 
-    fn update_mat(&mut self, diag: i32) {
+    fn update_mat(&mut self, diag: i64) {
         let from_mismatch = if self.current_score >= self.pens.mismatch_pen {
             self.grid.get(
                 AlignmentLayer::Matches,
@@ -559,33 +1615,77 @@ impl<'a> WavefrontState<'a> {
 mod tests {
     use super::*;
 
+    /// Aligns every pair of strings over alphabet, of lengths 1 to max_len with the query no
+    /// longer than the text, and asserts that WFA and SWG find the same score.
+    fn verify_exhaustive(alphabet: &[char], max_len: usize, pens: &Penalties) {
+        let mut strings: Vec<String> = Vec::new();
+        let mut current: Vec<String> = vec![String::new()];
+        for _ in 0..max_len {
+            current = current
+                .iter()
+                .flat_map(|prefix| alphabet.iter().map(move |c| format!("{}{}", prefix, c)))
+                .collect();
+            strings.extend(current.iter().cloned());
+        }
+        for text in &strings {
+            for query in strings.iter().filter(|query| query.len() <= text.len()) {
+                let wfa = wavefront_align(query, text, pens).unwrap();
+                let swg = crate::reference::affine_gap_align(query, text, pens).unwrap();
+                assert_eq!(
+                    wfa.score, swg.score,
+                    "query: {}, text: {}, pens: {:?}",
+                    query, text, pens
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_exhaustive() {
+        for (mismatch_pen, open_pen, extd_pen) in [(1, 0, 1), (4, 6, 2), (1, 2, 2), (9, 1, 1)] {
+            verify_exhaustive(
+                &['A', 'B'],
+                5,
+                &Penalties {
+                    mismatch_pen,
+                    open_pen,
+                    extd_pen,
+                },
+            );
+        }
+        verify_exhaustive(
+            &['A', 'C', 'G'],
+            3,
+            &Penalties {
+                mismatch_pen: 4,
+                open_pen: 6,
+                extd_pen: 2,
+            },
+        );
+    }
+
     #[test]
     fn test_new_wavefront_state() {
         // Doesn't do much currently but at least if we accidently
         // change the behaviour/meaning of the wavefront state structs,
         // we'll notice.
-        let state = new_wavefront_state(
-            "GATA",
-            "TAGAC",
-            &Penalties {
-                mismatch_pen: 1,
-                open_pen: 2,
-                extd_pen: 3,
-            },
-        );
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 2,
+            extd_pen: 3,
+        };
+        let state = new_wavefront_state("GATA", "TAGAC", &pens);
 
-        let mut manual_matches = vec![vec![None; 10]; 1];
-        manual_matches[0][5] = Some((0, AlignmentLayer::Matches));
         let manual = WavefrontState {
-            query: "GATA",
-            text: "TAGAC",
-            pens: &Penalties {
-                mismatch_pen: 1,
-                open_pen: 2,
-                extd_pen: 3,
-            },
+            pens: pens.clone(),
             q_chars: "GATA".chars().collect(),
             t_chars: "TAGAC".chars().collect(),
+            packed: None,
+            char_eq: None,
+            astar_bound: None,
+            adaptive_max_distance: None,
+            diagonals_pruned: 0,
+            diagonals_removed: 0,
             current_score: 0,
             num_diags: 10,
             final_diagonal: -1,
@@ -595,20 +1695,199 @@ mod tests {
         };
 
         assert_eq!(state, manual);
+        assert_eq!(
+            wavefront_state_from_chars("GATA".chars().collect(), "TAGAC".chars().collect(), pens),
+            manual
+        );
     }
+
+    #[test]
+    fn test_wavefront_state_snapshot() {
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        let mut state =
+            wavefront_state_from_chars("CAT".chars().collect(), "CATS".chars().collect(), pens);
+        state.extend();
+        let snapshot = state.clone();
+        run_wavefront(&mut state).unwrap();
+        assert_ne!(state, snapshot);
+        assert_eq!(snapshot.current_score, 0);
+        assert_eq!(
+            format!("{:?}", snapshot),
+            "WavefrontState { query: \"CAT\", text: \"CATS\", pens: Penalties { mismatch_pen: 1, \
+             open_pen: 1, extd_pen: 1 }, current_score: 0, final_diagonal: -1, grid: 1 layers, \
+             1 cells, 1 diagonals in the widest layer, .. }"
+        );
+        assert_eq!(
+            state.backtrace(),
+            wavefront_align("CAT", "CATS", &snapshot.pens)
+        );
+    }
+
+    #[test]
+    fn test_diagonals_beyond_i32() {
+        // Sequences of 3 and 3.5 billion chars: their diagonals don't fit in an i32.
+        let q_len: usize = 3_000_000_000;
+        let t_len: usize = 3_500_000_000;
+        let (final_diagonal, lowest_diag, highest_diag) = diagonal_bounds(q_len, t_len);
+        assert_eq!(final_diagonal, -500_000_000);
+        assert_eq!(lowest_diag, -3_500_000_000);
+        assert_eq!(highest_diag, 3_000_000_000);
+        assert_eq!(highest_diag - lowest_diag + 1, 6_500_000_001);
+
+        // The grid only allocates the diagonals of its layers, which can be anywhere.
+        let mut grid = new_wavefront_grid();
+        grid.add_layer(lowest_diag, lowest_diag + 2).unwrap();
+        grid.set(
+            AlignmentLayer::Matches,
+            1,
+            lowest_diag + 1,
+            Some((7, AlignmentLayer::Inserts)),
+        );
+        grid.increment(1, lowest_diag + 1);
+        assert_eq!(
+            grid.get(AlignmentLayer::Matches, 1, lowest_diag + 1),
+            Some((8, AlignmentLayer::Inserts))
+        );
+        assert_eq!(grid.get(AlignmentLayer::Matches, 1, lowest_diag - 1), None);
+        assert_eq!(grid.get(AlignmentLayer::Matches, 1, highest_diag), None);
+    }
+
     #[test]
     fn test_wavefront_update_ins() {
-        // TODO
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        let mut state = new_wavefront_state("CAT", "CATS", &pens);
+        state.extend();
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 0, 0),
+            Some((3, AlignmentLayer::Matches))
+        );
+
+        // The score 1 layer is left empty: on diagonal 0, the query has no char left to mismatch.
+        state.current_score = 2;
+        state.grid.add_layer(0, 0).unwrap();
+        state.grid.add_layer(-1, 1).unwrap();
+
+        // Inserting the 4th char of the query from (score 0, diagonal 0).
+        // The text offset doesn't change.
+        state.update_ins(1);
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Inserts, 2, 1),
+            Some((3, AlignmentLayer::Matches))
+        );
+        // The diagonal -1 can't be reached by inserting.
+        state.update_ins(-1);
+        assert_eq!(state.grid.get(AlignmentLayer::Inserts, 2, -1), None);
+
+        // Deleting the 4th char of the text: the text offset is incremented.
+        state.update_del(-1);
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Deletes, 2, -1),
+            Some((4, AlignmentLayer::Matches))
+        );
+        state.update_del(1);
+        assert_eq!(state.grid.get(AlignmentLayer::Deletes, 2, 1), None);
     }
 
     #[test]
     fn test_wavefront_update_mat() {
-        // TODO
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 5,
+            extd_pen: 5,
+        };
+        let mut state = new_wavefront_state("XA", "YA", &pens);
+        state.extend();
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 0, 0),
+            Some((0, AlignmentLayer::Matches))
+        );
+
+        state.current_score = 1;
+        state.grid.add_layer(-1, 1).unwrap();
+        for diag in -1..=1 {
+            state.update_ins(diag);
+            state.update_del(diag);
+            state.update_mat(diag);
+        }
+        // Only the mismatch is affordable.
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 1, 0),
+            Some((1, AlignmentLayer::Matches))
+        );
+        assert_eq!(state.grid.get(AlignmentLayer::Matches, 1, -1), None);
+        assert_eq!(state.grid.get(AlignmentLayer::Matches, 1, 1), None);
+
+        // The A's are then matched.
+        state.extend();
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 1, 0),
+            Some((2, AlignmentLayer::Matches))
+        );
+        assert!(state.is_finished());
+
+        // With a gap on the same diagonal, update_mat picks the furthest reaching point.
+        state.grid.set(
+            AlignmentLayer::Deletes,
+            1,
+            -1,
+            Some((2, AlignmentLayer::Matches)),
+        );
+        state.update_mat(-1);
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 1, -1),
+            Some((2, AlignmentLayer::Deletes))
+        );
     }
 
     #[test]
     fn test_wavefront_backtrace() {
-        // TODO
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        // Same steps as test_wavefront_update_ins.
+        let mut state = new_wavefront_state("CAT", "CATS", &pens);
+        state.extend();
+        state.current_score = 2;
+        state.grid.add_layer(0, 0).unwrap();
+        state.grid.add_layer(-1, 1).unwrap();
+        for diag in -1..=1 {
+            state.update_ins(diag);
+            state.update_del(diag);
+            state.update_mat(diag);
+        }
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 2, -1),
+            Some((4, AlignmentLayer::Deletes))
+        );
+        assert!(state.is_finished());
+        assert_eq!(
+            state.backtrace(),
+            Ok(Alignment::new(2, "CAT-".to_string(), "CATS".to_string()))
+        );
+
+        // Mismatch, then match.
+        let mut state = new_wavefront_state("XA", "YA", &pens);
+        state.extend();
+        state.current_score = 1;
+        state.grid.add_layer(-1, 1).unwrap();
+        for diag in -1..=1 {
+            state.update_mat(diag);
+        }
+        state.extend();
+        assert_eq!(
+            state.backtrace(),
+            Ok(Alignment::new(1, "XA".to_string(), "YA".to_string()))
+        );
     }
 
     #[test]
@@ -623,11 +1902,11 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "AViidI-".to_string(),
-                text_aligned: "-ViidIM".to_string(),
-                score: 4,
-            })
+            Ok(Alignment::new(
+                4,
+                "AViidI-".to_string(),
+                "-ViidIM".to_string()
+            ))
         );
 
         assert_eq!(
@@ -640,11 +1919,7 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "AVD-".to_string(),
-                text_aligned: "-VDM".to_string(),
-                score: 4,
-            })
+            Ok(Alignment::new(4, "AVD-".to_string(), "-VDM".to_string()))
         );
 
         assert_eq!(
@@ -657,11 +1932,7 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "AV".to_string(),
-                text_aligned: "VM".to_string(),
-                score: 4,
-            })
+            Ok(Alignment::new(4, "AV".to_string(), "VM".to_string()))
         );
     }
 
@@ -677,11 +1948,7 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "CAT".to_string(),
-                text_aligned: "CAT".to_string(),
-                score: 0,
-            })
+            Ok(Alignment::new(0, "CAT".to_string(), "CAT".to_string()))
         );
         assert_eq!(
             wavefront_align(
@@ -693,11 +1960,7 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "CAT-".to_string(),
-                text_aligned: "CATS".to_string(),
-                score: 2,
-            })
+            Ok(Alignment::new(2, "CAT-".to_string(), "CATS".to_string()))
         );
         assert_eq!(
             wavefront_align(
@@ -709,11 +1972,7 @@ mod tests {
                     open_pen: 100,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "XX".to_string(),
-                text_aligned: "YY".to_string(),
-                score: 2,
-            })
+            Ok(Alignment::new(2, "XX".to_string(), "YY".to_string()))
         );
         assert_eq!(
             wavefront_align(
@@ -725,11 +1984,7 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "XX--".to_string(),
-                text_aligned: "--YY".to_string(),
-                score: 6,
-            })
+            Ok(Alignment::new(6, "XX--".to_string(), "--YY".to_string()))
         );
         assert_eq!(
             wavefront_align(
@@ -741,11 +1996,11 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "XX--------".to_string(),
-                text_aligned: "--YYYYYYYY".to_string(),
-                score: 12,
-            })
+            Ok(Alignment::new(
+                12,
+                "XX--------".to_string(),
+                "--YYYYYYYY".to_string()
+            ))
         );
         assert_eq!(
             wavefront_align(
@@ -757,14 +2012,369 @@ mod tests {
                     open_pen: 1,
                 }
             ),
-            Ok(Alignment {
-                query_aligned: "XX-ZZ".to_string(),
-                text_aligned: "XXYZ-".to_string(),
-                score: 4,
-            })
+            Ok(Alignment::new(4, "XX-ZZ".to_string(), "XXYZ-".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_mismatch_gap_boundary() {
+        // "XX"/"YY" is aligned either with 2 mismatches, or with an insertion and a deletion.
+        // Mixing both (a mismatch and 2 gaps of length 1) is never strictly cheaper.
+        for mismatch_pen in 1..20 {
+            for open_pen in 1..10 {
+                for extd_pen in 1..10 {
+                    let pens = Penalties {
+                        mismatch_pen,
+                        open_pen,
+                        extd_pen,
+                    };
+                    let all_mismatch = 2 * mismatch_pen;
+                    let all_gap = 2 * (open_pen + 2 * extd_pen);
+                    let alignment = wavefront_align("XX", "YY", &pens).unwrap();
+                    assert_eq!(alignment.score, all_mismatch.min(all_gap), "{:?}", pens);
+                    assert_eq!(alignment.recompute_score(&pens), alignment.score);
+                    assert_eq!(
+                        crate::reference::affine_gap_align("XX", "YY", &pens)
+                            .unwrap()
+                            .score,
+                        alignment.score
+                    );
+                    if all_mismatch < all_gap {
+                        assert_eq!(alignment.query_aligned, "XX", "{:?}", pens);
+                        assert_eq!(alignment.text_aligned, "YY", "{:?}", pens);
+                    } else if all_gap < all_mismatch {
+                        assert!(
+                            alignment
+                                .query_aligned
+                                .chars()
+                                .zip(alignment.text_aligned.chars())
+                                .all(|(q, t)| q == '-' || t == '-'),
+                            "{:?}",
+                            pens
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wavefront_align_banded() {
+        let pens = Penalties {
+            mismatch_pen: 3,
+            extd_pen: 1,
+            open_pen: 1,
+        };
+        // The optimal alignment shifts the query by 3 chars.
+        assert_eq!(wavefront_align("AAACGT", "CGTCCC", &pens).unwrap().score, 8);
+        // With a band of 0, only the diagonal 0 is available.
+        assert_eq!(
+            wavefront_align_banded("AAACGT", "CGTCCC", &pens, 0),
+            Ok(Alignment::new(
+                15,
+                "AAACGT".to_string(),
+                "CGTCCC".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_wavefront_align_doubling() {
+        let query = "TCTTTACTCGCGCGTTGGAGAAATACAATAGT";
+        let text = "TCTATACTGCGCGTTTGGAGAAATAAAATAGTGCAAT";
+        for pens in [
+            Penalties {
+                mismatch_pen: 1,
+                extd_pen: 1,
+                open_pen: 1,
+            },
+            Penalties {
+                mismatch_pen: 135,
+                extd_pen: 19,
+                open_pen: 82,
+            },
+        ] {
+            assert_eq!(
+                wavefront_align_doubling(query, text, &pens, 1, 64).unwrap().score,
+                wavefront_align(query, text, &pens).unwrap().score
+            );
+        }
+        assert!(matches!(
+            wavefront_align_doubling(
+                "AAAAAAAAAAX",
+                "XAAAAAAAAAA",
+                &Penalties {
+                    mismatch_pen: 100,
+                    extd_pen: 1,
+                    open_pen: 1,
+                },
+                0,
+                0
+            ),
+            Err(AlignmentError::BandExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_wavefront_align_ops() {
+        let pens = Penalties {
+            mismatch_pen: 2,
+            extd_pen: 1,
+            open_pen: 1,
+        };
+        assert_eq!(
+            wavefront_align_ops("AVD", "VDM", &pens),
+            Ok((
+                4,
+                vec![
+                    AlignmentOp::Insertion,
+                    AlignmentOp::Match,
+                    AlignmentOp::Match,
+                    AlignmentOp::Deletion
+                ]
+            ))
+        );
+
+        for (query, text) in [
+            ("CAT", "CATS"),
+            ("XX", "YYYYYYYY"),
+            ("XXZZ", "XXYZ"),
+            ("AViidI", "ViidIM"),
+            (
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
+            ),
+        ] {
+            let (score, ops) = wavefront_align_ops(query, text, &pens).unwrap();
+            assert_eq!(
+                Alignment::from_ops(score, &ops, query, text),
+                Some(wavefront_align(query, text, &pens).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_wavefront_align_bytes() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            wavefront_align_bytes(b"GATTACA", b"GCATGCTA", &pens),
+            wavefront_align("GATTACA", "GCATGCTA", &pens)
+        );
+        assert_eq!(
+            wavefront_align_bytes_checked(b"GATTACA", b"GCATGCTA", &pens),
+            wavefront_align("GATTACA", "GCATGCTA", &pens)
+        );
+        // The raw path aligns the bytes of "é" and "è": their first bytes match.
+        assert_eq!(
+            wavefront_align_bytes("é".as_bytes(), "è".as_bytes(), &pens),
+            Ok(Alignment::new(
+                4,
+                "\u{c3}\u{a9}".to_string(),
+                "\u{c3}\u{a8}".to_string()
+            ))
+        );
+        assert_eq!(
+            wavefront_align_bytes(&[0xff], &[0xff], &pens)
+                .unwrap()
+                .score,
+            0
+        );
+        assert!(matches!(
+            wavefront_align_bytes_checked("é".as_bytes(), "è".as_bytes(), &pens),
+            Err(AlignmentError::NonAscii(_))
+        ));
+        assert!(matches!(
+            wavefront_align_bytes_checked(b"GATTACA", &[b'G', 0xff], &pens),
+            Err(AlignmentError::NonAscii(_))
+        ));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_packed() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(600);
+        for _ in 0..50 {
+            let text_length = rng.gen_range(1..300);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let mut query: Vec<char> = text.chars().collect();
+            for _ in 0..rng.gen_range(0..10) {
+                let pos = rng.gen_range(0..query.len());
+                match rng.gen_range(0..3) {
+                    0 => query[pos] = DNA_BASES[rng.gen_range(0..4)],
+                    1 if query.len() > 1 => {
+                        query.remove(pos);
+                    }
+                    _ => query.insert(pos, DNA_BASES[rng.gen_range(0..4)]),
+                }
+            }
+            let query: String = query.into_iter().collect();
+            let (query, text) = if query.len() > text.len() {
+                (text, query)
+            } else {
+                (query, text)
+            };
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            assert_eq!(
+                wavefront_align_packed(
+                    &Packed2Bit::new(&query).unwrap(),
+                    &Packed2Bit::new(&text).unwrap(),
+                    &pens
+                ),
+                wavefront_align(&query, &text, &pens)
+            );
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_astar() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(609);
+        for _ in 0..200 {
+            let text_length = rng.gen_range(1..100);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            // Drops or substitutes about 1 char in 10.
+            let mut query = String::new();
+            for c in text.chars() {
+                match rng.gen_range(0..20) {
+                    0 => (),
+                    1 => query.push(DNA_BASES[rng.gen_range(0..4)]),
+                    _ => query.push(c),
+                }
+            }
+            if query.is_empty() {
+                query.push('A');
+            }
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            assert_eq!(
+                wavefront_align_astar(&query, &text, &pens).unwrap().score,
+                wavefront_align(&query, &text, &pens).unwrap().score
+            );
+        }
+
+        // On a high-identity pair, fewer cells are computed.
+        let text = "GATTACAGATTACAGATTACAGATTACAGATTACAGATTACAGATTACAGATTACA";
+        let query = "GATTACAGATTACAGATTCAGATTACAGATTACAGATTACAGATTAGAGATTACA";
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let mut plain = new_wavefront_state(query, text, &pens);
+        run_wavefront(&mut plain).unwrap();
+        let mut astar = new_wavefront_state(query, text, &pens);
+        astar.astar_bound = Some(plain.current_score);
+        run_wavefront(&mut astar).unwrap();
+        assert_eq!(astar.backtrace(), plain.backtrace());
+        assert!(astar.grid.num_cells() < plain.grid.num_cells());
+    }
+
+    #[test]
+    fn test_original_case_preserved() {
+        // Soft-masked (lowercase) chars are aligned and returned as they are in the input.
+        let pens = Penalties {
+            mismatch_pen: 3,
+            open_pen: 2,
+            extd_pen: 1,
+        };
+        let (query, text) = ("GATtaCA", "GACtaGCA");
+        for alignment in [
+            wavefront_align(query, text, &pens).unwrap(),
+            crate::reference::affine_gap_align(query, text, &pens).unwrap(),
+        ] {
+            assert_eq!(alignment.query_aligned.replace('-', ""), query);
+            assert_eq!(alignment.text_aligned.replace('-', ""), text);
+            assert!(alignment.to_cigar_extended().contains('X'));
+        }
+    }
+
+    #[test]
+    fn test_zero_mismatch_pen() {
+        let pens = Penalties {
+            mismatch_pen: 0,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        for aligner in [
+            wavefront_align as AlignmentFunction,
+            wavefront_align_adaptive,
+            wavefront_align_astar,
+        ] {
+            assert!(matches!(
+                aligner("GATTACA", "GACTACA", &pens),
+                Err(AlignmentError::DegeneratePenalties(_))
+            ));
+        }
+        // SWG handles them: the mismatch is free.
+        assert_eq!(
+            crate::reference::affine_gap_align("GATTACA", "GACTACA", &pens)
+                .unwrap()
+                .score,
+            0
         );
     }
 
+    #[test]
+    fn test_score_overflow() {
+        let pens = Penalties {
+            mismatch_pen: u32::MAX / 4,
+            extd_pen: u32::MAX / 4,
+            open_pen: u32::MAX / 4,
+        };
+        assert!(matches!(
+            wavefront_align(
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                "TCTATACTGCGCGTTTGGAGAAATAAAATAGT",
+                &pens
+            ),
+            Err(AlignmentError::ScoreOverflow(_))
+        ));
+        assert_eq!(
+            wavefront_align(
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                "TCTTTACTCGCGCGTTGGAGAAATACAATAGT",
+                &pens
+            )
+            .unwrap()
+            .score,
+            0
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_align_stream() {
+        let input = "CAT\nCAT\nCAT\nCATS\nXX\nYY\nGATTACA\n";
+        let pens = Penalties {
+            mismatch_pen: 1,
+            extd_pen: 1,
+            open_pen: 1,
+        };
+        let results: Vec<_> = align_stream(input.as_bytes(), &pens).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0], wavefront_align("CAT", "CAT", &pens));
+        assert_eq!(results[1], wavefront_align("CAT", "CATS", &pens));
+        assert_eq!(results[2], wavefront_align("XX", "YY", &pens));
+        assert!(matches!(results[3], Err(AlignmentError::ReadFailure(_))));
+    }
+
     #[test]
     fn assert_align_score() {
         assert_eq!(
@@ -799,4 +2409,913 @@ mod tests {
             472
         );
     }
+
+    #[test]
+    fn test_realign() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            realign("GA-TT-ACA", "G-CATTA--GA", &pens, true),
+            wavefront_align("GATTACA", "GCATTAGA", &pens)
+        );
+        assert!(matches!(
+            realign("GA-TTACA", "GCATTAGA", &pens, false),
+            Err(AlignmentError::InvalidChar(_))
+        ));
+        assert_eq!(
+            realign("GATTACA", "GCATTAGA", &pens, false),
+            wavefront_align("GATTACA", "GCATTAGA", &pens)
+        );
+        assert!(matches!(
+            realign("---", "GCATTAGA", &pens, true),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[test]
+    fn test_match_blocks() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            match_blocks("GATTACA", "GATCACA", &pens),
+            Ok(vec![
+                MatchBlock {
+                    query_start: 0,
+                    text_start: 0,
+                    length: 3,
+                },
+                MatchBlock {
+                    query_start: 4,
+                    text_start: 4,
+                    length: 3,
+                },
+            ])
+        );
+        assert_eq!(
+            match_blocks("AAAACCCC", "AAAAGGGCCCC", &pens),
+            Ok(vec![
+                MatchBlock {
+                    query_start: 0,
+                    text_start: 0,
+                    length: 4,
+                },
+                MatchBlock {
+                    query_start: 4,
+                    text_start: 7,
+                    length: 4,
+                },
+            ])
+        );
+        assert_eq!(match_blocks("CAT", "GGG", &pens), Ok(vec![]));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_adaptive() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(620);
+        for _ in 0..200 {
+            let query_length = rng.gen_range(1..100);
+            let text_length = query_length + rng.gen_range(0..20);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let optimal = wavefront_align(&query, &text, &pens).unwrap();
+
+            // With an infinite cutoff, nothing is pruned: the alignment is exact.
+            let exact = wavefront_align_adaptive_report(&query, &text, &pens, usize::MAX).unwrap();
+            assert_eq!(exact.diagonals_pruned, 0);
+            assert_eq!(exact.alignment, optimal);
+
+            let pruned = wavefront_align_adaptive_report(&query, &text, &pens, 2).unwrap();
+            assert_eq!(pruned.path_score, pruned.alignment.recompute_score(&pens));
+            assert!(pruned.path_score >= optimal.score);
+            assert!(pruned.path_score <= pruned.alignment.score);
+        }
+
+        // The wavefronts of unrelated strings are wide, with many diagonals far behind.
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let query = random_sequence(&mut rng, 300, &DNA_BASES);
+        let text = random_sequence(&mut rng, 300, &DNA_BASES);
+        let exact = wavefront_align_adaptive_report(&query, &text, &pens, usize::MAX).unwrap();
+        let pruned = wavefront_align_adaptive_report(&query, &text, &pens, 10).unwrap();
+        assert_eq!(exact.diagonals_pruned, 0);
+        assert!(pruned.diagonals_pruned > 0);
+        assert!(pruned.cells_computed < exact.cells_computed);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_score_backtrace() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(621);
+        for _ in 0..500 {
+            let query_length = rng.gen_range(1..60);
+            let text_length = query_length + rng.gen_range(0..20);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let eager = wavefront_align(&query, &text, &pens).unwrap();
+            let score = wavefront_score(&query, &text, &pens).unwrap();
+            assert_eq!(score.score(), eager.score);
+            assert_eq!(score.backtrace(), Ok(eager));
+        }
+        assert!(matches!(
+            wavefront_score(
+                "",
+                "CAT",
+                &Penalties {
+                    mismatch_pen: 1,
+                    open_pen: 1,
+                    extd_pen: 1,
+                }
+            ),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_by() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let purine = |c: char| c == 'A' || c == 'G';
+        let same_class = |q: char, t: char| purine(q) == purine(t);
+        assert_eq!(
+            wavefront_align_by("ACGT", "GTAC", &pens, same_class),
+            Ok(Alignment::new(0, "ACGT".to_string(), "GTAC".to_string()))
+        );
+        assert_eq!(
+            wavefront_align_by("ACGT", "ACGT", &pens, |q, t| q == t),
+            wavefront_align("ACGT", "ACGT", &pens)
+        );
+
+        // Aligning with the classes is the same as aligning the strings of classes.
+        let mut rng = StdRng::seed_from_u64(622);
+        let to_classes = |s: &str| -> String {
+            s.chars()
+                .map(|c| if purine(c) { 'R' } else { 'Y' })
+                .collect()
+        };
+        for _ in 0..200 {
+            let query_length = rng.gen_range(1..40);
+            let text_length = query_length + rng.gen_range(0..10);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let by_class = wavefront_align_by(&query, &text, &pens, same_class).unwrap();
+            let of_classes = wavefront_align(&to_classes(&query), &to_classes(&text), &pens);
+            assert_eq!(by_class.score, of_classes.unwrap().score);
+            assert_eq!(by_class.query_aligned.replace('-', ""), query);
+            assert_eq!(by_class.text_aligned.replace('-', ""), text);
+        }
+    }
+
+    #[test]
+    fn test_degenerate_penalties() {
+        let zero = Penalties {
+            mismatch_pen: 0,
+            open_pen: 0,
+            extd_pen: 0,
+        };
+        assert!(matches!(
+            wavefront_align("GATTACA", "GCATGCU", &zero),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        let zero_extension = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 0,
+        };
+        assert!(matches!(
+            wavefront_align("GATTACA", "GCATGCU", &zero_extension),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        assert!(matches!(
+            wavefront_align_astar("GATTACA", "GCATGCU", &zero_extension),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+    }
+
+    #[test]
+    fn test_wavefront_align_chunked() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let query = "GATTACAGATTACAGGATTACA";
+        let text = "GATCACAGATTTTACACGGATTACATA";
+        let expected = wavefront_align(query, text, &pens).unwrap();
+        for steps_per_poll in [0, 1, 3, 1000] {
+            let mut polls = 0;
+            let mut chunked = wavefront_align_chunked(query, text, &pens, steps_per_poll).unwrap();
+            let alignment = loop {
+                match chunked {
+                    ChunkedAlignment::Pending(pending) => chunked = pending.poll().unwrap(),
+                    ChunkedAlignment::Done(alignment) => break alignment,
+                }
+                polls += 1;
+            };
+            assert_eq!(alignment, expected);
+            // The score is incremented once per step, and the last step doesn't increment it.
+            let steps = expected.score as usize + 1;
+            assert_eq!(polls, steps.div_ceil(steps_per_poll.max(1)));
+        }
+        assert!(matches!(
+            wavefront_align_chunked("GATTACA", "CAT", &pens, 1),
+            Err(AlignmentError::QueryTooLong(_))
+        ));
+    }
+
+    #[test]
+    fn test_current_wavefront() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let pending = |chunked| match chunked {
+            ChunkedAlignment::Pending(pending) => pending,
+            ChunkedAlignment::Done(_) => panic!("The alignment shouldn't be done."),
+        };
+        let initial = pending(wavefront_align_chunked("GATTACA", "GACTACA", &pens, 1).unwrap());
+        // Only the diagonal 0 is reached, and it isn't extended yet.
+        assert_eq!(
+            initial.current_wavefront(0).collect::<Vec<_>>(),
+            vec![(0, 0)]
+        );
+        assert_eq!(initial.current_wavefront(1).count(), 0);
+
+        let mut polled = pending(initial.poll().unwrap());
+        assert_eq!(
+            polled.current_wavefront(0).collect::<Vec<_>>(),
+            vec![(0, 2)]
+        );
+        // No diagonal can be reached with a score lower than mismatch_pen.
+        assert_eq!(polled.current_wavefront(1).count(), 0);
+        // Each poll computes the wavefront of the next score.
+        for _ in 0..3 {
+            polled = pending(polled.poll().unwrap());
+        }
+        assert_eq!(
+            polled.current_wavefront(4).collect::<Vec<_>>(),
+            vec![(0, 3)]
+        );
+        assert_eq!(polled.current_wavefront(100).count(), 0);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_align_reversed() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // The extra A is aligned at the other end of the run of As.
+        assert_eq!(
+            wavefront_align("GAAT", "GAAAT", &pens)
+                .unwrap()
+                .normalize_gaps(),
+            Alignment::new(8, "GAA-T".to_string(), "GAAAT".to_string())
+        );
+        assert_eq!(
+            align_reversed("GAAT", "GAAAT", &pens),
+            Ok(Alignment::new(8, "G-AAT".to_string(), "GAAAT".to_string()))
+        );
+
+        let mut rng = StdRng::seed_from_u64(651);
+        for _ in 0..100 {
+            let text_length = rng.gen_range(1..100);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let query_length = rng.gen_range(1..=text.len());
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let alignment = align_reversed(&query, &text, &pens).unwrap();
+            assert_eq!(
+                alignment.score,
+                wavefront_align(&query, &text, &pens).unwrap().score
+            );
+            assert!(alignment.is_score_consistent(&pens));
+            assert_eq!(alignment.query_aligned.replace('-', ""), query);
+            assert_eq!(alignment.text_aligned.replace('-', ""), text);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_checkpoint_resume() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(650);
+        for _ in 0..50 {
+            let text_length = rng.gen_range(1..200);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            // Deletes and substitutes about 10% of the chars of text.
+            let mut query = String::new();
+            for c in text.chars() {
+                match rng.gen_range(0..20) {
+                    0 => {}
+                    1 => query.push('A'),
+                    _ => query.push(c),
+                }
+            }
+            if query.is_empty() {
+                continue;
+            }
+            let pens = Penalties {
+                mismatch_pen: rng.gen_range(1..10),
+                open_pen: rng.gen_range(0..10),
+                extd_pen: rng.gen_range(1..10),
+            };
+            let expected = wavefront_align(&query, &text, &pens).unwrap();
+            let steps = rng.gen_range(1..=expected.score as usize + 1);
+            let chunked = wavefront_align_chunked(&query, &text, &pens, steps).unwrap();
+            let checkpoint = match chunked {
+                ChunkedAlignment::Pending(pending) => match pending.poll().unwrap() {
+                    ChunkedAlignment::Pending(pending) => pending.checkpoint(),
+                    ChunkedAlignment::Done(alignment) => {
+                        assert_eq!(alignment, expected);
+                        continue;
+                    }
+                },
+                ChunkedAlignment::Done(_) => unreachable!(),
+            };
+            assert_eq!(checkpoint.score(), steps as u32);
+            // Only the layers read by the next calls to next are kept.
+            let window = pens.mismatch_pen.max(pens.open_extd_pen()) as usize;
+            assert_eq!(
+                checkpoint.grid.first_score as usize,
+                (steps + 1).saturating_sub(window)
+            );
+            let resumed = wavefront_resume(&checkpoint, &query, &text, &pens).unwrap();
+            assert_eq!(resumed.score(), expected.score);
+            assert_eq!(resumed.backtrace(), Ok(expected));
+        }
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let checkpoint = match wavefront_align_chunked("GATTACA", "GCATGCTA", &pens, 3).unwrap() {
+            ChunkedAlignment::Pending(pending) => match pending.poll().unwrap() {
+                ChunkedAlignment::Pending(pending) => pending.checkpoint(),
+                ChunkedAlignment::Done(_) => unreachable!(),
+            },
+            ChunkedAlignment::Done(_) => unreachable!(),
+        };
+        assert!(matches!(
+            wavefront_resume(&checkpoint, "GATTACA", "GCATGCT", &pens),
+            Err(AlignmentError::InvalidCheckpoint(_))
+        ));
+        let other_pens = Penalties {
+            mismatch_pen: 1,
+            ..pens.clone()
+        };
+        assert!(matches!(
+            wavefront_resume(&checkpoint, "GATTACA", "GCATGCTA", &other_pens),
+            Err(AlignmentError::InvalidCheckpoint(_))
+        ));
+        let mut corrupted = checkpoint.clone();
+        corrupted.grid.first_score += 1;
+        assert!(matches!(
+            wavefront_resume(&corrupted, "GATTACA", "GCATGCTA", &pens),
+            Err(AlignmentError::InvalidCheckpoint(_))
+        ));
+    }
+
+    #[test]
+    fn test_backtrace_missing_predecessor() {
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        };
+        let mut state = new_wavefront_state("CAT", "CATS", &pens);
+        run_wavefront(&mut state).unwrap();
+        let expected = state.backtrace();
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Deletes, 2, -1),
+            Some((4, AlignmentLayer::Matches))
+        );
+        // The deletion claims to extend a gap whose cell doesn't exist: the gap opening
+        // from the score 0 cell is found instead.
+        state.grid.set(
+            AlignmentLayer::Deletes,
+            2,
+            -1,
+            Some((4, AlignmentLayer::Deletes)),
+        );
+        assert_eq!(state.backtrace(), expected);
+        assert_eq!(
+            state.backtrace_ops(),
+            wavefront_align_ops("CAT", "CATS", &pens).map(|(_, ops)| ops)
+        );
+
+        // Without any consistent predecessor, the backtrace fails instead of panicking.
+        state.grid.set(
+            AlignmentLayer::Deletes,
+            2,
+            -1,
+            Some((3, AlignmentLayer::Deletes)),
+        );
+        assert!(matches!(
+            state.backtrace(),
+            Err(AlignmentError::BacktraceFailed(_))
+        ));
+
+        // A mismatch, then a match, with gap openings much costlier than mismatches.
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 10,
+            extd_pen: 1,
+        };
+        let mut state = new_wavefront_state("ACGT", "AGGT", &pens);
+        run_wavefront(&mut state).unwrap();
+        let expected = state.backtrace();
+        assert_eq!(expected.as_ref().unwrap().to_cigar_extended(), "1=1X2=");
+        assert_eq!(
+            state.grid.get(AlignmentLayer::Matches, 1, 0),
+            Some((4, AlignmentLayer::Matches))
+        );
+        // The match cell claims to close a gap that doesn't exist: the mismatch from the
+        // score 0 cell is found instead.
+        state.grid.set(
+            AlignmentLayer::Matches,
+            1,
+            0,
+            Some((4, AlignmentLayer::Inserts)),
+        );
+        assert_eq!(state.backtrace(), expected);
+        // With the score 0 cell not extended, its offset still precedes the cell's, but the
+        // columns in between would pair C with G after the mismatch: it isn't a predecessor.
+        let (_, tag) = state.grid.get(AlignmentLayer::Matches, 0, 0).unwrap();
+        state
+            .grid
+            .set(AlignmentLayer::Matches, 0, 0, Some((0, tag)));
+        assert!(matches!(
+            state.backtrace(),
+            Err(AlignmentError::BacktraceFailed(_))
+        ));
+
+        // Gap openings much costlier than mismatches.
+        let pens = Penalties {
+            mismatch_pen: 1,
+            open_pen: 50,
+            extd_pen: 1,
+        };
+        for (query, text) in [
+            ("GATTACA", "GCATGCUAAAA"),
+            ("AAAAAAAACT", "CTAAAAAAAAAAAAA"),
+        ] {
+            let alignment = wavefront_align(query, text, &pens).unwrap();
+            assert_eq!(alignment.recompute_score(&pens), alignment.score);
+            assert_eq!(
+                alignment.score,
+                crate::reference::affine_gap_align(query, text, &pens)
+                    .unwrap()
+                    .score
+            );
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_with_match_reward() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        // Gotoh's DP, maximizing the similarity.
+        let max_similarity = |query: &str, text: &str, reward: i64, x: i64, o: i64, e: i64| {
+            let q: Vec<char> = query.chars().collect();
+            let t: Vec<char> = text.chars().collect();
+            let none = i64::MIN / 4;
+            // [(mis)match, insertion, deletion] states.
+            let mut states = vec![vec![[none; 3]; t.len() + 1]; q.len() + 1];
+            states[0][0][0] = 0;
+            for i in 0..=q.len() {
+                for j in 0..=t.len() {
+                    if i > 0 && j > 0 {
+                        let [m, ins, del] = states[i - 1][j - 1];
+                        let sub = if q[i - 1] == t[j - 1] { reward } else { -x };
+                        states[i][j][0] = m.max(ins).max(del) + sub;
+                    }
+                    if i > 0 {
+                        let [m, ins, del] = states[i - 1][j];
+                        states[i][j][1] = (ins - e).max(m.max(del) - o - e);
+                    }
+                    if j > 0 {
+                        let [m, ins, del] = states[i][j - 1];
+                        states[i][j][2] = (del - e).max(m.max(ins) - o - e);
+                    }
+                }
+            }
+            let [m, ins, del] = states[q.len()][t.len()];
+            m.max(ins).max(del)
+        };
+
+        assert_eq!(
+            wavefront_align_with_match_reward("GATTACA", "GATTACA", 2, 4, 6, 2)
+                .unwrap()
+                .0,
+            14
+        );
+        let mut rng = StdRng::seed_from_u64(636);
+        assert!(matches!(
+            wavefront_align_with_match_reward("GATTACA", "GATTACA", 0, 0, 6, 2),
+            Err(AlignmentError::DegeneratePenalties(_))
+        ));
+        for _ in 0..1000 {
+            let query_length = rng.gen_range(1..40);
+            let text_length = query_length + rng.gen_range(0..10);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let (reward, x, o, e) = (
+                rng.gen_range(0..5),
+                rng.gen_range(1..10),
+                rng.gen_range(0..10),
+                rng.gen_range(1..5),
+            );
+            let (similarity, alignment) =
+                wavefront_align_with_match_reward(&query, &text, reward, x, o, e).unwrap();
+            assert_eq!(
+                similarity,
+                max_similarity(&query, &text, reward as i64, x as i64, o as i64, e as i64)
+            );
+            assert_eq!(alignment.query_aligned.replace('-', ""), query);
+            assert_eq!(alignment.text_aligned.replace('-', ""), text);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_wavefront_align_band_report() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        // A single deletion of 3 chars.
+        let report = wavefront_align_band_report("ACGTACGTAC", "ACGTTTTACGTAC", &pens).unwrap();
+        assert_eq!(report.min_sufficient_band, 3);
+        assert_eq!(report.alignment.query_aligned, "ACGT---ACGTAC");
+        assert_eq!(
+            report.alignment,
+            wavefront_align("ACGTACGTAC", "ACGTTTTACGTAC", &pens).unwrap()
+        );
+        assert_eq!(
+            wavefront_align_band_report("GATTACA", "GATTACA", &pens)
+                .unwrap()
+                .min_sufficient_band,
+            0
+        );
+
+        let mut rng = StdRng::seed_from_u64(637);
+        for _ in 0..200 {
+            let query_length = rng.gen_range(1..100);
+            let text_length = query_length + rng.gen_range(0..20);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let report = wavefront_align_band_report(&query, &text, &pens).unwrap();
+            let banded =
+                wavefront_align_banded(&query, &text, &pens, report.min_sufficient_band).unwrap();
+            assert_eq!(banded.score, report.alignment.score);
+        }
+    }
+
+    #[test]
+    fn test_identity_matrix() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let matrix = identity_matrix(&["GATTACA", "GATCACA", "GATTTACA", ""], &pens);
+        assert_eq!(
+            matrix,
+            vec![
+                vec![1.0, 6.0 / 7.0, 7.0 / 8.0, 0.0],
+                vec![6.0 / 7.0, 1.0, 6.0 / 8.0, 0.0],
+                vec![7.0 / 8.0, 6.0 / 8.0, 1.0, 0.0],
+                vec![0.0, 0.0, 0.0, 1.0],
+            ]
+        );
+        assert!(identity_matrix(&[], &pens).is_empty());
+    }
+
+    #[test]
+    fn test_wavefront_align_tokens() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let query = ["the", "quick", "brown", "fox", "-", "jumps"];
+        let text = ["the", "quick", "red", "fox", "-", "really", "jumps"];
+        assert_eq!(
+            wavefront_align_tokens(&query, &text, &pens).unwrap(),
+            TokenAlignment {
+                score: 12,
+                query_aligned: vec![
+                    Some("the"),
+                    Some("quick"),
+                    Some("brown"),
+                    Some("fox"),
+                    Some("-"),
+                    None,
+                    Some("jumps"),
+                ],
+                text_aligned: text.iter().map(|token| Some(*token)).collect(),
+            }
+        );
+        assert!(matches!(
+            wavefront_align_tokens(&text, &query, &pens),
+            Err(AlignmentError::QueryTooLong(_))
+        ));
+        assert!(matches!(
+            wavefront_align_tokens(&[], &query, &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_align_min_identity() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        assert_eq!(
+            align_min_identity("GATTACA", "GATCACA", &pens, 0.8).unwrap(),
+            Some(wavefront_align("GATTACA", "GATCACA", &pens).unwrap())
+        );
+        assert_eq!(
+            align_min_identity("GATTACA", "GATCACA", &pens, 0.9).unwrap(),
+            None
+        );
+        // The length difference alone rules the pair out, before any wavefront is extended.
+        assert_eq!(max_identity(4, 12, 0, &pens), 1.0 / 3.0);
+        assert_eq!(
+            align_min_identity("ACGT", "ACGTACGTACGT", &pens, 0.5).unwrap(),
+            None
+        );
+        // A column costs at most 8: a score of 60 needs 8 columns that aren't matches.
+        assert_eq!(max_identity(100, 100, 60, &pens), 100.0 / 108.0);
+        // Unrelated strings exit once the score reaches 8 * 112, long before their optimal
+        // score.
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(680);
+        let query = random_sequence(&mut rng, 1000, &DNA_BASES);
+        let text = random_sequence(&mut rng, 1000, &DNA_BASES);
+        assert!(max_identity(1000, 1000, 8 * 112, &pens) < 0.9);
+        let optimal_score = wavefront_align(&query, &text, &pens).unwrap().score;
+        assert!(optimal_score > 8 * 112);
+        // Same loop as align_min_identity: the bound crosses the threshold while the
+        // alignment is still running.
+        let mut state = new_wavefront_state(&query, &text, &pens);
+        while max_identity(1000, 1000, state.current_score, &pens) >= 0.9 {
+            assert!(!step_wavefront(&mut state).unwrap());
+        }
+        assert!(state.current_score <= 8 * 112);
+        assert!(state.current_score < optimal_score);
+        assert_eq!(align_min_identity(&query, &text, &pens, 0.9).unwrap(), None);
+        assert!(matches!(
+            align_min_identity("", "GATTACA", &pens, 0.9),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[test]
+    fn test_identical_inputs() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let seq = "GATTACAGATTACA";
+        let expected = Alignment::new(0, seq.to_string(), seq.to_string());
+        assert_eq!(wavefront_align(seq, seq, &pens), Ok(expected.clone()));
+        assert_eq!(
+            crate::reference::affine_gap_align(seq, seq, &pens),
+            Ok(expected.clone())
+        );
+
+        // The fast path doesn't compute any wavefront.
+        let report = wavefront_align_adaptive_report(seq, seq, &pens, usize::MAX).unwrap();
+        assert_eq!(report.alignment, expected);
+        assert_eq!(report.cells_computed, 0);
+        assert_eq!(report.final_band_width, 0);
+
+        // The inputs are still checked.
+        assert!(matches!(
+            wavefront_align("", "", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+        assert!(matches!(
+            crate::reference::affine_gap_align("", "", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_wavefront_align_timeout_partial() {
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let query = format!("GATTACA{}", "ACGT".repeat(50));
+        let text = format!("GATTACA{}", "TGCA".repeat(50));
+        // Only the wavefronts of score 0 are computed: the alignment stops after the common
+        // prefix.
+        let result = wavefront_align_timeout_partial(&query, &text, &pens, Duration::ZERO).unwrap();
+        assert!(result.partial);
+        assert_eq!((result.query_end, result.text_end), (7, 7));
+        assert_eq!(
+            result.alignment,
+            Alignment::new(0, "GATTACA".to_string(), "GATTACA".to_string())
+        );
+
+        // From a later score, the alignment still covers prefixes, optimally.
+        let mut state = new_wavefront_state(&query, &text, &pens);
+        for _ in 0..20 {
+            assert!(!step_wavefront(&mut state).unwrap());
+        }
+        let ((score, diag), offset) = state.furthest_point();
+        let end = ((AlignmentLayer::Matches, score, diag), offset);
+        let (_, query_end, text_end) = state.position(end);
+        let alignment = state.backtrace_from(end).unwrap();
+        assert!(query_end > 7 && text_end > 7);
+        assert_eq!(alignment.query_aligned.replace('-', ""), query[..query_end]);
+        assert_eq!(alignment.text_aligned.replace('-', ""), text[..text_end]);
+        assert_eq!(alignment.recompute_score(&pens), score);
+        assert_eq!(
+            alignment.score,
+            wavefront_align(&query[..query_end], &text[..text_end], &pens)
+                .unwrap()
+                .score
+        );
+
+        // Without reaching the deadline, this is wavefront_align.
+        let result =
+            wavefront_align_timeout_partial(&query, &text, &pens, Duration::from_secs(60)).unwrap();
+        assert!(!result.partial);
+        assert_eq!((result.query_end, result.text_end), (207, 207));
+        assert_eq!(
+            result.alignment,
+            wavefront_align(&query, &text, &pens).unwrap()
+        );
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_rank_matches() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let texts = ["GATCACA", "GAT", "GATTACA", "CCCCCCC", "GATTTACA"];
+        // The text shorter than the query is left out.
+        assert_eq!(
+            rank_matches("GATTACA", &texts, &pens, 10),
+            vec![(2, 0), (0, 4), (4, 8), (3, 24)]
+        );
+        assert_eq!(
+            rank_matches("GATTACA", &texts, &pens, 2),
+            vec![(2, 0), (0, 4)]
+        );
+        assert!(rank_matches("GATTACA", &texts, &pens, 0).is_empty());
+
+        let mut rng = StdRng::seed_from_u64(683);
+        for _ in 0..50 {
+            let query_length = rng.gen_range(1..20);
+            let query = random_sequence(&mut rng, query_length, &DNA_BASES);
+            let texts: Vec<String> = (0..20)
+                .map(|_| {
+                    let length = rng.gen_range(1..30);
+                    random_sequence(&mut rng, length, &DNA_BASES)
+                })
+                .collect();
+            let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+            let mut expected: Vec<(usize, u32)> = texts
+                .iter()
+                .enumerate()
+                .filter_map(|(index, text)| {
+                    wavefront_align(&query, text, &pens)
+                        .ok()
+                        .map(|alignment| (index, alignment.score))
+                })
+                .collect();
+            expected.sort_by_key(|&(index, score)| (score, index));
+            let top_n = rng.gen_range(1..10);
+            expected.truncate(top_n);
+            assert_eq!(rank_matches(&query, &texts, &pens, top_n), expected);
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_classify() {
+        use crate::generation::{random_sequence, DNA_BASES};
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let pens = Penalties {
+            mismatch_pen: 4,
+            open_pen: 6,
+            extd_pen: 2,
+        };
+        let barcodes = ["ACGTACGTAC", "TTGCAAGCTA", "GGCATCCATG", "CATGGTACCA"];
+        // The third barcode, with a mismatch and a deleted char.
+        let (index, alignment) = classify(&barcodes, "GGCTTCATGC", &pens).unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(
+            alignment,
+            wavefront_align(barcodes[2], "GGCTTCATGC", &pens).unwrap()
+        );
+        // Ties go to the lowest index, and the queries longer than the text are skipped.
+        assert_eq!(
+            classify(&["GATTACAGATTACA", "GATTACA", "GATTACA"], "GATTACA", &pens)
+                .unwrap()
+                .0,
+            1
+        );
+        assert!(matches!(
+            classify(&["GATTACAGATTACA"], "GATTACA", &pens),
+            Err(AlignmentError::QueryTooLong(_))
+        ));
+        assert!(matches!(
+            classify(&[], "GATTACA", &pens),
+            Err(AlignmentError::ZeroLength(_))
+        ));
+
+        let mut rng = StdRng::seed_from_u64(638);
+        for _ in 0..100 {
+            let text_length = rng.gen_range(10..40);
+            let text = random_sequence(&mut rng, text_length, &DNA_BASES);
+            let queries: Vec<String> = (0..rng.gen_range(1..10))
+                .map(|_| {
+                    let length = rng.gen_range(1..=text.len());
+                    random_sequence(&mut rng, length, &DNA_BASES)
+                })
+                .collect();
+            let queries: Vec<&str> = queries.iter().map(|query| query.as_str()).collect();
+            let scores: Vec<u32> = queries
+                .iter()
+                .map(|query| wavefront_align(query, &text, &pens).unwrap().score)
+                .collect();
+            let (index, alignment) = classify(&queries, &text, &pens).unwrap();
+            assert_eq!(alignment.score, *scores.iter().min().unwrap());
+            assert_eq!(
+                index,
+                scores.iter().position(|s| *s == alignment.score).unwrap()
+            );
+        }
+    }
 }
@@ -0,0 +1,19 @@
+//! Checks that the library builds as no_std when the std feature is disabled.
+
+use std::process::Command;
+
+#[test]
+fn build_no_std() {
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--target-dir",
+            concat!(env!("CARGO_TARGET_TMPDIR"), "/no_std"),
+        ])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success());
+}
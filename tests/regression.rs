@@ -0,0 +1,124 @@
+//! Cases that broke the aligners at some point. Each one is aligned with WFA and SWG, which
+//! must both find its optimal score. New cases are appended to CASES.
+
+use lib::alignment_lib::{AlignmentFunction, Penalties};
+use lib::reference::affine_gap_align;
+use lib::wavefront_alignment::wavefront_align;
+
+/// (query, text, penalties, optimal score).
+const CASES: &[(&str, &str, Penalties, u32)] = &[
+    // The first and last chars only match gaps: the alignment leaves the main diagonal.
+    (
+        "AViidI",
+        "ViidIM",
+        Penalties {
+            mismatch_pen: 3,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        4,
+    ),
+    (
+        "AVD",
+        "VDM",
+        Penalties {
+            mismatch_pen: 2,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        4,
+    ),
+    // Mismatches and gaps have the same cost.
+    (
+        "AV",
+        "VM",
+        Penalties {
+            mismatch_pen: 2,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        4,
+    ),
+    // Gaps are much costlier than mismatches, then the other way around.
+    (
+        "XX",
+        "YY",
+        Penalties {
+            mismatch_pen: 1,
+            open_pen: 100,
+            extd_pen: 100,
+        },
+        2,
+    ),
+    (
+        "XX",
+        "YY",
+        Penalties {
+            mismatch_pen: 100,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        6,
+    ),
+    (
+        "XX",
+        "YYYYYYYY",
+        Penalties {
+            mismatch_pen: 100,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        12,
+    ),
+    // A deletion followed by an insertion replaces a mismatch.
+    (
+        "XXZZ",
+        "XXYZ",
+        Penalties {
+            mismatch_pen: 100,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        4,
+    ),
+    // The end of the query is reached before the end of the text.
+    (
+        "CAT",
+        "CATS",
+        Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        2,
+    ),
+    // A mismatch as the first column.
+    (
+        "XA",
+        "YA",
+        Penalties {
+            mismatch_pen: 1,
+            open_pen: 1,
+            extd_pen: 1,
+        },
+        1,
+    ),
+];
+
+#[test]
+fn regression_cases() {
+    for (query, text, pens, score) in CASES {
+        for (name, aligner) in [
+            ("WFA", wavefront_align as AlignmentFunction),
+            ("SWG", affine_gap_align),
+        ] {
+            let alignment = aligner(query, text, pens).unwrap();
+            assert_eq!(
+                alignment.score, *score,
+                "{} {} {} {:?}",
+                name, query, text, pens
+            );
+            assert_eq!(alignment.recompute_score(pens), *score);
+        }
+    }
+}
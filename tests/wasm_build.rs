@@ -0,0 +1,43 @@
+//! Checks that the library builds for the browser, without the binaries and their threads.
+
+use std::path::Path;
+use std::process::Command;
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Returns whether the standard library of target is installed for the current toolchain.
+fn target_installed(target: &str) -> bool {
+    let output = Command::new("rustc")
+        .args(["--print", "target-libdir", "--target", target])
+        .output()
+        .expect("failed to run rustc");
+    let libdir = String::from_utf8_lossy(&output.stdout);
+    output.status.success() && Path::new(libdir.trim()).is_dir()
+}
+
+#[test]
+fn build_wasm32() {
+    if !target_installed(TARGET) {
+        eprintln!(
+            "Skipping the wasm build: the {} target isn't installed (rustup target add {}).",
+            TARGET, TARGET
+        );
+        return;
+    }
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--lib",
+            "--target",
+            TARGET,
+            "--no-default-features",
+            "--features",
+            "wasm",
+            "--target-dir",
+            concat!(env!("CARGO_TARGET_TMPDIR"), "/wasm"),
+        ])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success());
+}